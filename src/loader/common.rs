@@ -1,58 +1,154 @@
 use std::convert::TryInto;
-use std::io::{self, Read};
-use std::path::Path;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 use serde::de::DeserializeOwned;
 
+use super::lz4;
 use crate::types::{ChunkPos, RegionPos};
 
 #[derive(Debug)]
 pub struct AnvilRegion {
 	pos: RegionPos,
-	bytes: Vec<u8>,
+	/// Where oversized chunks are stored, in `c.X.Z.mcc` files
+	regionDir: PathBuf,
+	/// Chunks are read from the file as they're loaded, so only the header is kept in memory
+	file: Mutex<File>,
+	fileLen: usize,
 	chunkOffsets: [(usize, usize); 1024],
+	timestamps: RegionTimestamps,
 }
 
+/// Why a region or chunk couldn't be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+	/// The region file's header or a chunk's header is malformed
+	BadHeader(String),
+
+	/// A chunk's header names a compression scheme which doesn't exist
+	UnknownCompression(u8),
+
+	Io(io::Error),
+
+	/// The chunk's data couldn't be decompressed or doesn't match the expected structure
+	Nbt(nbt::Error),
+
+	/// A block refers to a palette entry which doesn't exist
+	PaletteIndex { index: u32, paletteLen: usize },
+
+	/// A section stores fewer blocks than it should
+	MissingBlocks { found: usize },
+
+	/// A section's blocks are packed with a palette too small or too large to index
+	BadPalette { len: usize },
+}
+
+impl LoadError {
+	/// Short name of the kind of error, for reports.
+	pub fn kind(&self) -> &'static str {
+		match self {
+			Self::BadHeader(_) => "bad_header",
+			Self::UnknownCompression(_) => "unknown_compression",
+			Self::Io(_) => "io",
+			Self::Nbt(_) => "nbt",
+			Self::PaletteIndex { .. } => "palette_index",
+			Self::MissingBlocks { .. } => "missing_blocks",
+			Self::BadPalette { .. } => "bad_palette",
+		}
+	}
+}
+
+impl std::fmt::Display for LoadError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::BadHeader(msg) => write!(f, "bad header: {msg}"),
+			Self::UnknownCompression(scheme) => write!(f, "unknown compression scheme {scheme}"),
+			Self::Io(err) => write!(f, "{err}"),
+			Self::Nbt(err) => write!(f, "couldn't decode NBT: {err}"),
+			Self::PaletteIndex { index, paletteLen } => {
+				write!(f, "palette index {index} out of range for palette of {paletteLen}")
+			},
+			Self::MissingBlocks { found } => write!(f, "section has only {found} of 4096 blocks"),
+			Self::BadPalette { len } => write!(f, "blocks can't be packed with a palette of {len}"),
+		}
+	}
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+	fn from(err: io::Error) -> Self {
+		Self::Io(err)
+	}
+}
+
+impl From<nbt::Error> for LoadError {
+	fn from(err: nbt::Error) -> Self {
+		Self::Nbt(err)
+	}
+}
+
+/// When each chunk of a region was last saved, from the second table of the Anvil header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionTimestamps {
+	pos: RegionPos,
+	/// Seconds since the Unix epoch, or 0 for chunks which don't exist
+	timestamps: [u32; 1024],
+}
+
+const sectorSize: usize = 4096;
+
 impl AnvilRegion {
-	pub fn new(regionDir: impl AsRef<Path>, pos: RegionPos) -> Result<Self, std::io::Error> {
+	pub fn new(regionDir: impl AsRef<Path>, pos: RegionPos) -> Result<Self, LoadError> {
 		let regionFile = regionDir
 			.as_ref()
 			.join(format!("r.{}.{}.mca", pos.x, pos.z));
 		let regionFileName = regionFile.display();
-		let mut file = std::fs::File::open(&regionFile)?;
+		let mut file = File::open(&regionFile)?;
 
 		let fileLen = file.metadata()?.len() as usize;
-		if fileLen & 0xFFF != 0 {
-			return Err(io::Error::new(
-				io::ErrorKind::Other,
-				format!("{regionFileName}: file size is not a multiple of 4KiB"),
-			));
+		if fileLen & 0xFFF != 0 || fileLen < sectorSize * 2 {
+			return Err(LoadError::BadHeader(format!(
+				"{regionFileName}: file size is not a multiple of 4KiB or lacks a header"
+			)));
 		}
 
-		let mut bytes = Vec::with_capacity(fileLen);
-		file.read_to_end(&mut bytes)?;
+		let mut header = vec![0u8; sectorSize * 2];
+		file.read_exact(&mut header)?;
 
 		let mut chunkOffsets = [(0usize, 0usize); 1024];
 		for index in 0 .. chunkOffsets.len() {
-			let packed = u32::from_be_bytes(bytes[index * 4 .. index * 4 + 4].try_into().unwrap());
+			let packed = u32::from_be_bytes(header[index * 4 .. index * 4 + 4].try_into().unwrap());
 			let offset = (packed & 0xFF_FF_FF_00) >> 8;
 			let len = packed & 0xFF;
 			chunkOffsets[index] = ((offset as usize) * 4096, (len as usize) * 4096);
 		}
 
+		let timestamps = RegionTimestamps::parse(pos, &header[sectorSize ..]);
 		Ok(Self {
 			pos,
-			bytes,
+			regionDir: regionDir.as_ref().to_owned(),
+			file: Mutex::new(file),
+			fileLen,
 			chunkOffsets,
+			timestamps,
 		})
 	}
 
+	pub fn timestamps(&self) -> &RegionTimestamps {
+		&self.timestamps
+	}
+
 	fn get_offsets(&self, pos: ChunkPos) -> (usize, usize) {
 		let pos = pos.region_relative();
 		self.chunkOffsets[(pos.z * RegionPos::diameterChunks + pos.x) as usize]
 	}
 
-	fn get_compressed_chunk(&self, pos: ChunkPos) -> &[u8] {
+	/// Reads the sectors allocated to a chunk, or returns `None` if they lie past the end of the
+	/// file.
+	fn read_compressed_chunk(&self, pos: ChunkPos) -> io::Result<Option<Vec<u8>>> {
 		let regionPos = self.pos;
 		debug_assert!(
 			!self.is_empty(pos),
@@ -66,41 +162,113 @@ impl AnvilRegion {
 		);
 
 		let (offset, len) = self.get_offsets(pos);
-		&self.bytes[offset .. offset + len]
+		if offset + len > self.fileLen {
+			return Ok(None);
+		}
+		let mut bytes = vec![0u8; len];
+		let mut file = self.file.lock().unwrap();
+		file.seek(SeekFrom::Start(offset as u64))?;
+		file.read_exact(&mut bytes)?;
+		Ok(Some(bytes))
 	}
 
 	pub fn is_empty(&self, pos: ChunkPos) -> bool {
 		self.get_offsets(pos).1 == 0
 	}
 
-	pub fn load_chunk<T: DeserializeOwned>(&self, pos: ChunkPos) -> Result<T, nbt::Error> {
+	pub fn load_chunk<T: DeserializeOwned>(&self, pos: ChunkPos) -> Result<T, LoadError> {
 		let regionPos = self.pos;
-		let raw = self.get_compressed_chunk(pos);
-		assert!(raw.len() > 5);
+		let badHeader =
+			|msg: &str| LoadError::BadHeader(format!("chunk {pos:?} (region {regionPos:?}) {msg}"));
 
-		let len = u32::from_be_bytes(raw[0 .. 4].try_into().unwrap());
-		assert!(
-			len as usize <= raw.len() - 4,
-			"Raw chunk {pos:?} (region {regionPos:?}) has bad length in header"
-		);
+		let raw = self
+			.read_compressed_chunk(pos)?
+			.ok_or_else(|| badHeader("lies past the end of the region file"))?;
+		if raw.len() < 5 {
+			return Err(badHeader("is too short for a header"));
+		}
+		let len = u32::from_be_bytes(raw[0 .. 4].try_into().unwrap()) as usize;
+		if len == 0 || len > raw.len() - 4 {
+			return Err(badHeader("has a bad length"));
+		}
 
+		// with the high bit set the chunk is too large for the region file and stored separately
 		let compression = raw[4];
-		match compression {
-			1 => nbt::from_gzip_reader(&raw[5 ..]),
-			2 => nbt::from_zlib_reader(&raw[5 ..]),
-			_ => panic!(
-				"Raw chunk {pos:?} (region {regionPos:?}) has bad compression scheme in header"
-			),
+		let external;
+		let data = if compression & 0x80 != 0 {
+			let path = self.regionDir.join(format!("c.{}.{}.mcc", pos.x, pos.z));
+			external = std::fs::read(path)?;
+			&external[..]
+		} else {
+			&raw[5 .. 4 + len]
+		};
+
+		let res = match compression & 0x7F {
+			1 => nbt::from_gzip_reader(data),
+			2 => nbt::from_zlib_reader(data),
+			3 => nbt::from_reader(data),
+			4 => nbt::from_reader(&lz4::decompress_block_stream(data)?[..]),
+			_ => return Err(LoadError::UnknownCompression(compression)),
+		};
+		Ok(res?)
+	}
+}
+
+impl RegionTimestamps {
+	/// Reads only the timestamp table of a region file, which is much cheaper than loading the
+	/// whole region.
+	pub fn read(regionDir: impl AsRef<Path>, pos: RegionPos) -> Result<Self, LoadError> {
+		let regionFile = regionDir
+			.as_ref()
+			.join(format!("r.{}.{}.mca", pos.x, pos.z));
+		let mut file = File::open(&regionFile)?;
+		let mut table = vec![0u8; sectorSize];
+		file.seek(SeekFrom::Start(sectorSize as u64))?;
+		file.read_exact(&mut table)?;
+		Ok(Self::parse(pos, &table))
+	}
+
+	pub(crate) fn parse(pos: RegionPos, table: &[u8]) -> Self {
+		let mut timestamps = [0u32; 1024];
+		for (timestamp, bytes) in timestamps.iter_mut().zip(table.chunks_exact(4)) {
+			*timestamp = u32::from_be_bytes(bytes.try_into().unwrap());
 		}
+		Self { pos, timestamps }
+	}
+
+	pub fn pos(&self) -> RegionPos {
+		self.pos
+	}
+
+	/// Seconds since the Unix epoch, or 0 if the chunk doesn't exist.
+	pub fn get(&self, pos: ChunkPos) -> u32 {
+		debug_assert_eq!(RegionPos::from(pos), self.pos);
+		let pos = pos.region_relative();
+		self.timestamps[(pos.z * RegionPos::diameterChunks + pos.x) as usize]
+	}
+
+	/// Every chunk position of the region with its timestamp.
+	pub fn iter(&self) -> impl Iterator<Item = (ChunkPos, u32)> + '_ {
+		self.pos.chunks().map(|pos| (pos, self.get(pos)))
 	}
 }
 
+/// Reinterprets an NBT byte array, whose bytes are signed, as unsigned bytes.
+pub fn unsigned_bytes(arr: &[i8]) -> Vec<u8> {
+	bytemuck::cast_slice(arr).to_vec()
+}
+
 pub fn biterator(bits: usize, mut words: &[u64]) -> impl '_ + Iterator<Item = u32> {
 	let bits = bits as u32;
 	let mask = (1 << bits) - 1;
-	let mut currentWord = words[0];
-	words = &words[1 ..];
-	let mut bitsRemaining = u64::BITS;
+	// an empty array yields nothing, rather than panicking on a corrupt chunk
+	let (mut currentWord, mut bitsRemaining) = match words.split_first() {
+		Some((&first, rest)) => {
+			words = rest;
+			(first, u64::BITS)
+		},
+		None => (0, 0),
+	};
 	std::iter::from_fn(move || {
 		if bitsRemaining == 0 && words.len() == 0 {
 			None
@@ -115,7 +283,7 @@ pub fn biterator(bits: usize, mut words: &[u64]) -> impl '_ + Iterator<Item = u3
 			currentWord >>= bits;
 			if let Some(v) = bitsRemaining.checked_sub(bits) {
 				bitsRemaining = v;
-				// TODO: <=1.15 wraps entries across words
+				// entries never span words; see `biterator_spanning` for <=1.15
 				if bitsRemaining < bits {
 					bitsRemaining = 0;
 				}
@@ -127,9 +295,135 @@ pub fn biterator(bits: usize, mut words: &[u64]) -> impl '_ + Iterator<Item = u3
 	})
 }
 
+/// Like `biterator`, but for arrays written by 1.13-1.15 where entries are packed back-to-back and
+/// so may wrap from the end of one word into the start of the next.
+pub fn biterator_spanning(bits: usize, words: &[u64]) -> impl '_ + Iterator<Item = u32> {
+	let mask = (1u64 << bits) - 1;
+	let len = words.len() * u64::BITS as usize / bits;
+	(0 .. len).map(move |index| {
+		let bitIndex = index * bits;
+		let (word, offset) = (bitIndex / 64, bitIndex % 64);
+		let mut elem = words[word] >> offset;
+		if offset + bits > 64 {
+			elem |= words[word + 1] << (64 - offset);
+		}
+		(elem & mask) as u32
+	})
+}
+
 #[test]
 fn test_biterator() {
 	let inp: Vec<u64> = (0 .. 256).collect();
 	let res: Vec<u32> = biterator(4, &inp).collect();
 	assert_eq!(res.len(), 4096);
+
+	// 5 bits per entry: 12 entries per word, with the top 4 bits left unused
+	let expected: Vec<u32> = (0 .. 4096).map(|v| v % 32).collect();
+	let mut words = vec![0u64; 4096usize.div_ceil(12)];
+	for (index, &v) in expected.iter().enumerate() {
+		words[index / 12] |= (v as u64) << (index % 12 * 5);
+	}
+	let res: Vec<u32> = biterator(5, &words).take(4096).collect();
+	assert_eq!(res, expected);
+
+	assert_eq!(biterator(4, &[]).count(), 0);
+}
+
+#[test]
+fn test_biterator_spanning() {
+	let inp: Vec<u64> = (0 .. 256).collect();
+	let res: Vec<u32> = biterator_spanning(4, &inp).collect();
+	assert_eq!(res, biterator(4, &inp).collect::<Vec<_>>());
+
+	let expected: Vec<u32> = (0 .. 4096).map(|v| v % 32).collect();
+	let mut words = vec![0u64; 4096 * 5 / 64];
+	for (index, &v) in expected.iter().enumerate() {
+		let bitIndex = index * 5;
+		let (word, offset) = (bitIndex / 64, bitIndex % 64);
+		words[word] |= (v as u64) << offset;
+		if offset + 5 > 64 {
+			words[word + 1] |= (v as u64) >> (64 - offset);
+		}
+	}
+	let res: Vec<u32> = biterator_spanning(5, &words).collect();
+	assert_eq!(res, expected);
+}
+
+#[test]
+fn test_region_timestamps() {
+	let dir = std::env::temp_dir().join(format!("cuview-anvil-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let pos = RegionPos::new(-1, 0);
+	let chunk = ChunkPos::new(-31, 2);
+	let index = (2 * RegionPos::diameterChunks + 1) as usize;
+
+	// header plus one sector of chunk data
+	let mut bytes = vec![0u8; sectorSize * 3];
+	bytes[index * 4 .. index * 4 + 4].copy_from_slice(&(2u32 << 8 | 1).to_be_bytes());
+	let timestamp = sectorSize + index * 4;
+	bytes[timestamp .. timestamp + 4].copy_from_slice(&1_700_000_000u32.to_be_bytes());
+	std::fs::write(dir.join("r.-1.0.mca"), &bytes).unwrap();
+
+	let anvil = AnvilRegion::new(&dir, pos).unwrap();
+	let timestamps = RegionTimestamps::read(&dir, pos).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+
+	assert_eq!(anvil.timestamps(), &timestamps);
+	assert!(!anvil.is_empty(chunk));
+	assert_eq!(timestamps.get(chunk), 1_700_000_000);
+	assert_eq!(timestamps.get(ChunkPos::new(-32, 0)), 0);
+	assert_eq!(timestamps.iter().filter(|&(_, t)| t != 0).count(), 1);
+}
+
+#[test]
+fn test_load_chunk_compression() {
+	use serde::{Deserialize, Serialize};
+
+	#[derive(Debug, PartialEq, Deserialize, Serialize)]
+	struct Data {
+		value: i32,
+	}
+
+	let dir = std::env::temp_dir().join(format!("cuview-compression-{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let pos = RegionPos::new(0, 0);
+
+	let mut nbtBytes = vec![];
+	nbt::to_writer(&mut nbtBytes, &Data { value: 42 }, None).unwrap();
+	// an LZ4 stream holding the NBT uncompressed, then the end marker
+	let mut lz4Bytes = vec![];
+	for data in [&nbtBytes[..], &[]] {
+		lz4Bytes.extend_from_slice(b"LZ4Block\x10");
+		lz4Bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		lz4Bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		lz4Bytes.extend_from_slice(&[0; 4]);
+		lz4Bytes.extend_from_slice(data);
+	}
+
+	// chunk x in sector 2 + x, with compression scheme `schemes[x]`
+	let schemes: [(u8, &[u8]); 4] = [(3, &nbtBytes), (4, &lz4Bytes), (0x83, &[]), (9, &[0])];
+	let mut bytes = vec![0u8; sectorSize * (2 + schemes.len())];
+	for (x, &(scheme, data)) in schemes.iter().enumerate() {
+		bytes[x * 4 .. x * 4 + 4].copy_from_slice(&((2 + x as u32) << 8 | 1).to_be_bytes());
+		let sector = &mut bytes[sectorSize * (2 + x) ..];
+		sector[0 .. 4].copy_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+		sector[4] = scheme;
+		sector[5 .. 5 + data.len()].copy_from_slice(data);
+	}
+	// chunk 4 claims sectors past the end of the file
+	bytes[16 .. 20].copy_from_slice(&(100u32 << 8 | 1).to_be_bytes());
+	std::fs::write(dir.join("r.0.0.mca"), &bytes).unwrap();
+	std::fs::write(dir.join("c.2.0.mcc"), &nbtBytes).unwrap();
+
+	let anvil = AnvilRegion::new(&dir, pos).unwrap();
+	let load = |x| anvil.load_chunk::<Data>(ChunkPos::new(x, 0));
+	let results = [load(0), load(1), load(2), load(3), load(4)];
+	std::fs::remove_dir_all(&dir).unwrap();
+
+	for result in &results[.. 3] {
+		assert_eq!(result.as_ref().unwrap(), &Data { value: 42 });
+	}
+	assert!(matches!(results[3], Err(LoadError::UnknownCompression(9))));
+	assert!(matches!(results[4], Err(LoadError::BadHeader(_))));
+	assert_eq!(results[4].as_ref().unwrap_err().kind(), "bad_header");
 }
@@ -0,0 +1,675 @@
+//! Mapping of pre-1.13 numeric block IDs and metadata onto modern (1.18) blockstates.
+
+use crate::types::blockstate::{BlockState, BlockStateBuilder};
+use crate::types::ResourceLocation;
+
+/// Names of the dye colours, in the order of their numeric ids.
+pub const colors: [&str; 16] = [
+	"white",
+	"orange",
+	"magenta",
+	"light_blue",
+	"yellow",
+	"lime",
+	"pink",
+	"gray",
+	"light_gray",
+	"cyan",
+	"purple",
+	"blue",
+	"brown",
+	"green",
+	"red",
+	"black",
+];
+
+pub const woods: [&str; 6] = ["oak", "spruce", "birch", "jungle", "acacia", "dark_oak"];
+
+/// Vanilla block IDs as of 1.12, indexed by ID.
+#[rustfmt::skip]
+const legacyIds: [&str; 256] = [
+	"air", "stone", "grass", "dirt", "cobblestone", "planks", "sapling", "bedrock",
+	"flowing_water", "water", "flowing_lava", "lava", "sand", "gravel", "gold_ore", "iron_ore",
+	"coal_ore", "log", "leaves", "sponge", "glass", "lapis_ore", "lapis_block", "dispenser",
+	"sandstone", "noteblock", "bed", "golden_rail", "detector_rail", "sticky_piston", "web",
+	"tallgrass", "deadbush", "piston", "piston_head", "wool", "piston_extension", "yellow_flower",
+	"red_flower", "brown_mushroom", "red_mushroom", "gold_block", "iron_block", "double_stone_slab",
+	"stone_slab", "brick_block", "tnt", "bookshelf", "mossy_cobblestone", "obsidian", "torch",
+	"fire", "mob_spawner", "oak_stairs", "chest", "redstone_wire", "diamond_ore", "diamond_block",
+	"crafting_table", "wheat", "farmland", "furnace", "lit_furnace", "standing_sign",
+	"wooden_door", "ladder", "rail", "stone_stairs", "wall_sign", "lever", "stone_pressure_plate",
+	"iron_door", "wooden_pressure_plate", "redstone_ore", "lit_redstone_ore",
+	"unlit_redstone_torch", "redstone_torch", "stone_button", "snow_layer", "ice", "snow",
+	"cactus", "clay", "reeds", "jukebox", "fence", "pumpkin", "netherrack", "soul_sand",
+	"glowstone", "portal", "lit_pumpkin", "cake", "unpowered_repeater", "powered_repeater",
+	"stained_glass", "trapdoor", "monster_egg", "stonebrick", "brown_mushroom_block",
+	"red_mushroom_block", "iron_bars", "glass_pane", "melon_block", "pumpkin_stem", "melon_stem",
+	"vine", "fence_gate", "brick_stairs", "stone_brick_stairs", "mycelium", "waterlily",
+	"nether_brick", "nether_brick_fence", "nether_brick_stairs", "nether_wart",
+	"enchanting_table", "brewing_stand", "cauldron", "end_portal", "end_portal_frame",
+	"end_stone", "dragon_egg", "redstone_lamp", "lit_redstone_lamp", "double_wooden_slab",
+	"wooden_slab", "cocoa", "sandstone_stairs", "emerald_ore", "ender_chest", "tripwire_hook",
+	"tripwire", "emerald_block", "spruce_stairs", "birch_stairs", "jungle_stairs",
+	"command_block", "beacon", "cobblestone_wall", "flower_pot", "carrots", "potatoes",
+	"wooden_button", "skull", "anvil", "trapped_chest", "light_weighted_pressure_plate",
+	"heavy_weighted_pressure_plate", "unpowered_comparator", "powered_comparator",
+	"daylight_detector", "redstone_block", "quartz_ore", "hopper", "quartz_block",
+	"quartz_stairs", "activator_rail", "dropper", "stained_hardened_clay", "stained_glass_pane",
+	"leaves2", "log2", "acacia_stairs", "dark_oak_stairs", "slime", "barrier", "iron_trapdoor",
+	"prismarine", "sea_lantern", "hay_block", "carpet", "hardened_clay", "coal_block",
+	"packed_ice", "double_plant", "standing_banner", "wall_banner", "daylight_detector_inverted",
+	"red_sandstone", "red_sandstone_stairs", "double_stone_slab2", "stone_slab2",
+	"spruce_fence_gate", "birch_fence_gate", "jungle_fence_gate", "dark_oak_fence_gate",
+	"acacia_fence_gate", "spruce_fence", "birch_fence", "jungle_fence", "dark_oak_fence",
+	"acacia_fence", "spruce_door", "birch_door", "jungle_door", "acacia_door", "dark_oak_door",
+	"end_rod", "chorus_plant", "chorus_flower", "purpur_block", "purpur_pillar", "purpur_stairs",
+	"purpur_double_slab", "purpur_slab", "end_bricks", "beetroots", "grass_path", "end_gateway",
+	"repeating_command_block", "chain_command_block", "frosted_ice", "magma",
+	"nether_wart_block", "red_nether_brick", "bone_block", "structure_void", "observer",
+	"white_shulker_box", "orange_shulker_box", "magenta_shulker_box", "light_blue_shulker_box",
+	"yellow_shulker_box", "lime_shulker_box", "pink_shulker_box", "gray_shulker_box",
+	"silver_shulker_box", "cyan_shulker_box", "purple_shulker_box", "blue_shulker_box",
+	"brown_shulker_box", "green_shulker_box", "red_shulker_box", "black_shulker_box",
+	"white_glazed_terracotta", "orange_glazed_terracotta", "magenta_glazed_terracotta",
+	"light_blue_glazed_terracotta", "yellow_glazed_terracotta", "lime_glazed_terracotta",
+	"pink_glazed_terracotta", "gray_glazed_terracotta", "silver_glazed_terracotta",
+	"cyan_glazed_terracotta", "purple_glazed_terracotta", "blue_glazed_terracotta",
+	"brown_glazed_terracotta", "green_glazed_terracotta", "red_glazed_terracotta",
+	"black_glazed_terracotta", "concrete", "concrete_powder", "", "", "structure_block",
+];
+
+const numbers: [&str; 16] = [
+	"0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15",
+];
+
+/// Indexed by `EnumFacing` ordinal, as used by most blocks that can face in any direction.
+const facings: [&str; 6] = ["down", "up", "north", "south", "west", "east"];
+
+/// Indexed by `EnumFacing::getHorizontalIndex`.
+const horizontals: [&str; 4] = ["south", "west", "north", "east"];
+
+pub fn legacy_block_name(id: u16) -> Option<ResourceLocation> {
+	let name = *legacyIds.get(id as usize)?;
+	(!name.is_empty()).then(|| ResourceLocation::new("minecraft", name))
+}
+
+fn state<const N: usize>(name: &str, props: [(&str, &str); N]) -> BlockState {
+	let mut builder = BlockStateBuilder::new(name.into());
+	for (k, v) in props {
+		builder.set_property(k, v);
+	}
+	builder.build()
+}
+
+fn bool(v: u8) -> &'static str {
+	if v != 0 { "true" } else { "false" }
+}
+
+fn facing(meta: u8) -> &'static str {
+	facings[(meta & 7) as usize % facings.len()]
+}
+
+fn horizontal(meta: u8) -> &'static str {
+	horizontals[(meta & 3) as usize]
+}
+
+fn axis(meta: u8) -> &'static str {
+	["y", "x", "z", "none"][((meta >> 2) & 3) as usize]
+}
+
+fn half(meta: u8) -> &'static str {
+	if meta & 8 != 0 { "top" } else { "bottom" }
+}
+
+fn stairs(name: &str, meta: u8) -> BlockState {
+	let facing = ["east", "west", "south", "north"][(meta & 3) as usize];
+	let half = if meta & 4 != 0 { "top" } else { "bottom" };
+	state(name, [
+		("facing", facing),
+		("half", half),
+		("shape", "straight"),
+	])
+}
+
+fn door(name: &str, meta: u8, lowerMeta: Option<u8>) -> BlockState {
+	// the lower half stores facing and whether the door is open, the upper half its hinge
+	let (lower, upper) = if meta & 8 != 0 {
+		(lowerMeta.unwrap_or(0), meta)
+	} else {
+		(meta, 8)
+	};
+	let facing = ["east", "south", "west", "north"][(lower & 3) as usize];
+	state(name, [
+		("facing", facing),
+		("half", if meta & 8 != 0 { "upper" } else { "lower" }),
+		("hinge", if upper & 1 != 0 { "right" } else { "left" }),
+		("open", bool(lower & 4)),
+	])
+}
+
+fn trapdoor(name: &str, meta: u8) -> BlockState {
+	let facing = ["north", "south", "west", "east"][(meta & 3) as usize];
+	state(name, [
+		("facing", facing),
+		("half", half(meta)),
+		("open", bool(meta & 4)),
+	])
+}
+
+fn torch(name: &str, wallName: &str, meta: u8, lit: Option<bool>) -> BlockState {
+	let lit = lit.map(|v| if v { "true" } else { "false" });
+	let facing = match meta {
+		1 => "east",
+		2 => "west",
+		3 => "south",
+		4 => "north",
+		_ => {
+			return match lit {
+				Some(lit) => state(name, [("lit", lit)]),
+				None => state(name, []),
+			};
+		},
+	};
+	match lit {
+		Some(lit) => state(wallName, [("facing", facing), ("lit", lit)]),
+		None => state(wallName, [("facing", facing)]),
+	}
+}
+
+fn button(name: &str, meta: u8) -> BlockState {
+	let (face, facing) = match meta & 7 {
+		0 => ("ceiling", "north"),
+		1 => ("wall", "east"),
+		2 => ("wall", "west"),
+		3 => ("wall", "south"),
+		4 => ("wall", "north"),
+		_ => ("floor", "north"),
+	};
+	state(name, [
+		("face", face),
+		("facing", facing),
+		("powered", bool(meta & 8)),
+	])
+}
+
+fn rail(name: &str, meta: u8, powerable: bool) -> BlockState {
+	const shapes: [&str; 10] = [
+		"north_south",
+		"east_west",
+		"ascending_east",
+		"ascending_west",
+		"ascending_north",
+		"ascending_south",
+		"south_east",
+		"south_west",
+		"north_west",
+		"north_east",
+	];
+	if powerable {
+		state(name, [
+			("powered", bool(meta & 8)),
+			("shape", shapes[(meta & 7) as usize % 6]),
+		])
+	} else {
+		state(name, [("shape", shapes[meta as usize % shapes.len()])])
+	}
+}
+
+fn mushroom_block(name: &str, meta: u8) -> BlockState {
+	// sides showing the cap texture, in the order down, east, north, south, up, west
+	let sides: [u8; 6] = match meta {
+		1 => [0, 0, 1, 0, 1, 1],
+		2 => [0, 0, 1, 0, 1, 0],
+		3 => [0, 1, 1, 0, 1, 0],
+		4 => [0, 0, 0, 0, 1, 1],
+		5 => [0, 0, 0, 0, 1, 0],
+		6 => [0, 1, 0, 0, 1, 0],
+		7 => [0, 0, 0, 1, 1, 1],
+		8 => [0, 0, 0, 1, 1, 0],
+		9 => [0, 1, 0, 1, 1, 0],
+		10 => return state("mushroom_stem", [
+			("down", "false"),
+			("east", "true"),
+			("north", "true"),
+			("south", "true"),
+			("up", "false"),
+			("west", "true"),
+		]),
+		14 => [1; 6],
+		15 => return state("mushroom_stem", [
+			("down", "true"),
+			("east", "true"),
+			("north", "true"),
+			("south", "true"),
+			("up", "true"),
+			("west", "true"),
+		]),
+		_ => [0; 6],
+	};
+	state(name, [
+		("down", bool(sides[0])),
+		("east", bool(sides[1])),
+		("north", bool(sides[2])),
+		("south", bool(sides[3])),
+		("up", bool(sides[4])),
+		("west", bool(sides[5])),
+	])
+}
+
+fn stone_slab(meta: u8, double: bool) -> BlockState {
+	const names: [&str; 8] = [
+		"smooth_stone",
+		"sandstone",
+		"petrified_oak",
+		"cobblestone",
+		"brick",
+		"stone_brick",
+		"nether_brick",
+		"quartz",
+	];
+	let ty = if double { "double" } else { half(meta) };
+	if double && meta & 8 != 0 {
+		// "seamless" variants
+		match meta & 7 {
+			0 => return state("smooth_stone", []),
+			1 => return state("smooth_sandstone", []),
+			7 => return state("smooth_quartz", []),
+			_ => {},
+		}
+	}
+	let name = format!("{}_slab", names[(meta & 7) as usize]);
+	state(&name, [("type", ty), ("waterlogged", "false")])
+}
+
+fn slab(name: &str, meta: u8, double: bool) -> BlockState {
+	let ty = if double { "double" } else { half(meta) };
+	state(name, [("type", ty), ("waterlogged", "false")])
+}
+
+/// Flattens the vanilla block `name` (as named in 1.12) with metadata `meta` into its modern
+/// blockstate.
+///
+/// Upper halves of doors and double plants keep part of their state in the lower half, which
+/// should be passed as `lowerMeta` where known.
+pub fn flatten(name: &str, meta: u8, lowerMeta: Option<u8>) -> BlockState {
+	let meta = meta & 15;
+	let wood = |index: u8| woods[index as usize % woods.len()];
+	let color = colors[meta as usize];
+	let n = |v: u8| numbers[v as usize & 15];
+
+	match name {
+		"stone" => {
+			const names: [&str; 7] = [
+				"stone",
+				"granite",
+				"polished_granite",
+				"diorite",
+				"polished_diorite",
+				"andesite",
+				"polished_andesite",
+			];
+			state(names[meta as usize % names.len()], [])
+		},
+		"grass" => state("grass_block", [("snowy", "false")]),
+		"dirt" => match meta {
+			1 => state("coarse_dirt", []),
+			2 => state("podzol", [("snowy", "false")]),
+			_ => state("dirt", []),
+		},
+		"planks" => state(&format!("{}_planks", wood(meta)), []),
+		"sapling" => state(&format!("{}_sapling", wood(meta & 7)), [("stage", n(meta >> 3))]),
+		"flowing_water" | "water" => state("water", [("level", n(meta))]),
+		"flowing_lava" | "lava" => state("lava", [("level", n(meta))]),
+		"sand" => state(if meta == 1 { "red_sand" } else { "sand" }, []),
+		"log" | "log2" => {
+			let wood = wood((meta & 3) + if name == "log2" { 4 } else { 0 });
+			if meta & 12 == 12 {
+				state(&format!("{wood}_wood"), [("axis", "y")])
+			} else {
+				state(&format!("{wood}_log"), [("axis", axis(meta))])
+			}
+		},
+		"leaves" | "leaves2" => {
+			let wood = wood((meta & 3) + if name == "leaves2" { 4 } else { 0 });
+			state(&format!("{wood}_leaves"), [
+				("distance", "7"),
+				("persistent", bool(meta & 4)),
+			])
+		},
+		"sponge" => state(if meta == 1 { "wet_sponge" } else { "sponge" }, []),
+		"dispenser" | "dropper" => state(name, [
+			("facing", facing(meta)),
+			("triggered", bool(meta & 8)),
+		]),
+		"sandstone" | "red_sandstone" => {
+			let prefix = ["", "chiseled_", "cut_"][meta as usize % 3];
+			state(&format!("{prefix}{name}"), [])
+		},
+		"noteblock" => state("note_block", [
+			("instrument", "harp"),
+			("note", "0"),
+			("powered", "false"),
+		]),
+		"bed" => state("red_bed", [
+			("facing", horizontal(meta)),
+			("occupied", bool(meta & 4)),
+			("part", if meta & 8 != 0 { "head" } else { "foot" }),
+		]),
+		"golden_rail" => rail("powered_rail", meta, true),
+		"detector_rail" | "activator_rail" => rail(name, meta, true),
+		"rail" => rail(name, meta, false),
+		"piston" | "sticky_piston" => state(name, [
+			("extended", bool(meta & 8)),
+			("facing", facing(meta)),
+		]),
+		"piston_head" => state(name, [
+			("facing", facing(meta)),
+			("short", "false"),
+			("type", if meta & 8 != 0 { "sticky" } else { "normal" }),
+		]),
+		"piston_extension" => state("moving_piston", [("facing", facing(meta))]),
+		"web" => state("cobweb", []),
+		"tallgrass" => state(["dead_bush", "grass", "fern"][meta as usize % 3], []),
+		"deadbush" => state("dead_bush", []),
+		"wool" => state(&format!("{color}_wool"), []),
+		"stained_glass" => state(&format!("{color}_stained_glass"), []),
+		"stained_glass_pane" => state(&format!("{color}_stained_glass_pane"), []),
+		"stained_hardened_clay" => state(&format!("{color}_terracotta"), []),
+		"carpet" => state(&format!("{color}_carpet"), []),
+		"concrete" => state(&format!("{color}_concrete"), []),
+		"concrete_powder" => state(&format!("{color}_concrete_powder"), []),
+		"hardened_clay" => state("terracotta", []),
+		"yellow_flower" => state("dandelion", []),
+		"red_flower" => {
+			const names: [&str; 9] = [
+				"poppy",
+				"blue_orchid",
+				"allium",
+				"azure_bluet",
+				"red_tulip",
+				"orange_tulip",
+				"white_tulip",
+				"pink_tulip",
+				"oxeye_daisy",
+			];
+			state(names[meta as usize % names.len()], [])
+		},
+		"double_stone_slab" => stone_slab(meta, true),
+		"stone_slab" => stone_slab(meta, false),
+		"double_wooden_slab" => slab(&format!("{}_slab", wood(meta & 7)), meta, true),
+		"wooden_slab" => slab(&format!("{}_slab", wood(meta & 7)), meta, false),
+		"double_stone_slab2" => slab("red_sandstone_slab", meta, true),
+		"stone_slab2" => slab("red_sandstone_slab", meta, false),
+		"purpur_double_slab" => slab("purpur_slab", meta, true),
+		"purpur_slab" => slab("purpur_slab", meta, false),
+		"brick_block" => state("bricks", []),
+		"torch" => torch("torch", "wall_torch", meta, None),
+		"redstone_torch" => torch("redstone_torch", "redstone_wall_torch", meta, Some(true)),
+		"unlit_redstone_torch" => {
+			torch("redstone_torch", "redstone_wall_torch", meta, Some(false))
+		},
+		"fire" => state("fire", [("age", n(meta))]),
+		"mob_spawner" => state("spawner", []),
+		"oak_stairs" | "spruce_stairs" | "birch_stairs" | "jungle_stairs" | "acacia_stairs" |
+		"dark_oak_stairs" | "brick_stairs" | "stone_brick_stairs" | "nether_brick_stairs" |
+		"sandstone_stairs" | "red_sandstone_stairs" | "quartz_stairs" | "purpur_stairs" => {
+			stairs(name, meta)
+		},
+		"stone_stairs" => stairs("cobblestone_stairs", meta),
+		"chest" | "trapped_chest" => state(name, [
+			("facing", facing(meta.max(2))),
+			("type", "single"),
+		]),
+		"ender_chest" | "ladder" => state(name, [("facing", facing(meta.max(2)))]),
+		"furnace" | "lit_furnace" => state("furnace", [
+			("facing", facing(meta.max(2))),
+			("lit", bool((name == "lit_furnace") as u8)),
+		]),
+		"redstone_wire" => state(name, [("power", n(meta))]),
+		"wheat" | "carrots" | "potatoes" | "beetroots" => state(name, [("age", n(meta))]),
+		"farmland" => state(name, [("moisture", n(meta & 7))]),
+		"standing_sign" => state("oak_sign", [("rotation", n(meta))]),
+		"wall_sign" => state("oak_wall_sign", [("facing", facing(meta.max(2)))]),
+		"standing_banner" => state("white_banner", [("rotation", n(meta))]),
+		"wall_banner" => state("white_wall_banner", [("facing", facing(meta.max(2)))]),
+		"wooden_door" => door("oak_door", meta, lowerMeta),
+		"iron_door" | "spruce_door" | "birch_door" | "jungle_door" | "acacia_door" |
+		"dark_oak_door" => door(name, meta, lowerMeta),
+		"trapdoor" => trapdoor("oak_trapdoor", meta),
+		"iron_trapdoor" => trapdoor(name, meta),
+		"lever" => {
+			let (face, facing) = match meta & 7 {
+				0 => ("ceiling", "west"),
+				1 => ("wall", "east"),
+				2 => ("wall", "west"),
+				3 => ("wall", "south"),
+				4 => ("wall", "north"),
+				5 => ("floor", "north"),
+				6 => ("floor", "west"),
+				_ => ("ceiling", "north"),
+			};
+			state(name, [
+				("face", face),
+				("facing", facing),
+				("powered", bool(meta & 8)),
+			])
+		},
+		"stone_pressure_plate" => state(name, [("powered", bool(meta & 1))]),
+		"wooden_pressure_plate" => state("oak_pressure_plate", [("powered", bool(meta & 1))]),
+		"light_weighted_pressure_plate" | "heavy_weighted_pressure_plate" => {
+			state(name, [("power", n(meta))])
+		},
+		"redstone_ore" | "lit_redstone_ore" => state("redstone_ore", [
+			("lit", bool((name == "lit_redstone_ore") as u8)),
+		]),
+		"stone_button" => button(name, meta),
+		"wooden_button" => button("oak_button", meta),
+		"snow_layer" => state("snow", [("layers", n((meta & 7) + 1))]),
+		"snow" => state("snow_block", []),
+		"cactus" => state(name, [("age", n(meta))]),
+		"reeds" => state("sugar_cane", [("age", n(meta))]),
+		"jukebox" => state(name, [("has_record", bool((meta == 1) as u8))]),
+		"fence" => state("oak_fence", []),
+		"pumpkin" => state("carved_pumpkin", [("facing", horizontal(meta))]),
+		"lit_pumpkin" => state("jack_o_lantern", [("facing", horizontal(meta))]),
+		"portal" => state("nether_portal", [
+			("axis", if meta == 2 { "z" } else { "x" }),
+		]),
+		"cake" => state(name, [("bites", n(meta.min(6)))]),
+		"unpowered_repeater" | "powered_repeater" => state("repeater", [
+			("delay", n((meta >> 2) + 1)),
+			("facing", horizontal(meta)),
+			("locked", "false"),
+			("powered", bool((name == "powered_repeater") as u8)),
+		]),
+		"unpowered_comparator" | "powered_comparator" => state("comparator", [
+			("facing", horizontal(meta)),
+			("mode", if meta & 4 != 0 { "subtract" } else { "compare" }),
+			("powered", bool(meta & 8)),
+		]),
+		"monster_egg" => {
+			const names: [&str; 6] = [
+				"infested_stone",
+				"infested_cobblestone",
+				"infested_stone_bricks",
+				"infested_mossy_stone_bricks",
+				"infested_cracked_stone_bricks",
+				"infested_chiseled_stone_bricks",
+			];
+			state(names[meta as usize % names.len()], [])
+		},
+		"stonebrick" => {
+			let prefix = ["", "mossy_", "cracked_", "chiseled_"][meta as usize % 4];
+			state(&format!("{prefix}stone_bricks"), [])
+		},
+		"brown_mushroom_block" | "red_mushroom_block" => mushroom_block(name, meta),
+		"melon_block" => state("melon", []),
+		"pumpkin_stem" | "melon_stem" => state(name, [("age", n(meta & 7))]),
+		"vine" => state(name, [
+			("east", bool(meta & 8)),
+			("north", bool(meta & 4)),
+			("south", bool(meta & 1)),
+			("up", "false"),
+			("west", bool(meta & 2)),
+		]),
+		"fence_gate" | "spruce_fence_gate" | "birch_fence_gate" | "jungle_fence_gate" |
+		"dark_oak_fence_gate" | "acacia_fence_gate" => {
+			let name = if name == "fence_gate" { "oak_fence_gate" } else { name };
+			state(name, [
+				("facing", horizontal(meta)),
+				("in_wall", "false"),
+				("open", bool(meta & 4)),
+				("powered", bool(meta & 8)),
+			])
+		},
+		"mycelium" => state(name, [("snowy", "false")]),
+		"waterlily" => state("lily_pad", []),
+		"nether_brick" => state("nether_bricks", []),
+		"nether_wart" => state(name, [("age", n(meta & 3))]),
+		"brewing_stand" => state(name, [
+			("has_bottle_0", bool(meta & 1)),
+			("has_bottle_1", bool(meta & 2)),
+			("has_bottle_2", bool(meta & 4)),
+		]),
+		"cauldron" => match meta & 3 {
+			0 => state("cauldron", []),
+			level => state("water_cauldron", [("level", n(level))]),
+		},
+		"end_portal_frame" => state(name, [
+			("eye", bool(meta & 4)),
+			("facing", horizontal(meta)),
+		]),
+		"redstone_lamp" | "lit_redstone_lamp" => state("redstone_lamp", [
+			("lit", bool((name == "lit_redstone_lamp") as u8)),
+		]),
+		"cocoa" => state(name, [
+			("age", n(meta >> 2)),
+			("facing", horizontal(meta)),
+		]),
+		"tripwire_hook" => state(name, [
+			("attached", bool(meta & 4)),
+			("facing", horizontal(meta)),
+			("powered", bool(meta & 8)),
+		]),
+		"tripwire" => state(name, [
+			("attached", bool(meta & 4)),
+			("disarmed", bool(meta & 8)),
+			("powered", bool(meta & 1)),
+		]),
+		"command_block" | "repeating_command_block" | "chain_command_block" => {
+			state(name, [
+				("conditional", bool(meta & 8)),
+				("facing", facing(meta)),
+			])
+		},
+		"cobblestone_wall" => {
+			state(if meta == 1 { "mossy_cobblestone_wall" } else { "cobblestone_wall" }, [])
+		},
+		"skull" => match meta & 7 {
+			0 | 1 => state("skeleton_skull", [("rotation", "0")]),
+			_ => state("skeleton_wall_skull", [("facing", facing(meta))]),
+		},
+		"anvil" => {
+			let name = ["anvil", "chipped_anvil", "damaged_anvil"][(meta >> 2) as usize % 3];
+			state(name, [("facing", horizontal(meta))])
+		},
+		"daylight_detector" | "daylight_detector_inverted" => state("daylight_detector", [
+			("inverted", bool((name == "daylight_detector_inverted") as u8)),
+			("power", n(meta)),
+		]),
+		"quartz_ore" => state("nether_quartz_ore", []),
+		"hopper" => state(name, [
+			("enabled", bool(!meta & 8)),
+			("facing", facing(meta)),
+		]),
+		"quartz_block" => match meta {
+			1 => state("chiseled_quartz_block", []),
+			2 => state("quartz_pillar", [("axis", "y")]),
+			3 => state("quartz_pillar", [("axis", "x")]),
+			4 => state("quartz_pillar", [("axis", "z")]),
+			_ => state("quartz_block", []),
+		},
+		"slime" => state("slime_block", []),
+		"prismarine" => {
+			state(["prismarine", "prismarine_bricks", "dark_prismarine"][meta as usize % 3], [])
+		},
+		"hay_block" | "bone_block" | "purpur_pillar" => state(name, [("axis", axis(meta))]),
+		"double_plant" => {
+			const names: [&str; 6] = [
+				"sunflower",
+				"lilac",
+				"tall_grass",
+				"large_fern",
+				"rose_bush",
+				"peony",
+			];
+			let (ty, half) = if meta & 8 != 0 {
+				(lowerMeta.unwrap_or(0), "upper")
+			} else {
+				(meta, "lower")
+			};
+			state(names[(ty & 7) as usize % names.len()], [("half", half)])
+		},
+		"end_rod" => state(name, [("facing", facing(meta))]),
+		"chorus_flower" => state(name, [("age", n(meta.min(5)))]),
+		"end_bricks" => state("end_stone_bricks", []),
+		"grass_path" => state("dirt_path", []),
+		"frosted_ice" => state(name, [("age", n(meta & 3))]),
+		"magma" => state("magma_block", []),
+		"red_nether_brick" => state("red_nether_bricks", []),
+		"observer" => state(name, [
+			("facing", facing(meta)),
+			("powered", bool(meta & 8)),
+		]),
+		"structure_block" => {
+			state(name, [("mode", ["save", "load", "corner", "data"][meta as usize % 4])])
+		},
+		_ if name.ends_with("_shulker_box") => {
+			let name = name.replace("silver_", "light_gray_");
+			state(&name, [("facing", facing(meta))])
+		},
+		_ if name.ends_with("_glazed_terracotta") => {
+			let name = name.replace("silver_", "light_gray_");
+			state(&name, [("facing", horizontal(meta))])
+		},
+		_ => state(name, []),
+	}
+}
+
+#[test]
+fn test_flatten() {
+	assert!(legacy_block_name(1) == Some("stone".into()));
+	assert!(legacy_block_name(219) == Some("white_shulker_box".into()));
+	assert!(legacy_block_name(253).is_none());
+
+	let check = |name: &str, meta: u8, expected: &str| {
+		assert_eq!(flatten(name, meta, None).to_string(), expected);
+	};
+	check("stone", 3, "minecraft:diorite[]");
+	check("wool", 14, "minecraft:red_wool[]");
+	check("log", 10, "minecraft:birch_log[axis=z]");
+	check("log2", 13, "minecraft:dark_oak_wood[axis=y]");
+	check(
+		"stone_stairs",
+		6,
+		"minecraft:cobblestone_stairs[facing=south,half=top,shape=straight]",
+	);
+	check("stone_slab", 13, "minecraft:stone_brick_slab[type=top,waterlogged=false]");
+	check("torch", 5, "minecraft:torch[]");
+	check("torch", 3, "minecraft:wall_torch[facing=south]");
+	check("silver_glazed_terracotta", 2, "minecraft:light_gray_glazed_terracotta[facing=north]");
+	// levers along the X axis on floors and ceilings face west
+	check("lever", 0, "minecraft:lever[face=ceiling,facing=west,powered=false]");
+	check("lever", 6, "minecraft:lever[face=floor,facing=west,powered=false]");
+	check("lever", 13, "minecraft:lever[face=floor,facing=north,powered=true]");
+
+	// upper halves take their type from the lower half
+	assert_eq!(
+		flatten("double_plant", 10, Some(4)).to_string(),
+		"minecraft:rose_bush[half=upper]"
+	);
+	assert_eq!(
+		flatten("wooden_door", 9, Some(6)).to_string(),
+		"minecraft:oak_door[facing=west,half=upper,hinge=right,open=true]"
+	);
+}
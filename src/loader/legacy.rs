@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use super::common::{unsigned_bytes, AnvilRegion, LoadError};
+use super::flattening::{flatten, legacy_block_name};
+use super::mc1_18::{LevelDatForgeRegistry, LevelDatForgeRegistryEntry};
+use super::WorldLoader;
+use crate::types::blockstate::BlockState;
+use crate::types::shared::Shared;
+use crate::types::{ChunkPos, ResourceLocation};
+use crate::world;
+
+/// Loader for pre-flattening (1.2-1.12) Anvil worlds, which store numeric block IDs and metadata.
+struct Loader {
+	blockIds: HashMap<u16, ResourceLocation>,
+}
+
+impl Loader {
+	fn state_for(&self, id: u16, meta: u8, lowerMeta: Option<u8>) -> BlockState {
+		let name = self
+			.blockIds
+			.get(&id)
+			.copied()
+			.or_else(|| legacy_block_name(id));
+		match name {
+			Some(name) if name.modid.as_str() == "minecraft" => {
+				flatten(&name.name, meta, lowerMeta)
+			},
+			Some(name) => BlockState::stateless(name),
+			None => BlockState::stateless(format!("cuview:unknown_{id}").as_str().into()),
+		}
+	}
+}
+
+impl WorldLoader for Loader {
+	fn load_chunk(
+		&self,
+		chunk: &Shared<world::Chunk>,
+		pos: ChunkPos,
+		anvil: Arc<AnvilRegion>,
+	) -> Result<(), LoadError> {
+		let ChunkWrapper { level: rawChunk } = anvil.load_chunk(pos)?;
+		let mut sections: Vec<_> = rawChunk.sections.iter().collect();
+		sections.sort_by_key(|s| s.y);
+
+		let mut states = HashMap::new();
+		let mut below: Option<(i8, Vec<(u16, u8)>)> = None;
+		for rawSection in sections {
+			// the arrays are indexed directly below, so truncated ones have to be caught up front
+			let addLen = rawSection.add.as_ref().map_or(2048, Vec::len);
+			let nibbles = rawSection.data.len().min(addLen);
+			let found = rawSection.blocks.len().min(nibbles * 2);
+			if found < 4096 {
+				return Err(LoadError::MissingBlocks { found });
+			}
+
+			let blocks: Vec<(u16, u8)> = (0 .. 4096)
+				.map(|index| {
+					let id = rawSection.blocks[index] as u8 as u16;
+					let add = rawSection.add.as_deref().map(|add| nibble(add, index));
+					let meta = nibble(&rawSection.data, index);
+					(id | (add.unwrap_or(0) as u16) << 8, meta)
+				})
+				.collect();
+
+			let mut palette = world::Palette::new();
+			let mut paletteIds = HashMap::new();
+			let mut paletted = Vec::with_capacity(blocks.len());
+			for (index, &(id, meta)) in blocks.iter().enumerate() {
+				// doors and double plants keep part of their state in the block below
+				let lowerMeta = if meta & 8 != 0 {
+					if index >= 256 {
+						Some(blocks[index - 256].1)
+					} else {
+						below
+							.as_ref()
+							.filter(|(y, _)| *y == rawSection.y - 1)
+							.map(|(_, blocks)| blocks[index + 4096 - 256].1)
+					}
+				} else {
+					None
+				};
+
+				let state = *states
+					.entry((id, meta, lowerMeta))
+					.or_insert_with(|| self.state_for(id, meta, lowerMeta));
+				let nextId = paletteIds.len() as u32;
+				let paletteId = *paletteIds.entry(state).or_insert_with(|| {
+					palette.define(nextId, state);
+					nextId
+				});
+				paletted.push(paletteId);
+			}
+
+			let section = chunk.borrow_mut().new_section(rawSection.y, palette);
+			section.borrow_mut().fill_from_iter(paletted.into_iter())?;
+			section.borrow_mut().set_light(
+				rawSection.blockLight.as_deref().map(unsigned_bytes),
+				rawSection.skyLight.as_deref().map(unsigned_bytes),
+			);
+			below = Some((rawSection.y, blocks));
+		}
+		Ok(())
+	}
+}
+
+/// Reads the 4-bit entry at `index` of a nibble array, low nibble first.
+fn nibble(arr: &[i8], index: usize) -> u8 {
+	let byte = arr[index / 2] as u8;
+	if index.is_multiple_of(2) { byte & 0xF } else { byte >> 4 }
+}
+
+pub fn make_loader(root: &Path) -> Box<dyn WorldLoader> {
+	let levelDat: Option<LevelDat> = File::open(root.join("level.dat"))
+		.ok()
+		.and_then(|mut file| nbt::from_gzip_reader(&mut file).ok());
+	let blockIds = levelDat
+		.and_then(|dat| dat.forgeData)
+		.map(|data| data.block_ids())
+		.unwrap_or_default();
+	Box::new(Loader { blockIds })
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LevelDat {
+	#[serde(rename = "FML")]
+	pub forgeData: Option<LevelDatForgeData>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LevelDatForgeData {
+	/// 1.8 onwards
+	pub registries: Option<nbt::Map<String, LevelDatForgeRegistry>>,
+
+	/// 1.7, where block names are prefixed with `\u{1}` and item names with `\u{2}`
+	pub itemData: Option<Vec<LevelDatForgeRegistryEntry>>,
+}
+
+impl LevelDatForgeData {
+	pub fn block_ids(&self) -> HashMap<u16, ResourceLocation> {
+		let entries: Vec<(&str, i32)> = if let Some(registries) = &self.registries {
+			registries
+				.get("minecraft:blocks")
+				.map(|registry| {
+					registry
+						.ids
+						.iter()
+						.map(|entry| (entry.name.as_str(), entry.id))
+						.collect()
+				})
+				.unwrap_or_default()
+		} else if let Some(itemData) = &self.itemData {
+			itemData
+				.iter()
+				.filter_map(|entry| Some((entry.name.strip_prefix('\u{1}')?, entry.id)))
+				.collect()
+		} else {
+			vec![]
+		};
+
+		entries
+			.into_iter()
+			.filter_map(|(name, id)| Some((u16::try_from(id).ok()?, name.into())))
+			.collect()
+	}
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChunkWrapper {
+	#[serde(rename = "Level")]
+	pub level: Chunk,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Chunk {
+	#[serde(rename = "Sections", default)]
+	pub sections: Vec<ChunkSection>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChunkSection {
+	#[serde(rename = "Y")]
+	pub y: i8,
+
+	/// Low 8 bits of each block ID
+	#[serde(rename = "Blocks")]
+	pub blocks: Vec<i8>,
+
+	/// High 4 bits of each block ID, if any are used
+	#[serde(rename = "Add")]
+	pub add: Option<Vec<i8>>,
+
+	#[serde(rename = "Data")]
+	pub data: Vec<i8>,
+
+	#[serde(rename = "BlockLight")]
+	pub blockLight: Option<Vec<i8>>,
+
+	#[serde(rename = "SkyLight")]
+	pub skyLight: Option<Vec<i8>>,
+}
+
+#[test]
+fn test_nibble() {
+	let arr = [0x21u8 as i8, 0xF3u8 as i8];
+	assert_eq!(nibble(&arr, 0), 1);
+	assert_eq!(nibble(&arr, 1), 2);
+	assert_eq!(nibble(&arr, 2), 3);
+	assert_eq!(nibble(&arr, 3), 0xF);
+}
@@ -0,0 +1,137 @@
+use std::convert::TryInto;
+use std::io;
+
+/// Magic at the start of each block written by lz4-java's `LZ4BlockOutputStream`, which Minecraft
+/// uses for compression type 4.
+const blockMagic: &[u8] = b"LZ4Block";
+
+const methodRaw: u8 = 0x10;
+const methodLz4: u8 = 0x20;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Decompresses a stream of lz4-java blocks. Block checksums are not verified.
+pub fn decompress_block_stream(mut src: &[u8]) -> io::Result<Vec<u8>> {
+	const headerLen: usize = 8 + 1 + 4 * 3;
+
+	let mut res = vec![];
+	while !src.is_empty() {
+		if src.len() < headerLen || !src.starts_with(blockMagic) {
+			return Err(invalid("bad LZ4 block header"));
+		}
+		let method = src[8] & 0xF0;
+		let int = |at: usize| u32::from_le_bytes(src[at .. at + 4].try_into().unwrap()) as usize;
+		let (compressedLen, decompressedLen) = (int(9), int(13));
+		let data = src
+			.get(headerLen .. headerLen + compressedLen)
+			.ok_or_else(|| invalid("LZ4 block is truncated"))?;
+		src = &src[headerLen + compressedLen ..];
+
+		match method {
+			// an empty raw block marks the end of the stream
+			methodRaw if decompressedLen == 0 => break,
+			methodRaw if compressedLen == decompressedLen => res.extend_from_slice(data),
+			methodLz4 => {
+				let start = res.len();
+				decompress_block(data, &mut res)?;
+				if res.len() - start != decompressedLen {
+					return Err(invalid("LZ4 block decompressed to the wrong length"));
+				}
+			},
+			_ => return Err(invalid(format!("bad LZ4 block method {method:#x}"))),
+		}
+	}
+	Ok(res)
+}
+
+/// Decompresses a single raw LZ4 block, appending to `dest`.
+pub fn decompress_block(mut src: &[u8], dest: &mut Vec<u8>) -> io::Result<()> {
+	let start = dest.len();
+
+	fn read_len(src: &mut &[u8], mut len: usize) -> io::Result<usize> {
+		if len == 15 {
+			loop {
+				let (&byte, rest) =
+					src.split_first().ok_or_else(|| invalid("LZ4 length is truncated"))?;
+				*src = rest;
+				len += byte as usize;
+				if byte != 255 {
+					break;
+				}
+			}
+		}
+		Ok(len)
+	}
+
+	loop {
+		let (&token, rest) = src.split_first().ok_or_else(|| invalid("LZ4 block is truncated"))?;
+		src = rest;
+
+		let literals = read_len(&mut src, (token >> 4) as usize)?;
+		if src.len() < literals {
+			return Err(invalid("LZ4 literals are truncated"));
+		}
+		dest.extend_from_slice(&src[.. literals]);
+		src = &src[literals ..];
+
+		// the last sequence has no match
+		if src.is_empty() {
+			return Ok(());
+		}
+		if src.len() < 2 {
+			return Err(invalid("LZ4 match offset is truncated"));
+		}
+		let offset = u16::from_le_bytes([src[0], src[1]]) as usize;
+		src = &src[2 ..];
+		let matchLen = read_len(&mut src, (token & 0xF) as usize)? + 4;
+		if offset == 0 || offset > dest.len() - start {
+			return Err(invalid("LZ4 match offset is out of bounds"));
+		}
+
+		// matches may overlap the bytes they produce, so copy byte by byte
+		let from = dest.len() - offset;
+		for i in 0 .. matchLen {
+			dest.push(dest[from + i]);
+		}
+	}
+}
+
+#[test]
+fn test_lz4() {
+	// "abcabcabcabc!", as literals "abc" then a 9 byte match 3 bytes back, then literal "!"
+	let block = [0x35, b'a', b'b', b'c', 3, 0, 0x10, b'!'];
+	let mut dest = vec![];
+	decompress_block(&block, &mut dest).unwrap();
+	assert_eq!(dest, b"abcabcabcabc!");
+
+	// extended literal length: 15 + 5
+	let mut block = vec![0xF0, 5];
+	block.extend_from_slice(&[b'x'; 20]);
+	let mut dest = vec![];
+	decompress_block(&block, &mut dest).unwrap();
+	assert_eq!(dest, [b'x'; 20]);
+
+	let block_header = |method: u8, data: &[u8], decompressedLen: u32| {
+		let mut res = blockMagic.to_vec();
+		res.push(method);
+		res.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		res.extend_from_slice(&decompressedLen.to_le_bytes());
+		res.extend_from_slice(&[0; 4]);
+		res.extend_from_slice(data);
+		res
+	};
+	let compressed = [0x35, b'a', b'b', b'c', 3, 0, 0x10, b'!'];
+	let stream = [
+		block_header(methodLz4, &compressed, 13),
+		block_header(methodRaw, b"raw", 3),
+		block_header(methodRaw, &[], 0),
+	]
+	.concat();
+	assert_eq!(decompress_block_stream(&stream).unwrap(), b"abcabcabcabc!raw");
+
+	assert!(decompress_block_stream(b"LZ4Bloc").is_err());
+	let mut dest = vec![];
+	assert!(decompress_block(&[0x04, 5, 0], &mut dest).is_err());
+}
@@ -0,0 +1,175 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::common::{biterator, biterator_spanning, unsigned_bytes, AnvilRegion, LoadError};
+use super::mc1_18::{BlockEntity, BlockState};
+use super::WorldLoader;
+use crate::types::shared::Shared;
+use crate::types::ChunkPos;
+use crate::world;
+
+/// Data version of 20w17a, the first snapshot to stop packing entries across words.
+const firstNonSpanningVersion: i32 = 2529;
+
+struct Loader;
+
+impl WorldLoader for Loader {
+	fn load_chunk(
+		&self,
+		chunk: &Shared<world::Chunk>,
+		pos: ChunkPos,
+		anvil: std::sync::Arc<AnvilRegion>,
+	) -> Result<(), LoadError> {
+		// chunks that haven't been loaded since an upgrade keep their old format, so this is
+		// decided per-chunk rather than per-world
+		let ChunkWrapper {
+			dataVersion,
+			level: rawChunk,
+		} = anvil.load_chunk(pos)?;
+		for rawSection in &rawChunk.sections {
+			let rawPalette = match &rawSection.palette {
+				Some(v) => v,
+				None => continue,
+			};
+
+			let palette: world::Palette = rawPalette.iter().map(BlockState::build).collect();
+			let paletteBits = palette.bits();
+			// both biterators divide or shift by the entry size
+			if rawSection.blockArray.is_some() && !(1 ..= 32).contains(&paletteBits) {
+				return Err(LoadError::BadPalette {
+					len: rawPalette.len(),
+				});
+			}
+
+			let section = chunk.borrow_mut().new_section(rawSection.y, palette);
+			if let Some(blocks) = &rawSection.blockArray {
+				let words = bytemuck::cast_slice(blocks);
+				if dataVersion < firstNonSpanningVersion {
+					section
+						.borrow_mut()
+						.fill_from_iter(biterator_spanning(paletteBits, words))?;
+				} else {
+					section
+						.borrow_mut()
+						.fill_from_iter(biterator(paletteBits, words))?;
+				}
+			} else {
+				let it = std::iter::once(0).cycle().take(4096);
+				section.borrow_mut().fill_from_iter(it)?;
+			}
+			section.borrow_mut().set_light(
+				rawSection.blockLight.as_deref().map(unsigned_bytes),
+				rawSection.skyLight.as_deref().map(unsigned_bytes),
+			);
+		}
+		for rawEntity in &rawChunk.blockEntities {
+			let (entityPos, entity) = rawEntity.build();
+			// corrupt or relocated chunks can hold entities from elsewhere, which aren't worth
+			// losing the rest of the chunk over
+			if ChunkPos::from(entityPos) != pos {
+				continue;
+			}
+			chunk.borrow_mut().set_block_entity(entityPos, entity);
+		}
+		Ok(())
+	}
+}
+
+pub fn make_loader(root: &Path) -> Box<dyn WorldLoader> {
+	Box::new(Loader)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChunkWrapper {
+	#[serde(rename = "DataVersion")]
+	pub dataVersion: i32,
+
+	#[serde(rename = "Level")]
+	pub level: Chunk,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Chunk {
+	#[serde(rename = "Sections", default)]
+	pub sections: Vec<ChunkSection>,
+
+	#[serde(rename = "TileEntities", default)]
+	pub blockEntities: Vec<BlockEntity>,
+
+	#[serde(rename = "LastUpdate")]
+	pub lastUpdate: i64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChunkSection {
+	#[serde(rename = "Y")]
+	pub y: i8,
+
+	/// Absent in sections which only hold lighting data
+	#[serde(rename = "Palette")]
+	pub palette: Option<Vec<BlockState>>,
+
+	#[serde(rename = "BlockStates")]
+	pub blockArray: Option<Vec<i64>>,
+
+	#[serde(rename = "BlockLight")]
+	pub blockLight: Option<Vec<i8>>,
+
+	#[serde(rename = "SkyLight")]
+	pub skyLight: Option<Vec<i8>>,
+}
+
+#[test]
+fn test_bad_palette() {
+	use nbt::Value;
+
+	use crate::types::RegionPos;
+
+	let dir = std::env::temp_dir().join(format!("cuview-mc1_13-{}", std::process::id()));
+	std::fs::create_dir_all(dir.join("region")).unwrap();
+
+	// a section with blocks but an empty palette, in chunk 0 of the region in sector 2, stored
+	// before and after entries stopped spanning words
+	let mut results = vec![];
+	for dataVersion in [1631, firstNonSpanningVersion] {
+		let compound = |entries: Vec<(&str, Value)>| {
+			Value::Compound(entries.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+		};
+		let section = compound(vec![
+			("Y", Value::Byte(0)),
+			("Palette", Value::List(vec![])),
+			("BlockStates", Value::LongArray(vec![0; 256])),
+		]);
+		let mut blob = nbt::Blob::new();
+		blob.insert("DataVersion", Value::Int(dataVersion)).unwrap();
+		let level = compound(vec![
+			("Sections", Value::List(vec![section])),
+			("LastUpdate", Value::Long(0)),
+		]);
+		blob.insert("Level", level).unwrap();
+		let mut data = vec![];
+		blob.to_writer(&mut data).unwrap();
+
+		let mut bytes = vec![0u8; 4096 * 4];
+		bytes[0 .. 4].copy_from_slice(&(2u32 << 8 | 2).to_be_bytes());
+		bytes[8192 .. 8196].copy_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+		bytes[8196] = 3;
+		bytes[8197 .. 8197 + data.len()].copy_from_slice(&data);
+		std::fs::write(dir.join("region/r.0.0.mca"), &bytes).unwrap();
+
+		let world = world::World::new(&dir);
+		let dimension = world.borrow_mut().new_dimension("overworld".into(), &dir);
+		let region = dimension.borrow_mut().new_region(RegionPos::new(0, 0)).unwrap();
+		let pos = ChunkPos::new(0, 0);
+		let chunk = region.borrow_mut().new_chunk(pos);
+		let anvil = region.borrow().anvil();
+		results.push(Loader.load_chunk(&chunk, pos, anvil));
+		dimension.borrow_mut().unload_region(RegionPos::new(0, 0));
+	}
+	std::fs::remove_dir_all(&dir).unwrap();
+
+	for result in results {
+		assert!(matches!(result, Err(LoadError::BadPalette { len: 0 })), "{result:?}");
+	}
+}
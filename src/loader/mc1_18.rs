@@ -2,11 +2,12 @@ use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Deserializer};
 
-use super::common::{biterator, AnvilRegion};
+use super::common::{biterator, unsigned_bytes, AnvilRegion, LoadError};
+use super::flattening;
 use super::WorldLoader;
-use crate::types::blockstate::BlockStateBuilder;
+use crate::types::blockstate::{self, BlockStateBuilder};
 use crate::types::shared::Shared;
-use crate::types::{ChunkPos, RegionPos, ResourceLocation};
+use crate::types::{BlockPos, ChunkPos, RegionPos, ResourceLocation};
 use crate::world;
 
 struct Loader;
@@ -17,39 +18,48 @@ impl WorldLoader for Loader {
 		chunk: &Shared<world::Chunk>,
 		pos: ChunkPos,
 		anvil: std::sync::Arc<AnvilRegion>,
-	) {
-		let rawChunk: Chunk = anvil.load_chunk(pos).unwrap();
+	) -> Result<(), LoadError> {
+		let rawChunk: Chunk = anvil.load_chunk(pos)?;
 		for rawSection in &rawChunk.sections {
-			if rawSection.blocks.is_none() {
-				continue;
-			}
-
-			let blockInfo = rawSection.blocks.as_ref().unwrap();
-			let palette: world::Palette = blockInfo
-				.palette
-				.iter()
-				.map(|rawBS| {
-					let mut state = BlockStateBuilder::new(rawBS.name.as_str().into());
-					if let Some(props) = rawBS.properties.as_ref() {
-						for (k, v) in props {
-							state.set_property(k.as_str().into(), v.as_str().into());
-						}
-					}
-					state.build()
-				})
-				.collect();
+			let blockInfo = match &rawSection.blocks {
+				Some(v) => v,
+				None => continue,
+			};
+			let palette: world::Palette = blockInfo.palette.iter().map(BlockState::build).collect();
 			let paletteBits = palette.bits();
 
 			let section = chunk.borrow_mut().new_section(rawSection.y, palette);
 			if let Some(blocks) = &blockInfo.blockArray {
 				section
 					.borrow_mut()
-					.fill_from_iter(biterator(paletteBits, bytemuck::cast_slice(blocks)));
+					.fill_from_iter(biterator(paletteBits, bytemuck::cast_slice(blocks)))?;
 			} else {
 				let it = std::iter::once(0).cycle().take(4096);
-				section.borrow_mut().fill_from_iter(it);
+				section.borrow_mut().fill_from_iter(it)?;
+			}
+			section.borrow_mut().set_light(
+				rawSection.blockLight.as_deref().map(unsigned_bytes),
+				rawSection.skyLight.as_deref().map(unsigned_bytes),
+			);
+
+			if let Some(biomes) = &rawSection.biomes {
+				// biomes only tint blocks, so a section with broken biomes is still worth drawing
+				let biomes = biomes.unpack();
+				if biomes.len() == world::ChunkSection::biomeCellsPerSection {
+					section.borrow_mut().set_biomes(biomes);
+				}
+			}
+		}
+		for rawEntity in &rawChunk.blockEntities {
+			let (entityPos, entity) = rawEntity.build();
+			// corrupt or relocated chunks can hold entities from elsewhere, which aren't worth
+			// losing the rest of the chunk over
+			if ChunkPos::from(entityPos) != pos {
+				continue;
 			}
+			chunk.borrow_mut().set_block_entity(entityPos, entity);
 		}
+		Ok(())
 	}
 }
 
@@ -110,17 +120,13 @@ pub struct LevelDatForgeMod {
 	pub modVersion: String,
 }
 
-// #[derive(Clone, Debug, Deserialize)]
-// pub struct ChunkWrapper {
-// 	#[serde(rename = "Level")]
-// 	pub level: Chunk,
-// }
-
 #[derive(Clone, Debug, Deserialize)]
 pub struct Chunk {
-	// #[serde(rename = "Sections")]
 	pub sections: Vec<ChunkSection>,
 
+	#[serde(rename = "block_entities", default)]
+	pub blockEntities: Vec<BlockEntity>,
+
 	#[serde(rename = "LastUpdate")]
 	pub lastUpdate: i64,
 }
@@ -132,6 +138,14 @@ pub struct ChunkSection {
 
 	#[serde(rename = "block_states")]
 	pub blocks: Option<ChunkBlocks>,
+
+	pub biomes: Option<ChunkBiomes>,
+
+	#[serde(rename = "BlockLight")]
+	pub blockLight: Option<Vec<i8>>,
+
+	#[serde(rename = "SkyLight")]
+	pub skyLight: Option<Vec<i8>>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -141,9 +155,132 @@ pub struct ChunkBlocks {
 	pub palette: Vec<BlockState>,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChunkBiomes {
+	#[serde(rename = "data")]
+	pub biomeArray: Option<Vec<i64>>,
+	pub palette: Vec<String>,
+}
+
+impl ChunkBiomes {
+	pub fn unpack(&self) -> Vec<ResourceLocation> {
+		let palette: Vec<ResourceLocation> =
+			self.palette.iter().map(|name| name.as_str().into()).collect();
+		let cells = world::ChunkSection::biomeCellsPerSection;
+		match &self.biomeArray {
+			// unlike blocks, biome palettes may use fewer than 4 bits per entry
+			Some(data) if palette.len() > 1 => {
+				let bits = (palette.len() - 1).ilog2() as usize + 1;
+				biterator(bits, bytemuck::cast_slice(data))
+					.take(cells)
+					.map(|id| palette.get(id as usize).copied().unwrap_or(palette[0]))
+					.collect()
+			},
+			_ => palette.first().map(|&biome| vec![biome; cells]).unwrap_or_default(),
+		}
+	}
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct BlockState {
 	pub name: String,
 	pub properties: Option<nbt::Map<String, String>>,
 }
+
+impl BlockState {
+	pub fn build(&self) -> blockstate::BlockState {
+		let mut state = BlockStateBuilder::new(self.name.as_str().into());
+		if let Some(props) = self.properties.as_ref() {
+			for (k, v) in props {
+				state.set_property(k, v);
+			}
+		}
+		state.build()
+	}
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockEntity {
+	pub id: String,
+
+	pub x: i32,
+	pub y: i32,
+	pub z: i32,
+
+	/// Banner patterns up to 1.20.4
+	#[serde(rename = "Patterns", default)]
+	pub legacyPatterns: Vec<LegacyBannerPattern>,
+
+	/// Banner patterns since 1.20.5
+	#[serde(default)]
+	pub patterns: Vec<BannerPattern>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LegacyBannerPattern {
+	#[serde(rename = "Pattern")]
+	pub pattern: String,
+
+	#[serde(rename = "Color")]
+	pub color: i32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BannerPattern {
+	/// Usually the pattern's name, but may also define a pattern inline
+	pub pattern: nbt::Value,
+
+	pub color: String,
+}
+
+impl BlockEntity {
+	pub fn build(&self) -> (BlockPos, world::BlockEntity) {
+		let legacy = self.legacyPatterns.iter().map(|raw| {
+			let color = raw.color.clamp(0, flattening::colors.len() as i32 - 1) as u8;
+			(raw.pattern.clone(), color)
+		});
+		// patterns defined inline aren't in the jars, so can't be drawn anyway
+		let named = self.patterns.iter().filter_map(|raw| {
+			let pattern = match &raw.pattern {
+				nbt::Value::String(v) => v.clone(),
+				_ => return None,
+			};
+			let color = flattening::colors.iter().position(|&v| v == raw.color)?;
+			Some((pattern, color as u8))
+		});
+		let bannerPatterns = legacy
+			.chain(named)
+			.map(|(pattern, color)| world::BannerPattern { pattern, color })
+			.collect();
+		let entity = world::BlockEntity {
+			id: self.id.as_str().into(),
+			bannerPatterns,
+		};
+		(BlockPos::new(self.x, self.y, self.z), entity)
+	}
+}
+
+#[test]
+fn test_biomes() {
+	let biomes = ChunkBiomes {
+		biomeArray: None,
+		palette: vec!["plains".into()],
+	};
+	assert!(biomes.unpack() == vec![ResourceLocation::from("plains"); 64]);
+
+	// 3 entries needs 2 bits
+	let mut word = 0u64;
+	for i in 0 .. 32 {
+		word |= (i % 3) << (i * 2);
+	}
+	let biomes = ChunkBiomes {
+		biomeArray: Some(vec![word as i64, word as i64]),
+		palette: vec!["plains".into(), "desert".into(), "forest".into()],
+	};
+	let unpacked = biomes.unpack();
+	assert!(unpacked.len() == 64);
+	assert!(unpacked[0] == "plains".into());
+	assert!(unpacked[1] == "desert".into());
+	assert!(unpacked[5] == "forest".into());
+}
@@ -1,23 +1,52 @@
 use std::fs::{read_dir, File, FileType};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use anyhow::anyhow;
+use serde::Serialize;
 
-use self::common::AnvilRegion;
+use self::common::{AnvilRegion, LoadError};
+use crate::types::parallel::{available_threads, par_map_on};
 use crate::types::shared::Shared;
 use crate::types::{ChunkPos, RegionPos, ResourceLocation};
 use crate::world::{Chunk, Dimension, Region, World};
 
 pub mod blockstate;
 pub mod common;
+pub mod flattening;
+pub mod legacy;
+pub mod lz4;
+pub mod mc1_13;
 pub mod mc1_18;
 pub mod model;
+pub mod texture_meta;
 
 pub struct WorldWrangler {
 	rootDir: PathBuf,
 	loader: Box<dyn WorldLoader>,
 	world: Shared<World>,
+	problems: Mutex<Vec<Problem>>,
+	/// How many chunks [`Self::load_chunks`] decodes at once
+	threads: usize,
+}
+
+/// A region or chunk which couldn't be loaded and was skipped.
+#[derive(Debug)]
+pub struct Problem {
+	pub region: RegionPos,
+
+	/// Absent if the whole region couldn't be loaded
+	pub chunk: Option<ChunkPos>,
+
+	pub error: LoadError,
+}
+
+#[derive(Serialize)]
+struct ProblemJson {
+	region: (i32, i32),
+	chunk: Option<(i32, i32)>,
+	kind: &'static str,
+	message: String,
 }
 
 impl WorldWrangler {
@@ -30,9 +59,16 @@ impl WorldWrangler {
 			rootDir: worldRootDir.into(),
 			loader,
 			world,
+			problems: Mutex::new(vec![]),
+			threads: available_threads(),
 		})
 	}
 
+	/// Sets how many threads chunks are decoded on, every core by default.
+	pub fn set_threads(&mut self, threads: usize) {
+		self.threads = threads.max(1);
+	}
+
 	pub fn probe_dimensions(&self) -> Vec<(ResourceLocation, PathBuf)> {
 		let mut dimensions = vec![
 			("overworld".into(), self.rootDir.join(".")),
@@ -87,22 +123,41 @@ impl WorldWrangler {
 			}
 			let name = name.unwrap();
 
-			let mut coords = name
-				.splitn(4, ".")
-				.skip(1)
-				.take(2)
-				.map(|str| str.parse::<i32>().unwrap());
-			let x = coords.next().unwrap();
-			let y = coords.next().unwrap();
-			res.push(RegionPos::new(x, y));
+			// the directory also holds oversized chunks, in `c.X.Z.mcc` files
+			let coords = name
+				.strip_prefix("r.")
+				.and_then(|name| name.strip_suffix(".mca"))
+				.and_then(|coords| coords.split_once("."));
+			let (x, z) = match coords {
+				Some((x, z)) => (x.parse::<i32>(), z.parse::<i32>()),
+				None => continue,
+			};
+			if let (Ok(x), Ok(z)) = (x, z) {
+				res.push(RegionPos::new(x, z));
+			}
 		}
 		res
 	}
 
-	pub fn load_region(&self, dimension: &Shared<Dimension>, pos: RegionPos) -> Shared<Region> {
-		let region = dimension.borrow_mut().new_region(pos);
+	/// Opens a region, or records a [`Problem`] and returns `None` if its file is unreadable.
+	pub fn load_region(
+		&self,
+		dimension: &Shared<Dimension>,
+		pos: RegionPos,
+	) -> Option<Shared<Region>> {
+		let region = match dimension.borrow_mut().new_region(pos) {
+			Ok(v) => v,
+			Err(error) => {
+				self.record_problem(Problem {
+					region: pos,
+					chunk: None,
+					error,
+				});
+				return None;
+			},
+		};
 		self.loader.load_region(&region, pos);
-		region
+		Some(region)
 	}
 
 	pub fn probe_chunks(&self, region: &Shared<Region>) -> Vec<ChunkPos> {
@@ -113,17 +168,82 @@ impl WorldWrangler {
 		pos.chunks().filter(|pos| !anvil.is_empty(*pos)).collect()
 	}
 
-	pub fn load_chunk(&self, region: &Shared<Region>, pos: ChunkPos) -> Shared<Chunk> {
-		let (anvil, chunk) = {
+	/// Loads a single chunk, or records a [`Problem`] and returns `None` if it is corrupt.
+	pub fn load_chunk(&self, region: &Shared<Region>, pos: ChunkPos) -> Option<Shared<Chunk>> {
+		self.load_chunks(region, &[pos]).pop()
+	}
+
+	/// Loads a batch of chunks of one region, decoding them on several threads. The chunks are
+	/// returned in the order of `positions`; corrupt ones are left out as [`Problem`]s.
+	pub fn load_chunks(
+		&self,
+		region: &Shared<Region>,
+		positions: &[ChunkPos],
+	) -> Vec<Shared<Chunk>> {
+		// chunks are registered with the region up front, so that decoding them only needs a lock
+		// on each chunk itself
+		let (anvil, chunks) = {
 			let mut region = region.borrow_mut();
-			(region.anvil(), region.new_chunk(pos))
+			let chunks: Vec<_> = positions.iter().map(|&pos| region.new_chunk(pos)).collect();
+			(region.anvil(), chunks)
 		};
-		self.loader.load_chunk(&chunk, pos, anvil);
-		chunk
+		let results = par_map_on(self.threads, &chunks, |chunk| {
+			let pos = chunk.borrow().pos();
+			self.loader.load_chunk(chunk, pos, anvil.clone())
+		});
+
+		let mut loaded = Vec::with_capacity(chunks.len());
+		for ((chunk, &pos), result) in chunks.into_iter().zip(positions).zip(results) {
+			match result {
+				Ok(()) => loaded.push(chunk),
+				Err(error) => {
+					region.borrow_mut().unload_chunk(pos);
+					self.record_problem(Problem {
+						region: pos.into(),
+						chunk: Some(pos),
+						error,
+					});
+				},
+			}
+		}
+		loaded
+	}
+
+	/// Loads every chunk stored in a region, see [`Self::load_chunks`].
+	pub fn load_all_chunks(&self, region: &Shared<Region>) -> Vec<Shared<Chunk>> {
+		let positions = self.probe_chunks(region);
+		self.load_chunks(region, &positions)
+	}
+
+	pub fn record_problem(&self, problem: Problem) {
+		self.problems.lock().unwrap().push(problem);
+	}
+
+	/// Every problem recorded since the last call.
+	pub fn take_problems(&self) -> Vec<Problem> {
+		std::mem::take(&mut *self.problems.lock().unwrap())
 	}
 }
 
-pub trait WorldLoader {
+/// Writes problems as a JSON array of `{region, chunk, kind, message}` objects, with positions as
+/// `[x, z]` and `chunk` null for whole regions.
+pub fn write_problem_report(path: &Path, problems: &[Problem]) -> anyhow::Result<()> {
+	let json: Vec<_> = problems
+		.iter()
+		.map(|problem| ProblemJson {
+			region: (problem.region.x, problem.region.z),
+			chunk: problem.chunk.map(|pos| (pos.x, pos.z)),
+			kind: problem.error.kind(),
+			message: problem.error.to_string(),
+		})
+		.collect();
+	std::fs::write(path, serde_json::to_vec_pretty(&json)?)?;
+	Ok(())
+}
+
+/// Decodes chunks of one save format. Loaders are shared between threads when loading batches of
+/// chunks.
+pub trait WorldLoader: Send + Sync {
 	fn load_world(&self, world: &Shared<World>) {}
 
 	fn probe_mod_dimensions(&self, world: &Shared<World>) -> Vec<(ResourceLocation, PathBuf)> {
@@ -134,18 +254,38 @@ pub trait WorldLoader {
 
 	fn load_region(&self, region: &Shared<Region>, pos: RegionPos) {}
 
-	fn load_chunk(&self, chunk: &Shared<Chunk>, pos: ChunkPos, anvil: Arc<AnvilRegion>);
+	fn load_chunk(
+		&self,
+		chunk: &Shared<Chunk>,
+		pos: ChunkPos,
+		anvil: Arc<AnvilRegion>,
+	) -> Result<(), LoadError>;
 }
 
+/// Anvil format version stored in `level.dat` by every version since 1.2.
+const anvilVersion: i32 = 19133;
+
+/// Determines the Minecraft version a world was last saved with.
+///
+/// Versions before 1.9 don't record this, so Anvil worlds without a version name are reported as
+/// 1.8.0.
 pub fn identify_version(worldRoot: impl AsRef<Path>) -> Option<(u8, u8, u8)> {
 	let mut levelDat = File::open(worldRoot.as_ref().join("level.dat")).ok()?;
 	let nbt: nbt::Blob = nbt::from_gzip_reader(&mut levelDat).ok()?;
-	let nbt = nbt.get("Data")?;
+	let nbt = match nbt.get("Data")? {
+		nbt::Value::Compound(map) => map,
+		_ => return None,
+	};
 
-	let ver = match nbt {
-		nbt::Value::Compound(map) => map.get("Version"),
-		_ => None,
-	}?;
+	let ver = match nbt.get("Version") {
+		Some(v) => v,
+		None => {
+			return match nbt.get("version") {
+				Some(&nbt::Value::Int(v)) if v == anvilVersion => Some((1, 8, 0)),
+				_ => None,
+			};
+		},
+	};
 	let ver = match ver {
 		nbt::Value::Compound(map) => map.get("Name"),
 		_ => None,
@@ -165,8 +305,8 @@ pub fn get_loader(worldRootDir: impl AsRef<Path>) -> anyhow::Result<Box<dyn Worl
 	if let Some(ver) = identify_version(worldRoot) {
 		return match ver {
 			(1, 18, _) => Ok(mc1_18::make_loader(worldRoot)),
-			(1, 17, _) => Ok(mc1_18::make_loader(worldRoot)), // FIXME
-			(1, 16, _) => Ok(mc1_18::make_loader(worldRoot)),
+			(1, 13 ..= 17, _) => Ok(mc1_13::make_loader(worldRoot)),
+			(1, 2 ..= 12, _) => Ok(legacy::make_loader(worldRoot)),
 			_ => Err(anyhow!(
 				"Couldn't find any loader for `{worldRoot:?}` (version {ver:?})",
 			)),
@@ -176,3 +316,119 @@ pub fn get_loader(worldRootDir: impl AsRef<Path>) -> anyhow::Result<Box<dyn Worl
 		"Couldn't identify Minecraft version of `{worldRoot:?}`",
 	))
 }
+
+#[test]
+fn test_load_chunks_threads() {
+	use nbt::Value;
+
+	use crate::types::blockstate::BlockState;
+	use crate::types::BlockPos;
+
+	let dir = std::env::temp_dir().join(format!("cuview-wrangler-{}", std::process::id()));
+	std::fs::create_dir_all(dir.join("region")).unwrap();
+	let compound = |entries: Vec<(&str, Value)>| {
+		Value::Compound(entries.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+	};
+	let mut levelDat = nbt::Blob::new();
+	let version = compound(vec![("Name", Value::String("1.18.2".into()))]);
+	levelDat.insert("Data", compound(vec![("Version", version)])).unwrap();
+	levelDat.to_gzip_writer(&mut File::create(dir.join("level.dat")).unwrap()).unwrap();
+
+	// chunk x along the top row in sectors 2 + 2x, with stripes of stone and its own light; odd
+	// chunks lack sky light. Chunk 0 of the next row has an unknown compression scheme and chunk 1
+	// isn't NBT
+	let sectorSize = 4096;
+	let mut bytes = vec![0u8; sectorSize * (2 + 2 * 16)];
+	let mut write_chunk = |index: usize, sector: usize, scheme: u8, data: &[u8]| {
+		let header = (sector as u32) << 8 | 2;
+		bytes[index * 4 .. index * 4 + 4].copy_from_slice(&header.to_be_bytes());
+		let raw = &mut bytes[sectorSize * sector ..];
+		raw[0 .. 4].copy_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+		raw[4] = scheme;
+		raw[5 .. 5 + data.len()].copy_from_slice(data);
+	};
+	for x in 0 .. 14 {
+		let name = |name: &str| compound(vec![("Name", Value::String(name.into()))]);
+		let blocks = (0 .. 256).map(|i| if (i + x) % 3 == 0 { 0x1111_1111_1111_1111 } else { 0 });
+		let light = |shift: i64| (0 .. 2048).map(|i| ((i * x + shift) % 256) as i8).collect();
+		let mut section = vec![
+			("Y", Value::Byte(0)),
+			("block_states", compound(vec![
+				("palette", Value::List(vec![name("air"), name("stone")])),
+				("data", Value::LongArray(blocks.collect())),
+			])),
+			("BlockLight", Value::ByteArray(light(0))),
+		];
+		if x % 2 == 0 {
+			section.push(("SkyLight", Value::ByteArray(light(7))));
+		}
+		let mut chunk = nbt::Blob::new();
+		chunk.insert("sections", Value::List(vec![compound(section)])).unwrap();
+		chunk.insert("LastUpdate", Value::Long(0)).unwrap();
+		let mut data = vec![];
+		chunk.to_writer(&mut data).unwrap();
+		write_chunk(x as usize, 2 + 2 * x as usize, 3, &data);
+	}
+	write_chunk(32, 30, 9, &[0]);
+	write_chunk(33, 32, 3, &[0xFF; 16]);
+	std::fs::write(dir.join("region/r.0.0.mca"), &bytes).unwrap();
+
+	let load = |threads| {
+		let mut wrangler = WorldWrangler::new(&dir).unwrap();
+		wrangler.set_threads(threads);
+		let overworld = wrangler.probe_dimension("overworld".into()).unwrap();
+		let dimension = wrangler.load_dimension(overworld);
+		let region = wrangler.load_region(&dimension, RegionPos::new(0, 0)).unwrap();
+		let mut blocks = vec![];
+		for chunk in wrangler.load_all_chunks(&region) {
+			let chunk = chunk.borrow();
+			for pos in chunk.pos().blocks().filter(|pos| (0 .. 16).contains(&pos.y)) {
+				blocks.push((pos, chunk.get_block(pos), chunk.get_light(pos)));
+			}
+		}
+		let problems: Vec<_> = wrangler
+			.take_problems()
+			.into_iter()
+			.map(|problem| (problem.region, problem.chunk, problem.error.kind()))
+			.collect();
+		(blocks, problems)
+	};
+	let serial = load(1);
+	let parallel = load(4);
+	std::fs::remove_dir_all(&dir).unwrap();
+
+	assert_eq!(serial.0.len(), 14 * 4096);
+	let stone = BlockState::stateless("stone".into());
+	assert_eq!(serial.0[0], (BlockPos::new(0, 0, 0), Some(stone), Some(7)));
+	assert_eq!(serial.1, [
+		(RegionPos::new(0, 0), Some(ChunkPos::new(0, 1)), "unknown_compression"),
+		(RegionPos::new(0, 0), Some(ChunkPos::new(1, 1)), "nbt"),
+	]);
+	assert_eq!(serial, parallel);
+}
+
+#[test]
+fn test_probe_regions() {
+	use nbt::Value;
+
+	let dir = std::env::temp_dir().join(format!("cuview-probe-{}", std::process::id()));
+	std::fs::create_dir_all(dir.join("region")).unwrap();
+	let version = [("Name".to_owned(), Value::String("1.18.2".into()))];
+	let data = [("Version".to_owned(), Value::Compound(version.into_iter().collect()))];
+	let mut levelDat = nbt::Blob::new();
+	levelDat.insert("Data", Value::Compound(data.into_iter().collect())).unwrap();
+	levelDat.to_gzip_writer(&mut File::create(dir.join("level.dat")).unwrap()).unwrap();
+
+	// an oversized chunk sits next to the regions, along with anything else left there
+	for name in ["r.0.-1.mca", "c.40.-3.mcc", "r.1.x.mca", "r.2.2.mca.bak", "r.3.3.mca"] {
+		std::fs::write(dir.join("region").join(name), [0]).unwrap();
+	}
+	std::fs::write(dir.join("region/r.4.4.mca"), []).unwrap();
+
+	let wrangler = WorldWrangler::new(&dir).unwrap();
+	let overworld = wrangler.probe_dimension("overworld".into()).unwrap();
+	let mut regions = wrangler.probe_regions(&wrangler.load_dimension(overworld));
+	std::fs::remove_dir_all(&dir).unwrap();
+	regions.sort_by_key(|pos| (pos.x, pos.z));
+	assert_eq!(regions, [RegionPos::new(0, -1), RegionPos::new(3, 3)]);
+}
@@ -105,7 +105,11 @@ pub struct MultipartProp(pub Vec<String>);
 
 impl From<JsonValue> for MultipartProp {
 	fn from(v: JsonValue) -> Self {
-		let str = v.as_str().unwrap();
+		// some mods write booleans and numbers unquoted
+		let str = match v {
+			JsonValue::String(str) => str,
+			other => other.to_string(),
+		};
 		Self(str.split("|").map(ToOwned::to_owned).collect())
 	}
 }
@@ -145,8 +149,11 @@ pub struct JsonModel {
 	pub textures: Option<HashMap<IString, String>>,
 
 	pub elements: Option<Vec<Element>>,
+
+	/// Inherited from the parent when absent, and true for models without one
+	#[serde(rename = "ambientocclusion")]
+	pub ambientOcclusion: Option<bool>,
 	// TODO
-	// pub ambientocclusion: bool;
 	// pub display: HashMap<Display, DisplayTransform>;
 }
 
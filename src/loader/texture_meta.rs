@@ -0,0 +1,100 @@
+use glam::{uvec2, UVec2};
+use serde::Deserialize;
+
+/// Contents of a texture's `.png.mcmeta` file.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TextureMeta {
+	pub animation: Option<AnimationMeta>,
+}
+
+/// Describes a texture made up of frames laid out left to right and top to bottom, which are
+/// shown one after another.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimationMeta {
+	/// Ticks each frame is shown for, unless it gives its own
+	#[serde(default = "AnimationMeta::default_frametime")]
+	pub frametime: u32,
+
+	/// Order to show frames in; every frame in turn if absent
+	pub frames: Option<Vec<AnimationFrame>>,
+
+	/// Whether to fade from each frame into the next over its time, rather than switching at once
+	#[serde(default)]
+	pub interpolate: bool,
+
+	/// Size of each frame in pixels; by default, frames are square and as wide as the texture
+	pub width: Option<u32>,
+
+	pub height: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum AnimationFrame {
+	Index(u32),
+	Timed {
+		index: u32,
+		time: Option<u32>,
+	},
+}
+
+impl AnimationMeta {
+	fn default_frametime() -> u32 {
+		1
+	}
+
+	/// Size of each frame of a texture `imageSize` pixels big, as vanilla's
+	/// `calculateFrameSize`.
+	pub fn frame_size(&self, imageSize: UVec2) -> UVec2 {
+		match (self.width, self.height) {
+			(Some(width), Some(height)) => uvec2(width, height),
+			(Some(width), None) => uvec2(width, imageSize.y),
+			(None, Some(height)) => uvec2(imageSize.x, height),
+			(None, None) => UVec2::splat(imageSize.x.min(imageSize.y)),
+		}
+	}
+
+	/// Returns each frame to show in turn and for how many ticks, out of the `frameCount` the
+	/// texture has. Frames past the end of the texture are skipped.
+	pub fn schedule(&self, frameCount: u32) -> Vec<(u32, u32)> {
+		let frames: Vec<_> = match &self.frames {
+			Some(frames) => frames
+				.iter()
+				.map(|&frame| match frame {
+					AnimationFrame::Index(index) => (index, self.frametime),
+					AnimationFrame::Timed { index, time } => {
+						(index, time.unwrap_or(self.frametime))
+					},
+				})
+				.filter(|&(index, _)| index < frameCount)
+				.collect(),
+			None => (0 .. frameCount).map(|index| (index, self.frametime)).collect(),
+		};
+		frames.into_iter().map(|(index, time)| (index, time.max(1))).collect()
+	}
+}
+
+#[test]
+fn test_texture_meta() {
+	let meta: TextureMeta = serde_json::from_str(r#"{"animation": {}}"#).unwrap();
+	let animation = meta.animation.unwrap();
+	assert_eq!(animation.frame_size(uvec2(16, 512)), UVec2::splat(16));
+	assert_eq!(animation.schedule(3), [(0, 1), (1, 1), (2, 1)]);
+
+	let json = r#"{
+		"animation": {
+			"frametime": 3,
+			"interpolate": true,
+			"height": 8,
+			"frames": [1, {"index": 0, "time": 10}, {"index": 0}, 7]
+		}
+	}"#;
+	let animation = serde_json::from_str::<TextureMeta>(json).unwrap().animation.unwrap();
+	assert!(animation.interpolate);
+	assert_eq!(animation.frame_size(uvec2(16, 32)), uvec2(16, 8));
+	// frame 7 doesn't exist
+	assert_eq!(animation.schedule(4), [(1, 3), (0, 10), (0, 3)]);
+
+	let meta: TextureMeta = serde_json::from_str(r#"{"villager": {"hat": "full"}}"#).unwrap();
+	assert!(meta.animation.is_none());
+}
@@ -1,12 +1,10 @@
 #![allow(non_snake_case, non_upper_case_globals, unused)]
 
 use std::borrow::{Borrow, Cow};
-use std::collections::hash_map::DefaultHasher;
 use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::TryInto;
 use std::f32::consts::TAU;
 use std::ffi::OsStr;
-use std::hash::{Hash, Hasher};
 use std::io::{Read, Write};
 use std::mem::size_of;
 use std::path::{Component, Path, PathBuf};
@@ -16,14 +14,26 @@ use anyhow::Context;
 use blockstate::BlockStates;
 use clap::Parser;
 use cuview::jarfs::JarFS;
-use cuview::loader::common::AnvilRegion;
+use cuview::loader::common::{AnvilRegion, RegionTimestamps};
 use cuview::loader::model::{Element, Face as JsonFace, JsonBlockState, JsonModel};
 use cuview::loader::{self, *};
-use cuview::renderer::model::{models_for_states, Cube, Model, ModelCache, Texture};
+use cuview::renderer::model::{
+	models_for_states,
+	Cube,
+	FullVertex,
+	Model,
+	ModelCache,
+	Texture,
+};
+use cuview::renderer::{entity, fluid};
 use cuview::renderer::texture::{Cartographer, Image, TextureId};
+use cuview::renderer::tint::BlockColors;
+use cuview::renderer::surface::SurfaceMapper;
+use cuview::renderer::tiles::{RenderManifest, TilePyramid};
+use cuview::renderer::{self, Camera, RenderSettings, Renderer};
 use cuview::types::blockstate::{BlockState, BlockStateBuilder, BlockStateCache};
 use cuview::types::resource_location::ResourceKind;
-use cuview::types::{BlockPos, ChunkPos, IString, RegionPos, ResourceLocation};
+use cuview::types::{BlockPos, ChunkArea, ChunkPos, IString, RegionPos, ResourceLocation};
 use cuview::world::Palette;
 use glam::{uvec2, vec2, vec3, Mat4, UVec2, Vec2, Vec3};
 use loader::model::{BlockStateModel, MultipartCase, OneOrMany};
@@ -117,8 +127,10 @@ fn main() {
 
 #[derive(Parser, Debug)]
 struct Args {
+	/// Blockstate dump from the vanilla data generator; derived from the jars' blockstate JSONs if
+	/// omitted
 	#[arg(short, long)]
-	blockstates: PathBuf,
+	blockstates: Option<PathBuf>,
 
 	#[arg(short, long)]
 	jars: Vec<PathBuf>,
@@ -129,14 +141,86 @@ struct Args {
 	#[arg(short, long)]
 	worldRoot: PathBuf,
 
-	#[arg(short, long)]
-	targetChunk: ChunkPos,
+	/// Single chunk to render, as `x,z`
+	#[arg(
+		short,
+		long,
+		required_unless_present_any = ["area", "tiles"],
+		conflicts_with_all = ["area", "tiles"]
+	)]
+	targetChunk: Option<ChunkPos>,
+
+	/// Rectangle of chunks to render, as two corners `x1,z1:x2,z2`
+	#[arg(short, long, conflicts_with = "tiles")]
+	area: Option<ChunkArea>,
+
+	/// Renders every region top-down into a `z/x/y.png` map tile pyramid in this directory,
+	/// instead of a single image
+	#[arg(long)]
+	tiles: Option<PathBuf>,
+
+	/// Width and height of map tiles, in pixels; surface map tiles always have one pixel per block
+	#[arg(long, default_value_t = 512)]
+	tileSize: u32,
+
+	/// Draws a flat map coloured by the topmost block of each column, one pixel per block,
+	/// without 3D rendering
+	#[arg(long)]
+	surfaceMap: bool,
+
+	/// Shades the surface map by height differences between neighbouring columns
+	#[arg(long, requires = "surfaceMap")]
+	heightShading: bool,
 
+	/// World-space position of the camera
 	#[arg(long, default_value_t = Vec3Arg(vec3(-5.0, 4.0, -5.0)))]
 	cameraOrigin: Vec3Arg,
 
 	#[arg(long, default_value_t = Vec2Arg(Vec2::splat(0.0)))]
 	cameraAngles: Vec2Arg,
+
+	/// Whether to render on the GPU or with the software rasterizer
+	#[arg(long, value_enum, default_value_t = Backend::Wgpu)]
+	backend: Backend,
+
+	/// Width of the output image, in pixels
+	#[arg(long, default_value_t = 1280)]
+	width: u32,
+
+	/// Height of the output image, in pixels
+	#[arg(long, default_value_t = 720)]
+	height: u32,
+
+	/// Background colour, as hex `RRGGBB` or `RRGGBBAA`
+	#[arg(long, default_value_t = ColorArg(0xFF_007FFF))]
+	clearColor: ColorArg,
+
+	/// MSAA samples per pixel on the GPU; 1 disables multisampling
+	#[arg(long, default_value_t = 4)]
+	samples: u32,
+
+	/// Game tick to show animated textures at, 20 to a second
+	#[arg(long, default_value_t = 0, conflicts_with_all = ["tiles", "surfaceMap"])]
+	tick: u64,
+
+	/// Renders this many consecutive ticks from `--tick` into an animated PNG
+	#[arg(
+		long,
+		default_value_t = 1,
+		value_parser = clap::value_parser!(u64).range(1 ..),
+		conflicts_with_all = ["tiles", "surfaceMap"]
+	)]
+	frames: u64,
+
+	/// Saves each of `--frames` as a numbered `out_0000.png` and so on, rather than one animation
+	#[arg(long, requires = "frames")]
+	numberedFrames: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum Backend {
+	Wgpu,
+	Cpu,
 }
 
 macro_rules! replace {
@@ -184,6 +268,36 @@ macro_rules! VecArg {
 VecArg!(Vec2Arg Vec2 [x y]);
 VecArg!(Vec3Arg Vec3 [x y z]);
 
+/// An `0xAABBGGRR` colour, written as hex `RRGGBB` or `RRGGBBAA`.
+#[derive(Clone, Copy, Debug)]
+struct ColorArg(u32);
+
+impl std::fmt::Display for ColorArg {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let [r, g, b, a] = self.0.to_le_bytes();
+		write!(f, "{r:02X}{g:02X}{b:02X}")?;
+		if a != 0xFF {
+			write!(f, "{a:02X}")?;
+		}
+		Ok(())
+	}
+}
+
+impl std::str::FromStr for ColorArg {
+	type Err = String;
+
+	fn from_str(str: &str) -> Result<Self, Self::Err> {
+		let str = str.trim_start_matches('#');
+		let rgba = match str.len() {
+			6 => u32::from_str_radix(str, 16).map(|v| v << 8 | 0xFF),
+			8 => u32::from_str_radix(str, 16),
+			_ => return Err(format!("expected RRGGBB or RRGGBBAA, got {str:?}")),
+		};
+		let rgba = rgba.map_err(|err| err.to_string())?;
+		Ok(Self(u32::from_le_bytes(rgba.to_be_bytes())))
+	}
+}
+
 enum CameraArgs {
 	Perspective {},
 }
@@ -212,29 +326,35 @@ fn main() {
 		version.0, version.1, version.2
 	);
 
-	let blockstates = std::fs::read_to_string(args.blockstates).unwrap();
-	let blockstates: blockstate::BlockStates = serde_json::from_str(&blockstates).unwrap();
-	let blockstates = BlockStateCache::from_json(blockstates);
-
 	if let Some(jarlist) = args.jarlist {
 		let contents = std::fs::read_to_string(jarlist).unwrap();
 		let paths = contents.lines().map(PathBuf::from);
 		args.jars.extend(paths);
 	}
+	// tiles rendered from other textures or models are stale too
+	let inputs = input_fingerprint(args.jars.iter().chain(&args.blockstates));
 	let fs = JarFS::new(args.jars).unwrap();
 
-	let models = ModelCache::from_jsons(&fs);
-	let statemap = models_for_states(&fs, &blockstates);
+	let mut blockstates = if let Some(path) = args.blockstates {
+		let blockstates = std::fs::read_to_string(path).unwrap();
+		let blockstates: blockstate::BlockStates = serde_json::from_str(&blockstates).unwrap();
+		BlockStateCache::from_json(blockstates)
+	} else {
+		BlockStateCache::from_jarfs(&fs)
+	};
+
+	entity::add_properties(&mut blockstates);
+
+	let mut models = ModelCache::from_jsons(&fs);
+	let mut statemap = models_for_states(&fs, &blockstates);
+	entity::add_models(&blockstates, &mut models, &mut statemap);
+	fluid::add_models(&mut models);
 
 	let wrangler = WorldWrangler::new(worldRoot).unwrap();
 
 	let dim = wrangler.probe_dimension("overworld".into()).unwrap();
 	let dim = wrangler.load_dimension(dim);
 
-	let targetChunk = args.targetChunk;
-	let region = wrangler.load_region(&dim, targetChunk.into());
-	let chunk = wrangler.load_chunk(&region, targetChunk);
-	let chunk = chunk.borrow();
 	/*let world = cuview::world::World::new(&worldRoot);
 	let dim = world.borrow_mut().new_dimension("overworld".into(), &worldRoot);
 	let region = dim.borrow_mut().new_region(RegionPos::new(0, 0));
@@ -330,568 +450,158 @@ fn main() {
 		} */
 	});
 
-	// #[cfg(none)]
-	pollster::block_on(async {
-		let instance = wgpu::Instance::new(wgpu::Backends::all());
-		let adapter = instance
-			.request_adapter(&wgpu::RequestAdapterOptions {
-				power_preference: wgpu::PowerPreference::default(),
-				force_fallback_adapter: false,
-				compatible_surface: None,
-			})
-			.await
-			.unwrap();
-		let (device, queue) = adapter
-			.request_device(
-				&wgpu::DeviceDescriptor {
-					label: None,
-					features: wgpu::Features::PUSH_CONSTANTS |
-						wgpu::Features::MULTI_DRAW_INDIRECT |
-						wgpu::Features::INDIRECT_FIRST_INSTANCE,
-					limits: wgpu::Limits {
-						max_push_constant_size: 128,
-						max_texture_dimension_2d: 32768,
-						..wgpu::Limits::default()
-					},
-				},
-				None,
-			)
-			.await
-			.unwrap();
-
-		let (cameraBuffer, imgWidth, imgHeight) = {
-			let (imgWidth, imgHeight) = (1280, 720);
-			let cameraBuffer = device.create_buffer(&wgpu::BufferDescriptor {
-				label: None,
-				size: size_of::<[f32; 32]>() as wgpu::BufferAddress,
-				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-				mapped_at_creation: false,
-			});
-			// #[cfg(none)]
-			let projection = Mat4::perspective_rh(
-				110f32.to_radians(),
-				imgWidth as f32 / imgHeight as f32,
-				0.01,
-				1000.0,
-			);
-			let rot = Mat4::from_rotation_y(args.cameraAngles.0.y.to_radians()) *
-				Mat4::from_rotation_x(args.cameraAngles.0.x.to_radians());
-			let forward = rot.transform_vector3(Vec3::Z);
-			dbg!(forward);
-			let camera =
-				Mat4::look_at_rh(args.cameraOrigin.0, args.cameraOrigin.0 + forward, Vec3::Y);
-
-			/* let rot = Mat4::from_rotation_y(args.cameraAngles.0.y.to_radians()) *
-				Mat4::from_rotation_x(args.cameraAngles.0.x.to_radians());
-			let forward = rot.transform_vector3(Vec3::Z);
-			let pos = vec3(0.0, 321.0, 0.0);
-			let camera = Mat4::look_at_rh(
-				/* args.cameraOrigin.0 */ pos,
-				/* args.cameraOrigin.0 */ pos + forward,
-				Vec3::Y,
-			);
-			let cube = Cube::new(vec3(0.0, -64.0, 0.0), vec3(16.0, 320.0, 16.0)).transform(camera);
-			let projection = Mat4::orthographic_rh(
-				cube.mins.x,
-				cube.maxs.x,
-				cube.mins.y,
-				cube.maxs.y,
-				0.0,
-				1000.0,
-			); */
-
-			queue.write_buffer(&cameraBuffer, 0, bytemuck::cast_slice(projection.as_ref()));
-			queue.write_buffer(
-				&cameraBuffer,
-				size_of::<[f32; 16]>() as wgpu::BufferAddress,
-				bytemuck::cast_slice(camera.as_ref()),
-			);
-
-			// let cubeSize = cube.size();
-			// let scale = 32.0;
-			// (
-			// 	cameraBuffer,
-			// 	(cubeSize.x * scale) as u32,
-			// 	(cubeSize.y * scale) as u32,
-			// )
-			(cameraBuffer, imgWidth, imgHeight)
-		};
-
-		let frameSize = wgpu::Extent3d {
-			width: imgWidth,
-			height: imgHeight,
-			depth_or_array_layers: 1,
-		};
-		let frameFormat = wgpu::TextureFormat::Rgba8Unorm;
-		let frameTexture = device.create_texture(&wgpu::TextureDescriptor {
-			label: Some("frameTexture"),
-			size: frameSize,
-			mip_level_count: 1,
-			sample_count: 1,
-			dimension: wgpu::TextureDimension::D2,
-			format: frameFormat,
-			usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-		});
-		let frameTextureMultisample = device.create_texture(&wgpu::TextureDescriptor {
-			label: Some("frameTextureMultisample"),
-			size: frameSize,
-			mip_level_count: 1,
-			sample_count: 4,
-			dimension: wgpu::TextureDimension::D2,
-			format: frameFormat,
-			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-		});
-		let frameDepthFormat = wgpu::TextureFormat::Depth24Plus;
-		let frameDepthTexture = device.create_texture(&wgpu::TextureDescriptor {
-			label: Some("frameDepthTexture"),
-			size: frameSize,
-			mip_level_count: 1,
-			sample_count: 4,
-			dimension: wgpu::TextureDimension::D2,
-			format: frameDepthFormat,
-			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-		});
-		let frameCopyBufferSize = ImgBufferSize::new(frameSize);
-		let frameCopyBuffer = device.create_buffer(&wgpu::BufferDescriptor {
-			label: None,
-			mapped_at_creation: false,
-			size: (frameCopyBufferSize.bplPadded * frameCopyBufferSize.height)
-				as wgpu::BufferAddress,
-			usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-		});
-		let surfaceConfig = wgpu::SurfaceConfiguration {
-			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-			format: frameFormat,
-			width: frameSize.width,
-			height: frameSize.height,
-			present_mode: wgpu::PresentMode::Immediate,
-		};
+	let settings = RenderSettings {
+		size: match args.tiles {
+			Some(_) => UVec2::splat(args.tileSize),
+			None => uvec2(args.width, args.height),
+		},
+		clearColor: args.clearColor.0,
+		sampleCount: args.samples,
+		backend: match args.backend {
+			Backend::Wgpu => renderer::Backend::Wgpu,
+			Backend::Cpu => renderer::Backend::Cpu,
+		},
+	};
+	let (cartographer, blockTextureLayers) =
+		Cartographer::load_with_max_size(&fs, &models, settings.max_texture_diameter()).unwrap();
+	let blockColors = BlockColors::load(&fs).unwrap();
+	let (renderer, surfaceMapper) = if args.surfaceMap {
+		let mapper = SurfaceMapper::new(
+			&blockstates,
+			&statemap,
+			&models,
+			&cartographer,
+			&blockTextureLayers,
+			&blockColors,
+		);
+		(None, Some(mapper))
+	} else {
+		let renderer = Renderer::new(
+			&blockstates,
+			&statemap,
+			&models,
+			&cartographer,
+			&blockTextureLayers,
+			&blockColors,
+			settings,
+		)
+		.unwrap();
+		(Some(renderer), None)
+	};
 
-		let (cartographer, blockTextureLayers) = Cartographer::load(&fs, &models, &device).unwrap();
-		#[cfg(none)]
-		{
-			let base = PathBuf::from("./aout/");
-			std::fs::remove_dir_all(&base).unwrap_or_default();
-			std::fs::create_dir(&base).unwrap();
-			let diams = cartographer.element_diameters();
-			for (id, img) in blockTextureLayers.iter().enumerate() {
-				let diameter = diams[id];
-				let UVec2 {
-					x: width,
-					y: height,
-				} = img.size;
-				let path = base.join(format!("layer{id}_{width}x{height}_{diameter}x.png"));
-				img.save_to_file(&path).unwrap();
-				eprintln!("ok wrote {path:?}");
-			}
-		}
-		let blockTextureSize = wgpu::Extent3d {
-			width: blockTextureLayers[0].size.x,
-			height: blockTextureLayers[0].size.y,
-			depth_or_array_layers: blockTextureLayers.len() as u32,
-		};
-		let blockTexture = device.create_texture(&wgpu::TextureDescriptor {
-			label: None,
-			size: blockTextureSize,
-			mip_level_count: 1,
-			sample_count: 1,
-			dimension: wgpu::TextureDimension::D2,
-			format: wgpu::TextureFormat::Rgba8Unorm,
-			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-		});
-		let blockTextureView = blockTexture.create_view(&wgpu::TextureViewDescriptor {
-			dimension: Some(wgpu::TextureViewDimension::D2Array),
-			..Default::default()
-		});
-		for (i, layer) in blockTextureLayers.iter().enumerate() {
-			let mut dest = blockTexture.as_image_copy();
-			dest.origin = wgpu::Origin3d {
-				x: 0,
-				y: 0,
-				z: i as u32,
-			};
-			queue.write_texture(
-				dest,
-				bytemuck::cast_slice(&layer.pixels),
-				wgpu::ImageDataLayout {
-					offset: 0,
-					bytes_per_row: Some(
-						(layer.size.x * size_of::<u32>() as u32).try_into().unwrap(),
-					),
-					rows_per_image: None,
-				},
-				wgpu::Extent3d {
-					depth_or_array_layers: 1,
-					..blockTextureSize
-				},
+	if let Some(tileDir) = args.tiles {
+		let regions = wrangler.probe_regions(&dim);
+		let pyramid = TilePyramid::new(&tileDir, regions.iter().copied());
+		let manifestPath = tileDir.join("manifest.json");
+		let manifestSettings = format!(
+			"surfaceMap={} heightShading={} tileSize={} clearColor={} samples={} backend={:?} \
+			 inputs={}",
+			args.surfaceMap,
+			args.heightShading,
+			args.tileSize,
+			args.clearColor,
+			args.samples,
+			args.backend,
+			inputs
+		);
+		let mut manifest = RenderManifest::load(&manifestPath, &manifestSettings).unwrap();
+		let regionDir = dim.borrow().region_dir();
+		let mut changed = vec![];
+		for (i, &regionPos) in regions.iter().enumerate() {
+			eprint!(
+				"\rregion {}/{} ({},{})            ",
+				i + 1,
+				regions.len(),
+				regionPos.x,
+				regionPos.z
 			);
-		}
-		let blockTextureSampler = device.create_sampler(&wgpu::SamplerDescriptor {
-			address_mode_u: wgpu::AddressMode::ClampToEdge,
-			address_mode_v: wgpu::AddressMode::ClampToEdge,
-			address_mode_w: wgpu::AddressMode::ClampToEdge,
-			mag_filter: wgpu::FilterMode::Nearest,
-			min_filter: wgpu::FilterMode::Linear,
-			..Default::default()
-		});
-		let atlasDiameters = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: None,
-			usage: wgpu::BufferUsages::STORAGE,
-			contents: bytemuck::cast_slice(cartographer.element_diameters()),
-		});
-
-		let geometry = models.geometry_buffer(&cartographer);
-		let blockModelsBuffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: None,
-			usage: wgpu::BufferUsages::VERTEX,
-			contents: bytemuck::cast_slice(&geometry.vertices),
-		});
-
-		// assuming worst case every block in section is composed of 10 submodels
-		const submodelsPerBlock: usize = 10;
-		const submodelsPerSection: usize =
-			ChunkPos::diameterBlocks.pow(3) as usize * submodelsPerBlock;
-		let indirectBuffers: Vec<_> = ChunkPos::sections
-			.map(|_| {
-				device.create_buffer(&wgpu::BufferDescriptor {
-					label: None,
-					size: (submodelsPerSection * size_of::<wgpu::util::DrawIndirect>())
-						as wgpu::BufferAddress,
-					usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST,
-					mapped_at_creation: false,
-				})
-			})
-			.collect();
-
-		/* let debugTris: &[f32] = &[
-			0.0, 1.0, -1.0,   0.5, 1.0,
-			-1.0, 0.0, 1.0,   0.0, 0.0,
-			1.0, 0.0, 1.0,   1.0, 0.0,
-
-			0.0, 0.0, 0.0,   1.0, 0.0,
-			1.0, 0.0, 0.0,   1.0, 0.0,
-			0.0, 0.0, 1.0,   1.0, 0.0,
-
-			0.0, 0.0, 0.0,   0.0, 1.0,
-			1.0, 0.0, 0.0,   0.0, 1.0,
-			0.0, 1.0, 0.0,   0.0, 1.0,
-
-			0.0, 0.0, 0.0,   1.0, 1.0,
-			0.0, 1.0, 0.0,   1.0, 1.0,
-			0.0, 0.0, 1.0,   1.0, 1.0,
-		];
-		let debugTris = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: None,
-			usage: wgpu::BufferUsages::VERTEX,
-			contents: bytemuck::cast_slice(debugTris),
-		}); */
-
-		let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-			label: None,
-			source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/main.wgsl"))),
-		});
-		let bindGroupLayout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-			label: None,
-			entries: &[
-				wgpu::BindGroupLayoutEntry {
-					binding: 0,
-					visibility: wgpu::ShaderStages::VERTEX,
-					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Uniform,
-						has_dynamic_offset: false,
-						min_binding_size: wgpu::BufferSize::new(
-							size_of::<[f32; 32]>() as wgpu::BufferAddress
-						),
-					},
-					count: None,
+			let timestamps = match RegionTimestamps::read(&regionDir, regionPos) {
+				Ok(v) => v,
+				Err(error) => {
+					wrangler.record_problem(Problem {
+						region: regionPos,
+						chunk: None,
+						error,
+					});
+					continue;
 				},
-				wgpu::BindGroupLayoutEntry {
-					binding: 1,
-					visibility: wgpu::ShaderStages::VERTEX,
-					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Storage { read_only: true },
-						has_dynamic_offset: false,
-						min_binding_size: None,
-					},
-					count: None,
-				},
-				wgpu::BindGroupLayoutEntry {
-					binding: 2,
-					visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Texture {
-						sample_type: wgpu::TextureSampleType::Float { filterable: true },
-						view_dimension: wgpu::TextureViewDimension::D2Array,
-						multisampled: false,
-					},
-					count: None,
-				},
-				wgpu::BindGroupLayoutEntry {
-					binding: 3,
-					visibility: wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-					count: None,
-				},
-			],
-		});
-		let bindGroup = device.create_bind_group(&wgpu::BindGroupDescriptor {
-			label: None,
-			layout: &bindGroupLayout,
-			entries: &[
-				wgpu::BindGroupEntry {
-					binding: 0,
-					resource: cameraBuffer.as_entire_binding(),
-				},
-				wgpu::BindGroupEntry {
-					binding: 1,
-					resource: atlasDiameters.as_entire_binding(),
-				},
-				wgpu::BindGroupEntry {
-					binding: 2,
-					resource: wgpu::BindingResource::TextureView(&blockTextureView),
-				},
-				wgpu::BindGroupEntry {
-					binding: 3,
-					resource: wgpu::BindingResource::Sampler(&blockTextureSampler),
-				},
-			],
-		});
-		let pipelineLayout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-			label: None,
-			bind_group_layouts: &[&bindGroupLayout],
-			push_constant_ranges: &[
-				wgpu::PushConstantRange {
-					range: 0 .. 4,
-					stages: wgpu::ShaderStages::VERTEX,
-				},
-			],
-		});
-		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-			label: None,
-			layout: Some(&pipelineLayout),
-			vertex: wgpu::VertexState {
-				module: &shader,
-				entry_point: "vsMain",
-				buffers: &[
-					wgpu::VertexBufferLayout {
-						array_stride: size_of::<[f32; 6]>() as wgpu::BufferAddress,
-						step_mode: wgpu::VertexStepMode::Vertex,
-						attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Uint32],
-					},
-				],
-			},
-			fragment: Some(wgpu::FragmentState {
-				module: &shader,
-				entry_point: "fsMain",
-				targets: &[Some(
-					wgpu::ColorTargetState {
-						format: frameFormat,
-						blend: Some(wgpu::BlendState {
-							color: wgpu::BlendComponent {
-								src_factor: wgpu::BlendFactor::SrcAlpha,
-								dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-								operation: wgpu::BlendOperation::Add,
-							},
-							alpha: wgpu::BlendComponent {
-								src_factor: wgpu::BlendFactor::One,
-								dst_factor: wgpu::BlendFactor::One,
-								operation: wgpu::BlendOperation::Max,
-							},
-						}),
-						write_mask: wgpu::ColorWrites::ALL,
-					},
-				)],
-			}),
-			primitive: wgpu::PrimitiveState {
-				cull_mode: None, // Some(wgpu::Face::Back),
-				..wgpu::PrimitiveState::default()
-			},
-			depth_stencil: Some(wgpu::DepthStencilState {
-				format: wgpu::TextureFormat::Depth24Plus,
-				depth_write_enabled: true,
-				depth_compare: wgpu::CompareFunction::Less,
-				stencil: wgpu::StencilState::default(),
-				bias: wgpu::DepthBiasState::default(),
-			}),
-			multisample: wgpu::MultisampleState {
-				count: 4,
-				..Default::default()
-			},
-			multiview: None,
-		});
-
-		let mut encoder = device.create_command_encoder(&Default::default());
-		{
-			let colorView = frameTexture.create_view(&Default::default());
-			let multisampleView = frameTextureMultisample.create_view(&Default::default());
-			let depthView = frameDepthTexture.create_view(&wgpu::TextureViewDescriptor {
-				aspect: wgpu::TextureAspect::DepthOnly,
-				..Default::default()
-			});
-
-			let mut clearPass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-				label: None,
-				color_attachments: &[Some(
-					wgpu::RenderPassColorAttachment {
-						view: &multisampleView,
-						resolve_target: Some(&colorView),
-						ops: wgpu::Operations {
-							load: wgpu::LoadOp::Clear(wgpu::Color {
-								r: 1.0,
-								g: 0.5,
-								b: 0.0,
-								a: 1.0,
-							}),
-							store: true,
-						},
-					},
-				)],
-				depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-					view: &depthView,
-					depth_ops: Some(wgpu::Operations {
-						load: wgpu::LoadOp::Clear(1.0),
-						store: true,
-					}),
-					stencil_ops: None,
-				}),
-			});
-			drop(clearPass);
-
-			let mut indirectDraws = vec![];
-			for sectionY in chunk.sections() {
-				indirectDraws.clear();
-				let section = chunk.get_section(sectionY).unwrap();
-				let section = section.borrow();
-				for blockPos in targetChunk.blocks_in_section(sectionY) {
-					let state = section.get_block(blockPos);
-					let modelsets = statemap.get(&state).unwrap();
-					for set in modelsets {
-						// FIXME: weighting
-						let model = &set[blockpos_rng(blockPos).rem_euclid(set.len())];
-						let modelId = model.model;
-						if let Some((baseVertex, numVerts)) =
-							geometry.modelInfo.get(&modelId).copied()
-						{
-							let blockRel = blockPos.chunk_relative();
-							let blockIndex = blockRel.y * ChunkPos::diameterBlocks.pow(2) +
-								blockRel.z * ChunkPos::diameterBlocks +
-								blockRel.x;
-
-							// pack rotations into the unused upper 20 bits of instance id
-							// let rot = vec2(45f32.to_radians(), 0.0/* (14.5 * blockIndex as
-							// f32).to_radians() */);
-							let rot = vec2(
-								model.xRotation.unwrap_or(0.0).to_radians(),
-								model.yRotation.unwrap_or(0.0).to_radians(),
-							);
-							let rotTurns =
-								Vec2::from((rot / TAU).as_ref().map(|v| v.rem_euclid(1.0)));
-							let rotDiscrete = (rotTurns * 1024.0).as_uvec2();
-							let rotPacked = (rotDiscrete.y & 1023) << 10 | rotDiscrete.x & 1023;
-
-							let instance = rotPacked << 12 | blockIndex as u32;
-							indirectDraws.extend(
-								DrawIndirect {
-									base_vertex: baseVertex as u32,
-									vertex_count: numVerts as u32,
-									base_instance: instance,
-									instance_count: 1,
-								}
-								.as_bytes(),
-							);
-						}
-					}
-				}
-
-				let indirectBuffer =
-					&indirectBuffers[(sectionY - ChunkPos::sections.start()) as usize];
-				queue.write_buffer(indirectBuffer, 0, &indirectDraws);
-				// queue.submit(None);
-				let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-					label: None,
-					color_attachments: &[Some(
-						wgpu::RenderPassColorAttachment {
-							view: &multisampleView,
-							resolve_target: Some(&colorView),
-							ops: wgpu::Operations {
-								load: wgpu::LoadOp::Load,
-								store: true,
-							},
-						},
-					)],
-					depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-						view: &depthView,
-						depth_ops: Some(wgpu::Operations {
-							load: wgpu::LoadOp::Load,
-							store: true,
-						}),
-						stencil_ops: None,
-					}),
-				});
-				pass.set_pipeline(&pipeline);
-				pass.set_bind_group(0, &bindGroup, &[]);
-				pass.set_vertex_buffer(0, blockModelsBuffer.slice(..));
-				pass.set_push_constants(
-					wgpu::ShaderStages::VERTEX,
-					0,
-					bytemuck::bytes_of(&(sectionY as i32)),
-				);
-				// pass.set_push_constants(wgpu::ShaderStages::VERTEX, 4, );
-				pass.multi_draw_indirect(
-					indirectBuffer,
-					0,
-					(indirectDraws.len() / size_of::<DrawIndirect>()) as u32,
-				);
-				// drop(pass);
-				// queue.submit(None);
+			};
+			if !manifest.is_stale(&timestamps) && pyramid.has_region_tile(regionPos) {
+				continue;
 			}
-			// drop(pass);
-
-			encoder.copy_texture_to_buffer(
-				frameTexture.as_image_copy(),
-				wgpu::ImageCopyBuffer {
-					buffer: &frameCopyBuffer,
-					layout: wgpu::ImageDataLayout {
-						offset: 0,
-						bytes_per_row: Some(
-							(frameCopyBufferSize.bplPadded as u32).try_into().unwrap(),
-						),
-						rows_per_image: None,
-					},
+			let region = match wrangler.load_region(&dim, regionPos) {
+				Some(v) => v,
+				None => continue,
+			};
+			let chunks = wrangler.load_all_chunks(&region);
+			let image = match (&renderer, &surfaceMapper) {
+				(_, Some(mapper)) => mapper.render(&chunks, regionPos.into(), args.heightShading),
+				(Some(renderer), _) => {
+					let camera = Camera::top_down(regionPos.into());
+					renderer.render_chunks(&chunks, &camera).image
 				},
-				frameSize,
-			)
+				(None, None) => unreachable!(),
+			};
+			pyramid.save_region_tile(regionPos, &image).unwrap();
+			drop(chunks);
+			dim.borrow_mut().unload_region(regionPos);
+			manifest.record(&timestamps);
+			changed.push(regionPos);
 		}
-		let submission = queue.submit(Some(encoder.finish()));
+		eprintln!();
+		println!("Rendered {} of {} regions", changed.len(), regions.len());
+		pyramid.build_zoomed_out_of(changed).unwrap();
+		manifest.save(&manifestPath).unwrap();
+		report_problems(&wrangler, &tileDir.join("problems.json"));
+		return;
+	}
 
-		let slice = frameCopyBuffer.slice(..);
-		slice.map_async(wgpu::MapMode::Read, |_| {});
-		if !device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission)) {
-			std::thread::sleep(std::time::Duration::from_secs_f32(1.5));
-		}
+	let area = match (args.area, args.targetChunk) {
+		(Some(area), _) => area,
+		(None, Some(targetChunk)) => ChunkArea::from(targetChunk),
+		(None, None) => unreachable!("clap requires one of them"),
+	};
+	let existingRegions: HashSet<_> = wrangler.probe_regions(&dim).into_iter().collect();
+	let mut chunks = vec![];
+	for regionPos in area.regions().filter(|pos| existingRegions.contains(pos)) {
+		let region = match wrangler.load_region(&dim, regionPos) {
+			Some(v) => v,
+			None => continue,
+		};
+		let mut positions = wrangler.probe_chunks(&region);
+		positions.retain(|&pos| area.contains(pos));
+		chunks.extend(wrangler.load_chunks(&region, &positions));
+	}
+	println!("Loaded {} of {} chunks", chunks.len(), area.chunks().count());
+	report_problems(&wrangler, Path::new("problems.json"));
 
-		let padded = slice.get_mapped_range();
-		let mut pixels = vec![0u8; frameCopyBufferSize.bplUnpadded * frameCopyBufferSize.height];
-		let mut pixslice = &mut pixels[..];
-		for chunk in padded.chunks(frameCopyBufferSize.bplPadded) {
-			let len = frameCopyBufferSize.bplUnpadded;
-			pixslice[0 .. len].copy_from_slice(&chunk[0 .. len]);
-			pixslice = &mut pixslice[len ..];
+	if let Some(mapper) = surfaceMapper {
+		let image = mapper.render(&chunks, area, args.heightShading);
+		image.save_to_file(Path::new("out.png")).unwrap();
+		return;
+	}
+	let renderer = renderer.unwrap();
+	let camera = Camera::perspective(
+		args.cameraOrigin.0,
+		args.cameraAngles.0,
+		settings.aspect_ratio(),
+	);
+	let outputs = renderer.render_animation(&chunks, &camera, args.tick .. args.tick + args.frames);
+	println!(
+		"Faces: {} drawn, {} culled",
+		outputs[0].emittedFaces, outputs[0].culledFaces
+	);
+	if args.numberedFrames {
+		for (i, output) in outputs.iter().enumerate() {
+			output.image.save_to_file(Path::new(&format!("out_{i:04}.png"))).unwrap();
 		}
-		drop(padded);
-		frameCopyBuffer.unmap();
-
-		let file = std::fs::OpenOptions::new()
-			.write(true)
-			.create(true)
-			.truncate(true)
-			.open("out.png")
-			.unwrap();
-		let mut encoder = png::Encoder::new(file, frameSize.width, frameSize.height);
-		encoder.set_color(png::ColorType::Rgba);
-		encoder.set_depth(png::BitDepth::Eight);
-		let mut writer = encoder.write_header().unwrap();
-		writer.write_image_data(&pixels).unwrap();
-	});
+	} else if let [output] = &outputs[..] {
+		output.image.save_to_file(Path::new("out.png")).unwrap();
+	} else {
+		let frames: Vec<_> = outputs.into_iter().map(|output| output.image).collect();
+		// one frame per game tick
+		Image::save_animation(&frames, Path::new("out.png"), (1, 20)).unwrap();
+	}
 
 	#[cfg(none)]
 	{
@@ -925,32 +635,38 @@ fn main() {
 	}
 }
 
-fn blockpos_rng(pos: BlockPos) -> usize {
-	let mut hasher = DefaultHasher::new();
-	pos.hash(&mut hasher);
-	hasher.finish() as usize
-}
-
-#[derive(Clone, Copy, Debug)]
-struct ImgBufferSize {
-	pub width: usize,
-	pub height: usize,
-	pub bplUnpadded: usize,
-	pub bplPadded: usize,
+/// Identifies the contents of input files by their paths, sizes and modification times, as
+/// `path:size:mtime` joined by `;`.
+fn input_fingerprint<'a>(paths: impl IntoIterator<Item = &'a PathBuf>) -> String {
+	let fingerprints: Vec<_> = paths
+		.into_iter()
+		.map(|path| {
+			let metadata = std::fs::metadata(path).ok();
+			let size = metadata.as_ref().map_or(0, |v| v.len());
+			let mtime = metadata
+				.and_then(|v| v.modified().ok())
+				.and_then(|v| v.duration_since(std::time::UNIX_EPOCH).ok())
+				.map_or(0, |v| v.as_secs());
+			format!("{}:{size}:{mtime}", path.display())
+		})
+		.collect();
+	fingerprints.join(";")
 }
 
-impl ImgBufferSize {
-	pub fn new(extent: wgpu::Extent3d) -> Self {
-		let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
-		let bpl = extent.width * std::mem::size_of::<u32>() as u32;
-		let padding = (align - bpl % align) % align;
-		Self {
-			width: extent.width as usize,
-			height: extent.height as usize,
-			bplUnpadded: bpl as usize,
-			bplPadded: (bpl + padding) as usize,
-		}
+/// Writes the regions and chunks which were skipped for being corrupt to `path`, if there were any.
+fn report_problems(wrangler: &WorldWrangler, path: &Path) {
+	let problems = wrangler.take_problems();
+	if problems.is_empty() {
+		return;
+	}
+	for problem in &problems {
+		eprintln!(
+			"skipped region {:?} chunk {:?}: {}",
+			problem.region, problem.chunk, problem.error
+		);
 	}
+	write_problem_report(path, &problems).unwrap();
+	eprintln!("{} problems, see {path:?}", problems.len());
 }
 
 #[cfg(none)]
@@ -0,0 +1,694 @@
+use std::collections::{BTreeMap, HashMap};
+
+use glam::{vec2, BVec3, Mat4, Vec2, Vec3};
+
+use super::model::{Direction, Face, Model, ModelCache, Texture, Vertex};
+use super::tint;
+use crate::loader::flattening::colors as dyeColors;
+use crate::loader::model::BlockStateModel;
+use crate::types::blockstate::{BlockState, BlockStateCache};
+use crate::types::{IString, ResourceLocation};
+use crate::world::BlockEntity;
+
+/// Colours vanilla multiplies banner patterns by, in the order of [`dyeColors`], as `0xRRGGBB`
+const dyeTints: [u32; 16] = [
+	0xF9FFFE, 0xF9801D, 0xC74EBD, 0x3AB3DA, 0xFED83D, 0x80C71F, 0xF38BAA, 0x474F52, 0x9D9D97,
+	0x169C9C, 0x8932B8, 0x3C44AA, 0x835432, 0x5E7C16, 0xB02E26, 0x1D1D21,
+];
+
+/// Banner patterns as `(name, code)`; the name is that of the texture in `entity/banner` and, since
+/// 1.20.5, of the pattern in worlds, which stored the short code before then.
+#[rustfmt::skip]
+const bannerPatterns: &[(&str, &str)] = &[
+	("base", "b"), ("border", "bo"), ("bricks", "bri"), ("circle", "mc"), ("creeper", "cre"),
+	("cross", "cr"), ("curly_border", "cbo"), ("diagonal_left", "ld"), ("diagonal_right", "rud"),
+	("diagonal_up_left", "lud"), ("diagonal_up_right", "rd"), ("flow", "flw"), ("flower", "flo"),
+	("globe", "glb"), ("gradient", "gra"), ("gradient_up", "gru"), ("guster", "gus"),
+	("half_horizontal", "hh"), ("half_horizontal_bottom", "hhb"), ("half_vertical", "vh"),
+	("half_vertical_right", "vhr"), ("mojang", "moj"), ("piglin", "pig"), ("rhombus", "mr"),
+	("skull", "sku"), ("small_stripes", "ss"), ("square_bottom_left", "bl"),
+	("square_bottom_right", "br"), ("square_top_left", "tl"), ("square_top_right", "tr"),
+	("straight_cross", "sc"), ("stripe_bottom", "bs"), ("stripe_center", "cs"),
+	("stripe_downleft", "dls"), ("stripe_downright", "drs"), ("stripe_left", "ls"),
+	("stripe_middle", "ms"), ("stripe_right", "rs"), ("stripe_top", "ts"),
+	("triangle_bottom", "bt"), ("triangle_top", "tt"), ("triangles_bottom", "bts"),
+	("triangles_top", "tts"),
+];
+
+/// Mob heads as `(block, wall block, texture, whether the texture has a hat)`. Dragon and piglin
+/// heads have models of their own and aren't drawn.
+const skulls: &[(&str, &str, &str, bool)] = &[
+	("skeleton_skull", "skeleton_wall_skull", "entity/skeleton/skeleton", false),
+	(
+		"wither_skeleton_skull",
+		"wither_skeleton_wall_skull",
+		"entity/skeleton/wither_skeleton",
+		false,
+	),
+	("zombie_head", "zombie_wall_head", "entity/zombie/zombie", true),
+	("creeper_head", "creeper_wall_head", "entity/creeper/creeper", false),
+	("player_head", "player_wall_head", "entity/steve", true),
+];
+
+const horizontalFacings: &[&str] = &["north", "east", "south", "west"];
+
+const rotations: &[&str] = &[
+	"0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15",
+];
+
+/// A block which vanilla draws with a block entity renderer instead of a JSON model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EntityBlock {
+	Chest {
+		/// Name of the texture in `entity/chest`
+		texture: &'static str,
+		/// Whether the chest can be one half of a double chest
+		double: bool,
+	},
+	/// Index into [`dyeColors`]
+	Bed(usize),
+	Sign {
+		wood: IString,
+		wall: bool,
+	},
+	Banner {
+		/// Index into [`dyeColors`]
+		color: usize,
+		wall: bool,
+	},
+	/// Index into [`dyeColors`], if dyed
+	ShulkerBox(Option<usize>),
+	Skull {
+		/// Index into [`skulls`]
+		skull: usize,
+		wall: bool,
+	},
+	/// Only the bell itself; its frame has a JSON model
+	Bell,
+}
+
+impl EntityBlock {
+	fn of(block: ResourceLocation) -> Option<Self> {
+		if block.modid.as_str() != "minecraft" {
+			return None;
+		}
+		let name = block.name.as_str();
+		let dye = |color: &str| dyeColors.iter().position(|&v| v == color);
+		let res = match name {
+			"chest" => Self::Chest {
+				texture: "normal",
+				double: true,
+			},
+			"trapped_chest" => Self::Chest {
+				texture: "trapped",
+				double: true,
+			},
+			"ender_chest" => Self::Chest {
+				texture: "ender",
+				double: false,
+			},
+			"shulker_box" => Self::ShulkerBox(None),
+			"bell" => Self::Bell,
+			_ if name.ends_with("_hanging_sign") => return None,
+			_ => {
+				if let Some(color) = name.strip_suffix("_shulker_box").and_then(dye) {
+					Self::ShulkerBox(Some(color))
+				} else if let Some(color) = name.strip_suffix("_bed").and_then(dye) {
+					Self::Bed(color)
+				} else if let Some(color) = name.strip_suffix("_wall_banner").and_then(dye) {
+					Self::Banner { color, wall: true }
+				} else if let Some(color) = name.strip_suffix("_banner").and_then(dye) {
+					Self::Banner { color, wall: false }
+				} else if let Some(wood) = name.strip_suffix("_wall_sign") {
+					Self::Sign {
+						wood: wood.into(),
+						wall: true,
+					}
+				} else if let Some(wood) = name.strip_suffix("_sign") {
+					Self::Sign {
+						wood: wood.into(),
+						wall: false,
+					}
+				} else {
+					let (skull, &(floor, ..)) = skulls
+						.iter()
+						.enumerate()
+						.find(|(_, &(floor, wall, ..))| name == floor || name == wall)?;
+					Self::Skull {
+						skull,
+						wall: name != floor,
+					}
+				}
+			},
+		};
+		Some(res)
+	}
+
+	/// The properties this block's model depends on, with all of their values.
+	fn properties(self) -> &'static [(&'static str, &'static [&'static str])] {
+		match self {
+			Self::Chest { double: true, .. } => &[
+				("facing", horizontalFacings),
+				("type", &["single", "left", "right"]),
+			],
+			Self::Bed(_) => &[("facing", horizontalFacings), ("part", &["foot", "head"])],
+			Self::Sign { wall: false, .. } |
+			Self::Banner { wall: false, .. } |
+			Self::Skull { wall: false, .. } => &[("rotation", rotations)],
+			Self::Chest { .. } |
+			Self::Sign { .. } |
+			Self::Banner { .. } |
+			Self::Skull { .. } => &[("facing", horizontalFacings)],
+			Self::ShulkerBox(_) => &[(
+				"facing",
+				&["down", "up", "north", "south", "west", "east"],
+			)],
+			Self::Bell => &[],
+		}
+	}
+
+	/// Returns the name of the model to draw `state` with, and its rotation about X and Y in the
+	/// style of blockstate variants.
+	fn variant(self, state: BlockState) -> Option<(String, f32, f32)> {
+		let block = state.block_name().name;
+		let standing = || Some(state.get_property("rotation")?.parse::<u8>().ok()? as f32 * 22.5);
+		let res = match self {
+			Self::Chest { double, .. } => {
+				let name = match state.get_property("type") {
+					Some(side @ ("left" | "right")) if double => format!("{block}_{side}"),
+					_ => block.to_string(),
+				};
+				(name, 0.0, facing_rotation(state)?)
+			},
+			Self::Bed(_) => {
+				let name = format!("{block}_{}", state.get_property("part")?);
+				(name, 0.0, (facing_rotation(state)? + 180.0) % 360.0)
+			},
+			Self::Sign { wall: false, .. } |
+			Self::Banner { wall: false, .. } |
+			Self::Skull { wall: false, .. } => (block.to_string(), 0.0, standing()?),
+			Self::Sign { .. } | Self::Banner { .. } | Self::Skull { .. } => {
+				(block.to_string(), 0.0, facing_rotation(state)?)
+			},
+			Self::ShulkerBox(_) => {
+				let (xRotation, yRotation) = match state.get_property("facing")? {
+					"up" => (0.0, 0.0),
+					"down" => (180.0, 0.0),
+					"south" => (270.0, 0.0),
+					"west" => (270.0, 90.0),
+					"north" => (270.0, 180.0),
+					"east" => (270.0, 270.0),
+					_ => return None,
+				};
+				(block.to_string(), xRotation, yRotation)
+			},
+			Self::Bell => (block.to_string(), 0.0, 0.0),
+		};
+		Some(res)
+	}
+
+	/// Builds the model [`Self::variant`] names for `state`, unrotated.
+	fn model(self, id: ResourceLocation, state: BlockState) -> Model {
+		let texture = |name: &str| ResourceLocation::new("minecraft", &format!("entity/{name}"));
+		let origin = Mat4::IDENTITY;
+		match self {
+			Self::Chest { texture: name, .. } => {
+				let chestType = state.get_property("type").unwrap_or("single");
+				let (name, x, width, lockX, lockWidth) = match chestType {
+					"left" => (format!("{name}_left"), 0.0, 15.0, 0.0, 1.0),
+					"right" => (format!("{name}_right"), 1.0, 15.0, 15.0, 1.0),
+					_ => (name.to_owned(), 1.0, 14.0, 7.0, 2.0),
+				};
+				let mut model = EntityModel::new(id, texture(&format!("chest/{name}")), 64.0);
+				let bottom = cuboid([0.0, 19.0], [x, 0.0, 1.0], [width, 10.0, 14.0]);
+				let lid = cuboid([0.0, 0.0], [x, 0.0, 0.0], [width, 5.0, 14.0]);
+				let lock = cuboid([0.0, 0.0], [lockX, -1.0, 15.0], [lockWidth, 4.0, 1.0]);
+				model.add(origin, bottom);
+				model.add(origin * part([0.0, 9.0, 1.0], Vec3::ZERO), lid);
+				model.add(origin * part([0.0, 8.0, 0.0], Vec3::ZERO), lock);
+				model.build()
+			},
+			Self::Bed(color) => {
+				let texture = texture(&format!("bed/{}", dyeColors[color]));
+				let mut model = EntityModel::new(id, texture, 64.0);
+				let origin = Mat4::from_translation(Vec3::new(0.0, 9.0 / 16.0, 0.0)) *
+					Mat4::from_rotation_x(90f32.to_radians());
+				let half = std::f32::consts::FRAC_PI_2;
+				let legs = if state.get_property("part") == Some("head") {
+					model.add(origin, cuboid([0.0, 0.0], [0.0; 3], [16.0, 16.0, 6.0]));
+					[
+						([50.0, 6.0], [0.0, 6.0, 0.0], Vec3::new(half, 0.0, half)),
+						([50.0, 18.0], [-16.0, 6.0, 0.0], Vec3::new(half, 0.0, half * 2.0)),
+					]
+				} else {
+					model.add(origin, cuboid([0.0, 22.0], [0.0; 3], [16.0, 16.0, 6.0]));
+					[
+						([50.0, 0.0], [0.0, 6.0, -16.0], Vec3::new(half, 0.0, 0.0)),
+						([50.0, 12.0], [-16.0, 6.0, -16.0], Vec3::new(half, 0.0, half * 3.0)),
+					]
+				};
+				for (texOffset, from, rotation) in legs {
+					let leg = cuboid(texOffset, from, [3.0; 3]);
+					model.add(origin * part([0.0; 3], rotation), leg);
+				}
+				model.build()
+			},
+			Self::Sign { wood, wall } => {
+				let mut model = EntityModel::new(id, texture(&format!("signs/{wood}")), 64.0);
+				let origin = sign_origin(wall);
+				model.add(origin, cuboid([0.0, 0.0], [-12.0, -14.0, -1.0], [24.0, 12.0, 2.0]));
+				if !wall {
+					model.add(origin, cuboid([0.0, 14.0], [-1.0, -2.0, -1.0], [2.0, 14.0, 2.0]));
+				}
+				model.build()
+			},
+			Self::Banner { color, wall } => {
+				let mut model = EntityModel::new(id, texture("banner_base"), 64.0);
+				let origin = banner_origin(wall);
+				if !wall {
+					model.add(origin, cuboid([44.0, 0.0], [-1.0, -30.0, -1.0], [2.0, 42.0, 2.0]));
+				}
+				model.add(origin, cuboid([0.0, 42.0], [-10.0, -32.0, -1.0], [20.0, 2.0, 2.0]));
+				// the base colour is the first of the flag's layers
+				model.add(origin, banner_flag());
+				model.set_texture("pattern", texture("banner/base"));
+				model.add_layer(origin, banner_flag(), "pattern", dyeTints[color]);
+				model.build()
+			},
+			Self::ShulkerBox(color) => {
+				let name = match color {
+					Some(color) => format!("shulker/shulker_{}", dyeColors[color]),
+					None => "shulker/shulker".to_owned(),
+				};
+				let mut model = EntityModel::new(id, texture(&name), 64.0);
+				// slightly smaller than the block so as not to fight with its neighbours
+				let origin = Mat4::from_translation(Vec3::splat(0.5)) *
+					Mat4::from_scale(Vec3::splat(0.9995)) *
+					Mat4::from_scale(Vec3::new(1.0, -1.0, -1.0)) *
+					Mat4::from_translation(Vec3::new(0.0, -1.0, 0.0)) *
+					part([0.0, 24.0, 0.0], Vec3::ZERO);
+				model.add(origin, cuboid([0.0, 28.0], [-8.0, -8.0, -8.0], [16.0, 8.0, 16.0]));
+				model.add(origin, cuboid([0.0, 0.0], [-8.0, -16.0, -8.0], [16.0, 12.0, 16.0]));
+				model.build()
+			},
+			Self::Skull { skull, wall } => {
+				let (_, _, name, hat) = skulls[skull];
+				let size = if hat { 64.0 } else { 32.0 };
+				let texture = ResourceLocation::new("minecraft", name);
+				let mut model = EntityModel::with_size(id, texture, vec2(64.0, size));
+				let origin = if wall {
+					Vec3::new(0.5, 0.25, 0.25)
+				} else {
+					Vec3::new(0.5, 0.0, 0.5)
+				};
+				let origin = Mat4::from_translation(origin) *
+					Mat4::from_scale(Vec3::new(-1.0, -1.0, 1.0));
+				let head = cuboid([0.0, 0.0], [-4.0, -8.0, -4.0], [8.0; 3]);
+				model.add(origin, head);
+				if hat {
+					let hat = Cuboid {
+						texOffset: [32.0, 0.0],
+						grow: 0.25,
+						..head
+					};
+					model.add(origin, hat);
+				}
+				model.build()
+			},
+			Self::Bell => {
+				let mut model = EntityModel::new(id, texture("bell/bell_body"), 32.0);
+				let body = part([8.0, 12.0, 8.0], Vec3::ZERO);
+				model.add(body, cuboid([0.0, 0.0], [-3.0, -6.0, -3.0], [6.0, 7.0, 6.0]));
+				let base = body * part([-8.0, -12.0, -8.0], Vec3::ZERO);
+				model.add(base, cuboid([0.0, 13.0], [4.0; 3], [8.0, 2.0, 8.0]));
+				model.build()
+			},
+		}
+	}
+}
+
+/// Gives the blocks drawn by [`add_models`] the properties their models depend on, which their
+/// blockstate JSONs don't mention.
+pub fn add_properties(blockstates: &mut BlockStateCache) {
+	let blocks: Vec<_> = blockstates.blocks().collect();
+	for block in blocks {
+		if let Some(entity) = EntityBlock::of(block) {
+			blockstates.add_properties(block, entity.properties());
+		}
+	}
+}
+
+/// Builds models for the blocks vanilla draws with block entity renderers: chests, beds, signs,
+/// banners, shulker boxes, mob heads and bells. Their models are put in place of the empty JSON
+/// models of their states, except for bells, whose bell is drawn along with its frame.
+pub fn add_models(
+	blockstates: &BlockStateCache,
+	models: &mut ModelCache,
+	statemap: &mut HashMap<BlockState, Vec<Vec<BlockStateModel>>>,
+) {
+	let mut banners = false;
+	for state in blockstates.states() {
+		let entity = match EntityBlock::of(state.block_name()) {
+			Some(v) => v,
+			None => continue,
+		};
+		let (name, xRotation, yRotation) = match entity.variant(state) {
+			Some(v) => v,
+			None => continue,
+		};
+		let id = ResourceLocation::new("cuview", &format!("entity/{name}"));
+		models.entry(id).or_insert_with(|| entity.model(id, state));
+		let variant = BlockStateModel {
+			model: id,
+			xRotation: Some(xRotation),
+			yRotation: Some(yRotation),
+			uvlock: None,
+			weight: None,
+		};
+		if entity == EntityBlock::Bell {
+			statemap.entry(state).or_default().push(vec![variant]);
+		} else {
+			statemap.insert(state, vec![vec![variant]]);
+		}
+		banners |= matches!(entity, EntityBlock::Banner { .. });
+	}
+
+	if !banners {
+		return;
+	}
+	for wall in [false, true] {
+		for &(pattern, _) in bannerPatterns {
+			for (color, &tint) in dyeTints.iter().enumerate() {
+				let id = banner_layer_id(pattern, color, wall);
+				let texture = format!("entity/banner/{pattern}");
+				let texture = ResourceLocation::new("minecraft", &texture);
+				let mut model = EntityModel::new(id, texture, 64.0);
+				model.add_layer(banner_origin(wall), banner_flag(), "texture", tint);
+				models.insert(id, model.build());
+			}
+		}
+	}
+}
+
+/// Returns the models drawn over those of `state` for the data of its block entity, i.e. the
+/// patterns of banners.
+pub fn instance_models(state: BlockState, entity: &BlockEntity) -> Vec<BlockStateModel> {
+	let (block, wall) = match EntityBlock::of(state.block_name()) {
+		Some(block @ EntityBlock::Banner { wall, .. }) => (block, wall),
+		_ => return vec![],
+	};
+	let (_, _, yRotation) = match block.variant(state) {
+		Some(v) => v,
+		None => return vec![],
+	};
+	entity
+		.bannerPatterns
+		.iter()
+		.filter_map(|layer| {
+			let pattern = layer.pattern.strip_prefix("minecraft:").unwrap_or(&layer.pattern);
+			let &(name, _) = bannerPatterns
+				.iter()
+				.find(|&&(name, code)| name == pattern || code == pattern)?;
+			Some(BlockStateModel {
+				model: banner_layer_id(name, layer.color as usize, wall),
+				xRotation: None,
+				yRotation: Some(yRotation),
+				uvlock: None,
+				weight: None,
+			})
+		})
+		.collect()
+}
+
+fn banner_layer_id(pattern: &str, color: usize, wall: bool) -> ResourceLocation {
+	let kind = if wall { "wall_banner" } else { "banner" };
+	let color = dyeColors.get(color).copied().unwrap_or("unknown");
+	ResourceLocation::new("cuview", &format!("entity/{kind}_pattern/{pattern}/{color}"))
+}
+
+/// Vanilla's `Direction.toYRot` for the `facing` of `state`, the angle block entity renderers turn
+/// their models by.
+fn facing_rotation(state: BlockState) -> Option<f32> {
+	let angle = match state.get_property("facing")? {
+		"south" => 0.0,
+		"west" => 90.0,
+		"north" => 180.0,
+		"east" => 270.0,
+		_ => return None,
+	};
+	Some(angle)
+}
+
+/// Where sign models are placed before being turned, either standing in the middle of the block or
+/// hanging on its northern side.
+fn sign_origin(wall: bool) -> Mat4 {
+	let offset = if wall {
+		Vec3::new(0.0, -0.3125, -0.4375)
+	} else {
+		Vec3::ZERO
+	};
+	Mat4::from_translation(Vec3::splat(0.5) + offset) *
+		Mat4::from_scale(Vec3::new(2.0, -2.0, -2.0) / 3.0)
+}
+
+/// Like [`sign_origin`], but with wall banners hanging lower down, as they reach below their
+/// block.
+fn banner_origin(wall: bool) -> Mat4 {
+	if wall {
+		Mat4::from_translation(Vec3::new(0.0, -0.5 - 1.0 / 6.0, 0.0)) * sign_origin(wall)
+	} else {
+		sign_origin(wall)
+	}
+}
+
+fn banner_flag() -> Cuboid {
+	cuboid([0.0, 0.0], [-10.0, -32.0, -2.0], [20.0, 40.0, 1.0])
+}
+
+/// Placement of a model part relative to its parent, `offset` pixels away and turned by
+/// `rotation` radians about Z, Y and then X, like vanilla's `ModelPart.translateAndRotate`.
+fn part(offset: [f32; 3], rotation: Vec3) -> Mat4 {
+	Mat4::from_translation(Vec3::from(offset) / 16.0) *
+		Mat4::from_rotation_z(rotation.z) *
+		Mat4::from_rotation_y(rotation.y) *
+		Mat4::from_rotation_x(rotation.x)
+}
+
+/// A box of a vanilla entity model, in pixels.
+#[derive(Clone, Copy, Debug)]
+struct Cuboid {
+	/// Top left of the box's texture layout, in texels
+	texOffset: [f32; 2],
+	from: [f32; 3],
+	size: [f32; 3],
+	/// Pixels added to each side without changing the texture, like vanilla's `CubeDeformation`
+	grow: f32,
+}
+
+fn cuboid(texOffset: [f32; 2], from: [f32; 3], size: [f32; 3]) -> Cuboid {
+	Cuboid {
+		texOffset,
+		from,
+		size,
+		grow: 0.0,
+	}
+}
+
+/// The faces of a box as laid out by vanilla's `ModelPart.Cube`. Each goes round four corners,
+/// given as 1 for the box's maximum along each axis and 0 for its minimum, and is textured with
+/// the rectangle between two of the box's texel columns and rows, as `[u1, v1, u2, v2]` indices.
+const boxFaces: [([[u8; 3]; 4], [usize; 4]); 6] = [
+	([[1, 0, 1], [0, 0, 1], [0, 0, 0], [1, 0, 0]], [1, 0, 2, 1]),
+	([[1, 1, 0], [0, 1, 0], [0, 1, 1], [1, 1, 1]], [2, 1, 3, 0]),
+	([[0, 0, 0], [0, 0, 1], [0, 1, 1], [0, 1, 0]], [0, 1, 1, 2]),
+	([[1, 0, 0], [0, 0, 0], [0, 1, 0], [1, 1, 0]], [1, 1, 2, 2]),
+	([[1, 0, 1], [1, 0, 0], [1, 1, 0], [1, 1, 1]], [2, 1, 4, 2]),
+	([[0, 0, 1], [1, 0, 1], [1, 1, 1], [0, 1, 1]], [4, 1, 5, 2]),
+];
+
+/// Builds a [`Model`] out of [`Cuboid`]s.
+struct EntityModel {
+	id: ResourceLocation,
+	/// In texels, which every texture of the model is assumed to have
+	textureSize: Vec2,
+	textureSlots: BTreeMap<IString, Texture>,
+	faces: Vec<Face>,
+}
+
+impl EntityModel {
+	/// A model whose faces default to a square `texture`.
+	fn new(id: ResourceLocation, texture: ResourceLocation, textureSize: f32) -> Self {
+		Self::with_size(id, texture, Vec2::splat(textureSize))
+	}
+
+	fn with_size(id: ResourceLocation, texture: ResourceLocation, textureSize: Vec2) -> Self {
+		let mut res = Self {
+			id,
+			textureSize,
+			textureSlots: BTreeMap::new(),
+			faces: vec![],
+		};
+		res.set_texture("texture", texture);
+		res.set_texture("particle", texture);
+		res
+	}
+
+	fn set_texture(&mut self, slot: &str, texture: ResourceLocation) {
+		self.textureSlots.insert(slot.into(), Texture::Asset(texture));
+	}
+
+	/// Adds the faces of `cuboid`, placed within the block by `pose`.
+	fn add(&mut self, pose: Mat4, cuboid: Cuboid) {
+		self.add_layer(pose, cuboid, "texture", tint::untinted);
+	}
+
+	/// Adds the faces of `cuboid` textured from `slot` and multiplied by `color`.
+	fn add_layer(&mut self, pose: Mat4, cuboid: Cuboid, slot: &str, color: u32) {
+		let Cuboid {
+			texOffset: [u, v],
+			from,
+			size,
+			grow,
+		} = cuboid;
+		let [dx, dy, dz] = size;
+		let mins = Vec3::from(from) - grow;
+		let maxs = Vec3::from(from) + Vec3::from(size) + grow;
+		let us = [
+			u,
+			u + dz,
+			u + dz + dx,
+			u + dz + dx * 2.0,
+			u + dz * 2.0 + dx,
+			u + (dz + dx) * 2.0,
+		];
+		let vs = [v, v + dz, v + dz + dy];
+
+		for (corners, [u1, v1, u2, v2]) in boxFaces {
+			let corners = corners.map(|[x, y, z]| {
+				Vec3::select(BVec3::new(x != 0, y != 0, z != 0), maxs, mins)
+			});
+			// vanilla's first corner takes the right of the rectangle, then goes anticlockwise
+			let uvs = [
+				vec2(us[u2], vs[v1]),
+				vec2(us[u1], vs[v1]),
+				vec2(us[u1], vs[v2]),
+				vec2(us[u2], vs[v2]),
+			];
+			let outwards = corners.iter().sum::<Vec3>() / 4.0 - (mins + maxs) / 2.0;
+			let mut face = Face {
+				verts: [Vertex {
+					pos: [0.0; 3],
+					uv: [0.0; 2],
+				}; 4],
+				direction: Direction::nearest(pose.transform_vector3(outwards)),
+				cullface: None,
+				texture: Texture::Slot(slot.into()),
+				tintIndex: None,
+				shade: true,
+				color,
+			};
+			// faces are triangle strips rather than going round
+			for (vertex, corner) in [0, 1, 3, 2].into_iter().enumerate() {
+				face.verts[vertex].pos = pose.transform_point3(corners[corner] / 16.0).into();
+				face.set_texture_uv(vertex, uvs[corner] / self.textureSize);
+			}
+			self.faces.push(face);
+		}
+	}
+
+	fn build(self) -> Model {
+		Model {
+			id: self.id,
+			parent: None,
+			textureSlots: self.textureSlots,
+			faces: self.faces,
+			// vanilla lights block entities as a whole
+			ambientOcclusion: false,
+		}
+	}
+}
+
+#[test]
+fn test_entity_models() {
+	use crate::types::blockstate::BlockStateBuilder;
+
+	let state = |block: &str, props: &str| {
+		BlockStateBuilder::from_variants_model(block.into(), props).build()
+	};
+	let bounds = |model: &Model| {
+		let corners = model.faces.iter().flat_map(|face| face.verts.map(|v| Vec3::from(v.pos)));
+		let cube = super::model::Cube::from_points(corners);
+		((cube.mins * 16.0).round(), (cube.maxs * 16.0).round())
+	};
+
+	// a chest facing north is a single chest turned around
+	let chest = state("chest", "facing=north,type=single");
+	let block = EntityBlock::of(chest.block_name()).unwrap();
+	assert_eq!(block.variant(chest), Some(("chest".to_owned(), 0.0, 180.0)));
+	let model = block.model("cuview:entity/chest".into(), chest);
+	assert_eq!(model.texture("texture"), "entity/chest/normal".into());
+	// with its lock sticking out of the southern side
+	assert_eq!(bounds(&model), (Vec3::new(1.0, 0.0, 1.0), Vec3::new(15.0, 14.0, 16.0)));
+
+	// the top of the lid shows the texels right of its underside
+	let top = model
+		.faces
+		.iter()
+		.find(|face| face.direction == Direction::Up && face.verts[0].pos[1] * 16.0 > 13.9)
+		.unwrap();
+	let texel = |vertex| (top.texture_uv(vertex) * 64.0).round();
+	let corner = (0 .. 4).find(|&i| top.verts[i].pos == [1.0 / 16.0, 14.0 / 16.0, 1.0 / 16.0]);
+	assert_eq!(texel(corner.unwrap()), vec2(28.0, 14.0));
+
+	// the base colour of a wall banner hangs down below its block
+	let banner = state("red_wall_banner", "facing=east");
+	let block = EntityBlock::of(banner.block_name()).unwrap();
+	assert_eq!(block, EntityBlock::Banner { color: 14, wall: true });
+	assert_eq!(block.variant(banner), Some(("red_wall_banner".to_owned(), 0.0, 270.0)));
+	let model = block.model("cuview:entity/red_wall_banner".into(), banner);
+	let flag = model.faces.last().unwrap();
+	assert_eq!(flag.color, 0xB02E26);
+	assert!(bounds(&model).0.y < 0.0);
+
+	assert_eq!(EntityBlock::of("oak_hanging_sign".into()), None);
+	assert_eq!(EntityBlock::of("dragon_head".into()), None);
+}
+
+#[test]
+fn test_instance_models() {
+	use crate::types::blockstate::BlockStateBuilder;
+	use crate::world::BannerPattern;
+
+	let banner =
+		BlockStateBuilder::from_variants_model("white_banner".into(), "rotation=4").build();
+	let entity = BlockEntity {
+		id: "banner".into(),
+		bannerPatterns: vec![
+			BannerPattern {
+				pattern: "bs".to_owned(),
+				color: 14,
+			},
+			BannerPattern {
+				pattern: "minecraft:creeper".to_owned(),
+				color: 15,
+			},
+			BannerPattern {
+				pattern: "unknown".to_owned(),
+				color: 0,
+			},
+		],
+	};
+	let layers = instance_models(banner, &entity);
+	let ids: Vec<_> = layers.iter().map(|v| v.model.to_string()).collect();
+	assert_eq!(
+		ids,
+		[
+			"cuview:entity/banner_pattern/stripe_bottom/red",
+			"cuview:entity/banner_pattern/creeper/black",
+		]
+	);
+	assert!(layers.iter().all(|v| v.yRotation == Some(90.0)));
+
+	let chest = BlockStateBuilder::from_variants_model("chest".into(), "facing=north").build();
+	assert!(instance_models(chest, &entity).is_empty());
+}
@@ -0,0 +1,414 @@
+use std::collections::BTreeMap;
+
+use glam::{vec2, IVec3, Vec2, Vec3};
+
+use super::model::{Direction, Face, Model, ModelCache, Texture, Vertex};
+use super::texture::Cartographer;
+use crate::types::blockstate::BlockState;
+use crate::types::ResourceLocation;
+
+/// Blocks which are always full of water, without a `waterlogged` property to say so
+const underwaterBlocks: &[&str] =
+	&["bubble_column", "kelp", "kelp_plant", "seagrass", "tall_seagrass"];
+
+/// How far fluid faces are pulled into their block, as vanilla does to keep them from fighting
+/// with the faces of neighbouring blocks
+const inset: f32 = 0.001;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Fluid {
+	Water,
+	Lava,
+}
+
+impl Fluid {
+	pub const all: [Self; 2] = [Self::Water, Self::Lava];
+
+	fn name(self) -> &'static str {
+		match self {
+			Self::Water => "water",
+			Self::Lava => "lava",
+		}
+	}
+
+	/// Id of the model [`add_models`] gives the fluid's textures.
+	pub fn model_id(self) -> ResourceLocation {
+		ResourceLocation::new("cuview", &format!("fluid/{}", self.name()))
+	}
+}
+
+/// The fluid in a block, like vanilla's `FluidState`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FluidState {
+	pub fluid: Fluid,
+	/// From 1 to 8, where 8 is a source or falling fluid
+	pub amount: u8,
+}
+
+impl FluidState {
+	/// Returns the fluid in `state`, which must not have been normalized as that strips the
+	/// `level` and `waterlogged` properties.
+	pub fn of(state: BlockState) -> Option<Self> {
+		let block = state.block_name();
+		let vanilla = block.modid.as_str() == "minecraft";
+		let fluid = match block.name.as_str() {
+			"water" if vanilla => Fluid::Water,
+			"lava" if vanilla => Fluid::Lava,
+			name => {
+				let underwater = vanilla && underwaterBlocks.contains(&name);
+				if underwater || state.get_property("waterlogged") == Some("true") {
+					return Some(Self {
+						fluid: Fluid::Water,
+						amount: 8,
+					});
+				}
+				return None;
+			},
+		};
+		// levels of 8 and above fall straight down, and are as full as a source
+		let level = state
+			.get_property("level")
+			.and_then(|v| v.parse::<u8>().ok())
+			.unwrap_or(0);
+		let amount = match level {
+			1 ..= 7 => 8 - level,
+			_ => 8,
+		};
+		Some(Self { fluid, amount })
+	}
+
+	/// Height of the fluid's surface when there's none of it above, like vanilla's
+	/// `FluidState.getOwnHeight`.
+	pub fn own_height(self) -> f32 {
+		self.amount as f32 / 9.0
+	}
+}
+
+/// What the fluid mesher needs to know about a block around a fluid.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FluidNeighbour {
+	pub fluid: Option<FluidState>,
+
+	/// Whether the block is a full, opaque cube
+	pub solid: bool,
+}
+
+/// Atlas ids of a fluid's textures, as [`super::texture::TextureId::packed`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FluidTextures {
+	pub still: u32,
+	pub flow: u32,
+}
+
+impl FluidTextures {
+	/// Looks up the textures of the model [`add_models`] gives `fluid`, if it has been added.
+	pub fn find(fluid: Fluid, models: &ModelCache, cartographer: &Cartographer) -> Option<Self> {
+		let model = models.get(&fluid.model_id())?;
+		let id = |slot| cartographer.id_for_texture(model.texture(slot)).map(|id| id.packed());
+		Some(Self {
+			still: id("still")?,
+			flow: id("flow")?,
+		})
+	}
+
+	/// The id of the texture in `slot` of a face from [`fluid_faces`].
+	pub fn get(&self, texture: Texture) -> u32 {
+		match texture {
+			Texture::Slot(slot) if slot.as_str() == "flow" => self.flow,
+			_ => self.still,
+		}
+	}
+}
+
+/// Adds a model for each fluid which has no faces, and only names its still and flowing textures,
+/// so that they end up in the atlas.
+pub fn add_models(models: &mut ModelCache) {
+	for fluid in Fluid::all {
+		let texture = |kind: &str| {
+			let loc = ResourceLocation::new("minecraft", &format!("block/{}_{kind}", fluid.name()));
+			Texture::Asset(loc)
+		};
+		let textureSlots = BTreeMap::from([
+			("still".into(), texture("still")),
+			("flow".into(), texture("flow")),
+			("particle".into(), texture("still")),
+		]);
+		models.insert(
+			fluid.model_id(),
+			Model {
+				id: fluid.model_id(),
+				parent: None,
+				textureSlots,
+				faces: vec![],
+				ambientOcclusion: false,
+			},
+		);
+	}
+}
+
+/// Builds the surface of `state` in a block, the way vanilla's `LiquidBlockRenderer` does. The
+/// blocks around it are looked up by their offset with `neighbour`. Faces are left out against
+/// more of the same fluid and against solid blocks, and multiplied by `color`. Their textures are
+/// the `still` and `flow` slots of [`FluidTextures`].
+pub fn fluid_faces(
+	state: FluidState,
+	color: u32,
+	neighbour: impl Fn(IVec3) -> FluidNeighbour,
+) -> Vec<Face> {
+	let fluid = state.fluid;
+	let same = |offset: IVec3| neighbour(offset).fluid.is_some_and(|v| v.fluid == fluid);
+	let [nw, sw, se, ne] = corner_heights(fluid, &neighbour);
+	let mut faces = vec![];
+	let face = |verts: [(Vec3, Vec2); 4], direction: Direction, flowing: bool| {
+		let mut face = Face {
+			verts: [Vertex {
+				pos: [0.0; 3],
+				uv: [0.0; 2],
+			}; 4],
+			direction,
+			// lit by the neighbour each face looks out onto
+			cullface: Some(direction),
+			texture: Texture::Slot(if flowing { "flow" } else { "still" }.into()),
+			tintIndex: None,
+			shade: true,
+			color,
+		};
+		// vanilla's quads go round their corners, while faces are triangle strips
+		for (vertex, corner) in [0, 1, 3, 2].into_iter().enumerate() {
+			let (pos, uv) = verts[corner];
+			face.verts[vertex].pos = pos.into();
+			face.set_texture_uv(vertex, uv / 16.0);
+		}
+		face
+	};
+
+	let up = neighbour(IVec3::Y);
+	let covered = nw.min(sw).min(se).min(ne) >= 1.0 && up.solid;
+	if !same(IVec3::Y) && !covered {
+		let flow = flow(state, &neighbour);
+		// the flowing texture is twice the size of the still one, and turned to face downstream
+		let uvs = if flow == Vec2::ZERO {
+			[vec2(0.0, 0.0), vec2(0.0, 16.0), vec2(16.0, 16.0), vec2(16.0, 0.0)]
+		} else {
+			let angle = flow.y.atan2(flow.x) - std::f32::consts::FRAC_PI_2;
+			let (s, c) = (angle.sin() * 4.0, angle.cos() * 4.0);
+			[
+				vec2(8.0 - c - s, 8.0 - c + s),
+				vec2(8.0 - c + s, 8.0 + c + s),
+				vec2(8.0 + c + s, 8.0 + c - s),
+				vec2(8.0 + c - s, 8.0 - c - s),
+			]
+		};
+		let verts = [
+			(Vec3::new(0.0, nw - inset, 0.0), uvs[0]),
+			(Vec3::new(0.0, sw - inset, 1.0), uvs[1]),
+			(Vec3::new(1.0, se - inset, 1.0), uvs[2]),
+			(Vec3::new(1.0, ne - inset, 0.0), uvs[3]),
+		];
+		faces.push(face(verts, Direction::Up, flow != Vec2::ZERO));
+	}
+
+	let down = neighbour(IVec3::NEG_Y);
+	let bottom = if !same(IVec3::NEG_Y) && !down.solid {
+		let verts = [
+			(Vec3::new(0.0, inset, 1.0), vec2(0.0, 16.0)),
+			(Vec3::new(0.0, inset, 0.0), vec2(0.0, 0.0)),
+			(Vec3::new(1.0, inset, 0.0), vec2(16.0, 0.0)),
+			(Vec3::new(1.0, inset, 1.0), vec2(16.0, 16.0)),
+		];
+		faces.push(face(verts, Direction::Down, false));
+		inset
+	} else {
+		0.0
+	};
+
+	// each side as the two ends of its top edge, in vanilla's order, with their heights
+	let sides = [
+		(Direction::North, [0.0, 0.0], [1.0, 0.0], nw, ne),
+		(Direction::South, [1.0, 1.0], [0.0, 1.0], se, sw),
+		(Direction::West, [0.0, 1.0], [0.0, 0.0], sw, nw),
+		(Direction::East, [1.0, 0.0], [1.0, 1.0], ne, se),
+	];
+	for (direction, [x1, z1], [x2, z2], h1, h2) in sides {
+		let offset = direction.normal().as_ivec3();
+		if same(offset) || neighbour(offset).solid {
+			continue;
+		}
+		let pull = -direction.normal() * inset;
+		let verts = [
+			(Vec3::new(x1, h1, z1) + pull, vec2(0.0, (1.0 - h1) * 8.0)),
+			(Vec3::new(x2, h2, z2) + pull, vec2(8.0, (1.0 - h2) * 8.0)),
+			(Vec3::new(x2, bottom, z2) + pull, vec2(8.0, 8.0)),
+			(Vec3::new(x1, bottom, z1) + pull, vec2(0.0, 8.0)),
+		];
+		faces.push(face(verts, direction, true));
+	}
+	faces
+}
+
+/// Heights of the fluid's surface at the north-west, south-west, south-east and north-east
+/// corners of its block, averaged over the blocks around each corner.
+fn corner_heights(fluid: Fluid, neighbour: &impl Fn(IVec3) -> FluidNeighbour) -> [f32; 4] {
+	let height = |offset| height(fluid, neighbour, offset);
+	let own = height(IVec3::ZERO);
+	if own >= 1.0 {
+		return [1.0; 4];
+	}
+	let [north, south, west, east] = [IVec3::NEG_Z, IVec3::Z, IVec3::NEG_X, IVec3::X];
+	let corner = |a: IVec3, b: IVec3| {
+		let (heightA, heightB) = (height(a), height(b));
+		if heightA >= 1.0 || heightB >= 1.0 {
+			return 1.0;
+		}
+		// like vanilla's `calculateAverageHeight`, which weighs nearly full blocks heavily
+		let (mut sum, mut weight) = (0.0, 0.0);
+		let mut add = |height: f32| {
+			if height >= 0.8 {
+				sum += height * 10.0;
+				weight += 10.0;
+			} else if height >= 0.0 {
+				sum += height;
+				weight += 1.0;
+			}
+		};
+		if heightA > 0.0 || heightB > 0.0 {
+			let diagonal = height(a + b);
+			if diagonal >= 1.0 {
+				return 1.0;
+			}
+			add(diagonal);
+		}
+		add(own);
+		add(heightB);
+		add(heightA);
+		sum / weight
+	};
+	[corner(north, west), corner(south, west), corner(south, east), corner(north, east)]
+}
+
+/// Height of the fluid in the block at `offset`, for averaging into corner heights: full under
+/// more of the same fluid, or -1 in a solid block, which leaves it out of the average.
+fn height(fluid: Fluid, neighbour: &impl Fn(IVec3) -> FluidNeighbour, offset: IVec3) -> f32 {
+	let block = neighbour(offset);
+	match block.fluid {
+		Some(state) if state.fluid == fluid => {
+			let above = neighbour(offset + IVec3::Y).fluid;
+			if above.is_some_and(|v| v.fluid == fluid) {
+				1.0
+			} else {
+				state.own_height()
+			}
+		},
+		_ if block.solid => -1.0,
+		_ => 0.0,
+	}
+}
+
+/// Which way the fluid flows across the top of its block, as X and Z. Like vanilla's
+/// `FlowingFluid.getFlow`, it runs towards lower neighbours and off of edges with more of the
+/// fluid below, and is zero on still fluid.
+fn flow(state: FluidState, neighbour: &impl Fn(IVec3) -> FluidNeighbour) -> Vec2 {
+	let mut flow = Vec2::ZERO;
+	for direction in [Direction::North, Direction::South, Direction::West, Direction::East] {
+		let offset = direction.normal().as_ivec3();
+		let block = neighbour(offset);
+		let difference = match block.fluid {
+			Some(other) if other.fluid == state.fluid => state.own_height() - other.own_height(),
+			Some(_) => continue,
+			None if block.solid => continue,
+			None => match neighbour(offset + IVec3::NEG_Y).fluid {
+				Some(below) if below.fluid == state.fluid => {
+					state.own_height() - (below.own_height() - 8.0 / 9.0)
+				},
+				_ => continue,
+			},
+		};
+		flow += vec2(offset.x as f32, offset.z as f32) * difference;
+	}
+	flow
+}
+
+#[test]
+fn test_fluid_state() {
+	use crate::types::blockstate::BlockStateBuilder;
+
+	let state = |block: &str, props: &str| {
+		FluidState::of(BlockStateBuilder::from_variants_model(block.into(), props).build())
+	};
+	let water = |amount| {
+		Some(FluidState {
+			fluid: Fluid::Water,
+			amount,
+		})
+	};
+	assert_eq!(state("water", "level=0"), water(8));
+	assert_eq!(state("water", "level=3"), water(5));
+	assert_eq!(state("water", "level=9"), water(8));
+	assert_eq!(state("lava", "level=7").unwrap().fluid, Fluid::Lava);
+	assert_eq!(state("oak_stairs", "facing=east,waterlogged=true"), water(8));
+	assert_eq!(state("oak_stairs", "facing=east,waterlogged=false"), None);
+	assert_eq!(FluidState::of(BlockState::stateless("kelp_plant".into())), water(8));
+	assert_eq!(FluidState::of(BlockState::stateless("stone".into())), None);
+}
+
+#[test]
+fn test_fluid_faces() {
+	use std::collections::HashMap;
+
+	let water = |amount| FluidNeighbour {
+		fluid: Some(FluidState {
+			fluid: Fluid::Water,
+			amount,
+		}),
+		solid: false,
+	};
+	let stone = FluidNeighbour {
+		fluid: None,
+		solid: true,
+	};
+	let top = |faces: &[Face]| {
+		let top = faces.iter().find(|face| face.direction == Direction::Up).copied();
+		top.map(|face| face.verts.map(|v| Vec3::from(v.pos)))
+	};
+
+	// a source on stone, with flowing water to the east
+	let mut blocks = HashMap::from([
+		(IVec3::ZERO, water(8)),
+		(IVec3::NEG_Y, stone),
+		(IVec3::X, water(7)),
+	]);
+	let neighbour = |blocks: &HashMap<IVec3, FluidNeighbour>| {
+		let blocks = blocks.clone();
+		move |offset| blocks.get(&offset).copied().unwrap_or_default()
+	};
+	let faces = fluid_faces(water(8).fluid.unwrap(), 0x3F76E4, neighbour(&blocks));
+	// no bottom against the stone, nor an eastern side against more water
+	let directions: Vec<_> = faces.iter().map(|face| face.direction).collect();
+	assert_eq!(
+		directions,
+		[Direction::Up, Direction::North, Direction::South, Direction::West]
+	);
+	assert!(faces.iter().all(|face| face.color == 0x3F76E4));
+
+	// corners sink towards the air, less so where there's more water; nearly full blocks count
+	// for ten times as much
+	let heights = top(&faces).unwrap().map(|v| v.y + inset);
+	let source = 8.0 / 9.0;
+	let west = source * 10.0 / 12.0;
+	let east = (source * 10.0 + 7.0 / 9.0) / 13.0;
+	// the face is a triangle strip, going north-west, south-west, north-east, south-east
+	for (height, expected) in heights.into_iter().zip([west, west, east, east]) {
+		assert!((height - expected).abs() < 1e-5, "{heights:?}");
+	}
+	// and it flows downhill, away from the source
+	let flow = flow(water(8).fluid.unwrap(), &neighbour(&blocks));
+	assert!(flow.abs_diff_eq(vec2(1.0 / 9.0, 0.0), 1e-6));
+	assert!(matches!(faces[0].texture, Texture::Slot(slot) if slot.as_str() == "flow"));
+
+	// under more water the block is full, and has no top
+	blocks.insert(IVec3::Y, water(8));
+	let faces = fluid_faces(water(8).fluid.unwrap(), 0x3F76E4, neighbour(&blocks));
+	assert_eq!(top(&faces), None);
+	let north = faces.iter().find(|face| face.direction == Direction::North).unwrap();
+	assert!(north.verts.iter().any(|v| v.pos[1] == 1.0));
+}
@@ -0,0 +1,680 @@
+use std::borrow::Cow;
+use std::f32::consts::TAU;
+use std::mem::size_of;
+
+use anyhow::anyhow;
+use glam::{vec2, UVec2, Vec2};
+use wgpu::util::{DeviceExt, DrawIndirect};
+
+use super::model::{FullVertex, GeometryBuffer};
+use super::texture::{Cartographer, Image, TextureFrame};
+use super::{blocksPerSection, section_index, Camera, ChunkMesh, RenderSettings};
+use crate::types::ChunkPos;
+
+/// The wgpu side of [`super::Renderer`]: a device with the block textures and geometry uploaded,
+/// and targets to render into.
+pub(super) struct GpuPipeline {
+	device: wgpu::Device,
+	queue: wgpu::Queue,
+	pipeline: wgpu::RenderPipeline,
+	/// Like `pipeline`, without depth writes
+	fluidPipeline: wgpu::RenderPipeline,
+	bindGroup: wgpu::BindGroup,
+	cameraBuffer: wgpu::Buffer,
+	blockTints: wgpu::Buffer,
+	blockLights: wgpu::Buffer,
+	blockOcclusion: wgpu::Buffer,
+	textureFrames: wgpu::Buffer,
+	blockModelsBuffer: wgpu::Buffer,
+	frameSize: wgpu::Extent3d,
+	frameTexture: wgpu::Texture,
+	/// Absent without MSAA
+	frameTextureMultisample: Option<wgpu::Texture>,
+	frameDepthTexture: wgpu::Texture,
+	frameCopyBuffer: wgpu::Buffer,
+	frameCopyBufferSize: ImgBufferSize,
+}
+
+const frameFormat: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+impl GpuPipeline {
+	pub fn new(
+		geometry: &GeometryBuffer,
+		cartographer: &Cartographer,
+		blockTextureLayers: &[Image],
+		settings: &RenderSettings,
+	) -> anyhow::Result<Self> {
+		pollster::block_on(Self::new_async(
+			geometry,
+			cartographer,
+			blockTextureLayers,
+			settings,
+		))
+	}
+
+	async fn new_async(
+		geometry: &GeometryBuffer,
+		cartographer: &Cartographer,
+		blockTextureLayers: &[Image],
+		settings: &RenderSettings,
+	) -> anyhow::Result<Self> {
+		let instance = wgpu::Instance::new(wgpu::Backends::all());
+		let adapter = instance
+			.request_adapter(&wgpu::RequestAdapterOptions {
+				power_preference: wgpu::PowerPreference::default(),
+				force_fallback_adapter: false,
+				compatible_surface: None,
+			})
+			.await
+			.ok_or_else(|| anyhow!("no suitable graphics adapter found"))?;
+		let (device, queue) = adapter
+			.request_device(
+				&wgpu::DeviceDescriptor {
+					label: None,
+					features: wgpu::Features::PUSH_CONSTANTS |
+						wgpu::Features::MULTI_DRAW_INDIRECT |
+						wgpu::Features::INDIRECT_FIRST_INSTANCE,
+					limits: wgpu::Limits {
+						max_push_constant_size: 128,
+						max_texture_dimension_2d: 32768,
+						..wgpu::Limits::default()
+					},
+				},
+				None,
+			)
+			.await?;
+
+		let cameraBuffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: None,
+			size: size_of::<[f32; 32]>() as wgpu::BufferAddress,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let sampleCount = settings.sampleCount;
+		let frameSize = wgpu::Extent3d {
+			width: settings.size.x,
+			height: settings.size.y,
+			depth_or_array_layers: 1,
+		};
+		let frameTexture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("frameTexture"),
+			size: frameSize,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: frameFormat,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+		});
+		let frameTextureMultisample = (sampleCount > 1).then(|| {
+			device.create_texture(&wgpu::TextureDescriptor {
+				label: Some("frameTextureMultisample"),
+				size: frameSize,
+				mip_level_count: 1,
+				sample_count: sampleCount,
+				dimension: wgpu::TextureDimension::D2,
+				format: frameFormat,
+				usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+			})
+		});
+		let frameDepthFormat = wgpu::TextureFormat::Depth24Plus;
+		let frameDepthTexture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("frameDepthTexture"),
+			size: frameSize,
+			mip_level_count: 1,
+			sample_count: sampleCount,
+			dimension: wgpu::TextureDimension::D2,
+			format: frameDepthFormat,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+		});
+		let frameCopyBufferSize = ImgBufferSize::new(frameSize);
+		let frameCopyBuffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: None,
+			mapped_at_creation: false,
+			size: (frameCopyBufferSize.bplPadded * frameCopyBufferSize.height)
+				as wgpu::BufferAddress,
+			usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+		});
+
+		let blockTextureSize = wgpu::Extent3d {
+			width: blockTextureLayers[0].size.x,
+			height: blockTextureLayers[0].size.y,
+			depth_or_array_layers: blockTextureLayers.len() as u32,
+		};
+		let blockTexture = device.create_texture(&wgpu::TextureDescriptor {
+			label: None,
+			size: blockTextureSize,
+			mip_level_count: cartographer.mip_levels() + 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: wgpu::TextureFormat::Rgba8Unorm,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+		});
+		let blockTextureView = blockTexture.create_view(&wgpu::TextureViewDescriptor {
+			dimension: Some(wgpu::TextureViewDimension::D2Array),
+			..Default::default()
+		});
+		for (i, layer) in blockTextureLayers.iter().enumerate() {
+			let mips = cartographer.mip_chain(i as u8, layer);
+			for (level, image) in std::iter::once(layer).chain(&mips).enumerate() {
+				let mut dest = blockTexture.as_image_copy();
+				dest.mip_level = level as u32;
+				dest.origin = wgpu::Origin3d {
+					x: 0,
+					y: 0,
+					z: i as u32,
+				};
+				queue.write_texture(
+					dest,
+					bytemuck::cast_slice(&image.pixels),
+					wgpu::ImageDataLayout {
+						offset: 0,
+						bytes_per_row: Some(
+							(image.size.x * size_of::<u32>() as u32).try_into().unwrap(),
+						),
+						rows_per_image: None,
+					},
+					wgpu::Extent3d {
+						width: image.size.x,
+						height: image.size.y,
+						depth_or_array_layers: 1,
+					},
+				);
+			}
+		}
+		let blockTextureSampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			address_mode_u: wgpu::AddressMode::ClampToEdge,
+			address_mode_v: wgpu::AddressMode::ClampToEdge,
+			address_mode_w: wgpu::AddressMode::ClampToEdge,
+			mag_filter: wgpu::FilterMode::Nearest,
+			min_filter: wgpu::FilterMode::Linear,
+			mipmap_filter: wgpu::FilterMode::Linear,
+			..Default::default()
+		});
+		let textureRects = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: None,
+			usage: wgpu::BufferUsages::STORAGE,
+			contents: bytemuck::cast_slice(cartographer.texture_rects()),
+		});
+
+		let layerBases = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: None,
+			usage: wgpu::BufferUsages::STORAGE,
+			contents: bytemuck::cast_slice(cartographer.layer_bases()),
+		});
+		let textureFrames = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: None,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+			contents: bytemuck::cast_slice(&cartographer.frames_at(0)),
+		});
+
+		let perBlockBuffer = || {
+			device.create_buffer(&wgpu::BufferDescriptor {
+				label: None,
+				size: (ChunkPos::sections.count() * blocksPerSection * size_of::<u32>())
+					as wgpu::BufferAddress,
+				usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+				mapped_at_creation: false,
+			})
+		};
+		let blockTints = perBlockBuffer();
+		let blockLights = perBlockBuffer();
+		let blockOcclusion = perBlockBuffer();
+
+		let blockModelsBuffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: None,
+			usage: wgpu::BufferUsages::VERTEX,
+			contents: bytemuck::cast_slice(&geometry.vertices),
+		});
+
+		let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+			label: None,
+			source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../shaders/main.wgsl"))),
+		});
+		let bindGroupLayout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: None,
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: wgpu::BufferSize::new(
+							size_of::<[f32; 32]>() as wgpu::BufferAddress
+						),
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 1,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Storage { read_only: true },
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 2,
+					visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						sample_type: wgpu::TextureSampleType::Float { filterable: true },
+						view_dimension: wgpu::TextureViewDimension::D2Array,
+						multisampled: false,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 3,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 4,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Storage { read_only: true },
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 5,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Storage { read_only: true },
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 6,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Storage { read_only: true },
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 7,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Storage { read_only: true },
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 8,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Storage { read_only: true },
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+		let bindGroup = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: None,
+			layout: &bindGroupLayout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: cameraBuffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: textureRects.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(&blockTextureView),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::Sampler(&blockTextureSampler),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: blockTints.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 5,
+					resource: blockLights.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 6,
+					resource: blockOcclusion.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 7,
+					resource: layerBases.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 8,
+					resource: textureFrames.as_entire_binding(),
+				},
+			],
+		});
+		let pipelineLayout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: None,
+			bind_group_layouts: &[&bindGroupLayout],
+			push_constant_ranges: &[
+				wgpu::PushConstantRange {
+					range: 0 .. 16,
+					stages: wgpu::ShaderStages::VERTEX,
+				},
+			],
+		});
+		let createPipeline = |depthWrite| {
+			device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+				label: None,
+				layout: Some(&pipelineLayout),
+				vertex: wgpu::VertexState {
+					module: &shader,
+					entry_point: "vsMain",
+					buffers: &[
+						wgpu::VertexBufferLayout {
+							array_stride: size_of::<FullVertex>() as wgpu::BufferAddress,
+							step_mode: wgpu::VertexStepMode::Vertex,
+							attributes: &wgpu::vertex_attr_array![
+								0 => Float32x3,
+								1 => Float32x2,
+								2 => Uint32,
+								3 => Sint32,
+								4 => Float32x3,
+								5 => Uint32,
+								6 => Uint32,
+							],
+						},
+					],
+				},
+				fragment: Some(wgpu::FragmentState {
+					module: &shader,
+					entry_point: "fsMain",
+					targets: &[Some(
+						wgpu::ColorTargetState {
+							format: frameFormat,
+							blend: Some(wgpu::BlendState {
+								color: wgpu::BlendComponent {
+									src_factor: wgpu::BlendFactor::SrcAlpha,
+									dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+									operation: wgpu::BlendOperation::Add,
+								},
+								alpha: wgpu::BlendComponent {
+									src_factor: wgpu::BlendFactor::One,
+									dst_factor: wgpu::BlendFactor::One,
+									operation: wgpu::BlendOperation::Max,
+								},
+							}),
+							write_mask: wgpu::ColorWrites::ALL,
+						},
+					)],
+				}),
+				primitive: wgpu::PrimitiveState {
+					cull_mode: None, // Some(wgpu::Face::Back),
+					..wgpu::PrimitiveState::default()
+				},
+				depth_stencil: Some(wgpu::DepthStencilState {
+					format: frameDepthFormat,
+					depth_write_enabled: depthWrite,
+					// ties go to the later face, as in the rasterizer
+					depth_compare: wgpu::CompareFunction::LessEqual,
+					stencil: wgpu::StencilState::default(),
+					bias: wgpu::DepthBiasState::default(),
+				}),
+				multisample: wgpu::MultisampleState {
+					count: sampleCount,
+					..Default::default()
+				},
+				multiview: None,
+			})
+		};
+		let pipeline = createPipeline(true);
+		// fluids don't hide each other, so every surface in front of the terrain blends over it
+		let fluidPipeline = createPipeline(false);
+
+		Ok(Self {
+			device,
+			queue,
+			pipeline,
+			fluidPipeline,
+			bindGroup,
+			cameraBuffer,
+			blockTints,
+			blockLights,
+			blockOcclusion,
+			textureFrames,
+			blockModelsBuffer,
+			frameSize,
+			frameTexture,
+			frameTextureMultisample,
+			frameDepthTexture,
+			frameCopyBuffer,
+			frameCopyBufferSize,
+		})
+	}
+
+	/// Draws `chunks` with each texture replaced by its frame in `frames`, as laid out by
+	/// [`Cartographer::frames_at`].
+	pub fn render(
+		&self,
+		chunks: &[ChunkMesh],
+		camera: &Camera,
+		frames: &[TextureFrame],
+		clearColor: u32,
+	) -> Image {
+		let Self {
+			device,
+			queue,
+			..
+		} = self;
+
+		queue.write_buffer(&self.textureFrames, 0, bytemuck::cast_slice(frames));
+		queue.write_buffer(
+			&self.cameraBuffer,
+			0,
+			bytemuck::cast_slice(camera.projection.as_ref()),
+		);
+		queue.write_buffer(
+			&self.cameraBuffer,
+			size_of::<[f32; 16]>() as wgpu::BufferAddress,
+			bytemuck::cast_slice(camera.view.as_ref()),
+		);
+
+		let colorView = self.frameTexture.create_view(&Default::default());
+		let multisampleView = self
+			.frameTextureMultisample
+			.as_ref()
+			.map(|tex| tex.create_view(&Default::default()));
+		let depthView = self
+			.frameDepthTexture
+			.create_view(&wgpu::TextureViewDescriptor {
+				aspect: wgpu::TextureAspect::DepthOnly,
+				..Default::default()
+			});
+		let colorAttachment = |load| wgpu::RenderPassColorAttachment {
+			view: multisampleView.as_ref().unwrap_or(&colorView),
+			resolve_target: multisampleView.is_some().then_some(&colorView),
+			ops: wgpu::Operations { load, store: true },
+		};
+		let depthAttachment = |load| wgpu::RenderPassDepthStencilAttachment {
+			view: &depthView,
+			depth_ops: Some(wgpu::Operations { load, store: true }),
+			stencil_ops: None,
+		};
+
+		let [r, g, b, a] = clearColor.to_le_bytes().map(|v| v as f64 / 255.0);
+		let mut encoder = device.create_command_encoder(&Default::default());
+		drop(encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: None,
+			color_attachments: &[Some(colorAttachment(wgpu::LoadOp::Clear(
+				wgpu::Color { r, g, b, a },
+			)))],
+			depth_stencil_attachment: Some(depthAttachment(wgpu::LoadOp::Clear(1.0))),
+		}));
+		queue.submit(Some(encoder.finish()));
+
+		// the per-block buffers only hold one chunk, so each is submitted separately. Fluids go
+		// after every chunk's opaque geometry, so that what's under them shows through wherever it
+		// was drawn from
+		let mut indirectDraws = vec![];
+		for fluid in [false, true] {
+			for chunk in chunks {
+				let fluidVertices;
+				let (sections, vertexBuffer, pipeline) = if fluid {
+					if chunk.fluidSections.is_empty() {
+						continue;
+					}
+					fluidVertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+						label: None,
+						usage: wgpu::BufferUsages::VERTEX,
+						contents: bytemuck::cast_slice(&chunk.fluidVertices),
+					});
+					(&chunk.fluidSections, &fluidVertices, &self.fluidPipeline)
+				} else {
+					(&chunk.sections, &self.blockModelsBuffer, &self.pipeline)
+				};
+				queue.write_buffer(&self.blockTints, 0, bytemuck::cast_slice(&chunk.tints));
+				queue.write_buffer(&self.blockLights, 0, bytemuck::cast_slice(&chunk.lights));
+				let occlusion = bytemuck::cast_slice(&chunk.occlusion);
+				queue.write_buffer(&self.blockOcclusion, 0, occlusion);
+
+				let mut encoder = device.create_command_encoder(&Default::default());
+				for (sectionY, draws) in sections {
+					indirectDraws.clear();
+					for draw in draws {
+						// pack rotations into the unused upper 20 bits of instance id
+						let rot = vec2(draw.xRotation.to_radians(), draw.yRotation.to_radians());
+						let rotTurns = Vec2::from((rot / TAU).as_ref().map(|v| v.rem_euclid(1.0)));
+						let rotDiscrete = (rotTurns * 1024.0).as_uvec2();
+						let rotPacked = (rotDiscrete.y & 1023) << 10 | rotDiscrete.x & 1023;
+
+						let instance = rotPacked << 12 | draw.pos.index_in_section() as u32;
+						indirectDraws.extend(
+							DrawIndirect {
+								base_vertex: draw.vertices.start as u32,
+								vertex_count: draw.vertices.len() as u32,
+								base_instance: instance,
+								instance_count: 1,
+							}
+							.as_bytes(),
+						);
+					}
+					if indirectDraws.is_empty() {
+						continue;
+					}
+
+					let indirectBuffer =
+						device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+							label: None,
+							usage: wgpu::BufferUsages::INDIRECT,
+							contents: &indirectDraws,
+						});
+					let tintBase = (section_index(*sectionY) * blocksPerSection) as u32;
+
+					let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+						label: None,
+						color_attachments: &[Some(colorAttachment(wgpu::LoadOp::Load))],
+						depth_stencil_attachment: Some(depthAttachment(wgpu::LoadOp::Load)),
+					});
+					pass.set_pipeline(pipeline);
+					pass.set_bind_group(0, &self.bindGroup, &[]);
+					pass.set_vertex_buffer(0, vertexBuffer.slice(..));
+					pass.set_push_constants(
+						wgpu::ShaderStages::VERTEX,
+						0,
+						bytemuck::bytes_of(&(*sectionY as i32)),
+					);
+					pass.set_push_constants(
+						wgpu::ShaderStages::VERTEX,
+						4,
+						bytemuck::bytes_of(&tintBase),
+					);
+					pass.set_push_constants(
+						wgpu::ShaderStages::VERTEX,
+						8,
+						bytemuck::cast_slice(&[chunk.pos.x, chunk.pos.z]),
+					);
+					pass.multi_draw_indirect(
+						&indirectBuffer,
+						0,
+						(indirectDraws.len() / size_of::<DrawIndirect>()) as u32,
+					);
+				}
+				queue.submit(Some(encoder.finish()));
+			}
+		}
+
+		let mut encoder = device.create_command_encoder(&Default::default());
+		encoder.copy_texture_to_buffer(
+			self.frameTexture.as_image_copy(),
+			wgpu::ImageCopyBuffer {
+				buffer: &self.frameCopyBuffer,
+				layout: wgpu::ImageDataLayout {
+					offset: 0,
+					bytes_per_row: Some(
+						(self.frameCopyBufferSize.bplPadded as u32)
+							.try_into()
+							.unwrap(),
+					),
+					rows_per_image: None,
+				},
+			},
+			self.frameSize,
+		);
+		let submission = queue.submit(Some(encoder.finish()));
+
+		let slice = self.frameCopyBuffer.slice(..);
+		slice.map_async(wgpu::MapMode::Read, |_| {});
+		if !device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission)) {
+			std::thread::sleep(std::time::Duration::from_secs_f32(1.5));
+		}
+
+		let size = self.frameCopyBufferSize;
+		let padded = slice.get_mapped_range();
+		let mut pixels = Vec::with_capacity(size.width * size.height);
+		for row in padded.chunks(size.bplPadded) {
+			pixels.extend_from_slice(bytemuck::cast_slice(&row[0 .. size.bplUnpadded]));
+		}
+		drop(padded);
+		self.frameCopyBuffer.unmap();
+
+		Image {
+			size: UVec2::new(self.frameSize.width, self.frameSize.height),
+			pixels,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ImgBufferSize {
+	pub width: usize,
+	pub height: usize,
+	pub bplUnpadded: usize,
+	pub bplPadded: usize,
+}
+
+impl ImgBufferSize {
+	pub fn new(extent: wgpu::Extent3d) -> Self {
+		let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+		let bpl = extent.width * std::mem::size_of::<u32>() as u32;
+		let padding = (align - bpl % align) % align;
+		Self {
+			width: extent.width as usize,
+			height: extent.height as usize,
+			bplUnpadded: bpl as usize,
+			bplPadded: (bpl + padding) as usize,
+		}
+	}
+}
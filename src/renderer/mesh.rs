@@ -0,0 +1,549 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use glam::{IVec3, Mat4, Vec3};
+
+use super::model::{
+	choose_models,
+	variant_rotation,
+	Direction,
+	Face,
+	FullVertex,
+	GeometryBuffer,
+	ModelCache,
+	Texture,
+};
+use super::entity;
+use super::fluid::{fluid_faces, Fluid, FluidNeighbour, FluidState, FluidTextures};
+use super::texture::Cartographer;
+use super::tint;
+use crate::loader::model::BlockStateModel;
+use crate::types::blockstate::{BlockState, BlockStateCache};
+use crate::types::{BlockPos, ChunkPos};
+use crate::world::ChunkSection;
+
+/// A run of faces from one block's model which survived culling.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelDraw {
+	pub pos: BlockPos,
+
+	pub xRotation: f32,
+
+	pub yRotation: f32,
+
+	/// Range of vertices in the [`GeometryBuffer`] to draw
+	pub vertices: Range<usize>,
+
+	/// Light levels around the block, see [`packed_light`]
+	pub light: u32,
+
+	/// Which blocks around this one are solid, see [`Mesher::packed_occlusion`]
+	pub occlusion: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SectionMesh {
+	pub draws: Vec<ModelDraw>,
+
+	/// Draws of fluid surfaces, whose vertices are in `fluidVertices` rather than the
+	/// [`GeometryBuffer`]
+	pub fluidDraws: Vec<ModelDraw>,
+
+	/// Fluid surfaces change shape from block to block, so are built for each section
+	pub fluidVertices: Vec<FullVertex>,
+
+	pub emittedFaces: usize,
+
+	pub culledFaces: usize,
+}
+
+/// Decides which faces of the blocks in a section need drawing, skipping those with a `cullface`
+/// which is covered by a neighbouring full, opaque cube.
+pub struct Mesher<'a> {
+	blockstates: &'a BlockStateCache,
+	statemap: &'a HashMap<BlockState, Vec<Vec<BlockStateModel>>>,
+	models: &'a ModelCache,
+	geometry: &'a GeometryBuffer,
+	occluders: HashSet<BlockState>,
+	/// Only fluids whose model [`fluid::add_models`] has added are drawn
+	fluidTextures: HashMap<Fluid, FluidTextures>,
+}
+
+impl<'a> Mesher<'a> {
+	pub fn new(
+		blockstates: &'a BlockStateCache,
+		statemap: &'a HashMap<BlockState, Vec<Vec<BlockStateModel>>>,
+		models: &'a ModelCache,
+		geometry: &'a GeometryBuffer,
+		cartographer: &Cartographer,
+	) -> Self {
+		// each side only needs one opaque face covering it, as overlays like those on the sides of
+		// grass are drawn over another face
+		let occludes = |variant: &BlockStateModel| {
+			let model = match models.get(&variant.model) {
+				Some(v) => v,
+				None => return false,
+			};
+			let opaque = |face: &&Face| {
+				let texture = match face.texture {
+					Texture::Slot(slot) => model.texture(&slot),
+					Texture::Asset(loc) => loc,
+				};
+				face.covers_side() && cartographer.is_opaque(texture)
+			};
+			Direction::all.into_iter().all(|dir| {
+				model.faces.iter().filter(|face| face.direction == dir).any(|face| opaque(&face))
+			})
+		};
+		let occluders = statemap
+			.iter()
+			.filter(|(_, sets)| sets.len() == 1 && sets[0].iter().all(occludes))
+			.map(|(&state, _)| state)
+			.collect();
+		let fluidTextures = Fluid::all
+			.into_iter()
+			.filter_map(|fluid| Some((fluid, FluidTextures::find(fluid, models, cartographer)?)))
+			.collect();
+
+		Self {
+			blockstates,
+			statemap,
+			models,
+			geometry,
+			occluders,
+			fluidTextures,
+		}
+	}
+
+	pub fn mesh_section(&self, section: &ChunkSection) -> SectionMesh {
+		let mut mesh = SectionMesh::default();
+		let (chunkPos, sectionY) = section.pos();
+		// drawn on top of the models of their blocks, like the patterns of banners
+		let instanceModels: HashMap<BlockPos, Vec<BlockStateModel>> = {
+			let chunk = section.chunk();
+			let chunk = chunk.borrow();
+			chunk
+				.block_entities()
+				.filter(|(pos, _)| pos.section() == sectionY)
+				.map(|(pos, entity)| {
+					let state = self.blockstates.normalize(section.get_block(pos));
+					(pos, entity::instance_models(state, entity))
+				})
+				.filter(|(_, models)| !models.is_empty())
+				.collect()
+		};
+		for pos in chunkPos.blocks_in_section(sectionY) {
+			let rawState = section.get_block(pos);
+			if let Some(fluid) = FluidState::of(rawState) {
+				self.mesh_fluid(section, pos, fluid, &mut mesh);
+			}
+			let state = self.blockstates.normalize(rawState);
+			let sets = match self.statemap.get(&state) {
+				Some(v) => v,
+				None => continue,
+			};
+			let light = packed_light(section, pos);
+			let firstDraw = mesh.draws.len();
+
+			let instanceModels = instanceModels.get(&pos).map(Vec::as_slice).unwrap_or_default();
+			for variant in choose_models(sets, pos).chain(instanceModels) {
+				let (model, (baseVertex, _)) =
+					match (self.models.get(&variant.model), self.geometry.variant_info(variant)) {
+						(Some(model), Some(info)) => (model, info),
+						_ => continue,
+					};
+				let xRotation = variant.xRotation.unwrap_or(0.0);
+				let yRotation = variant.yRotation.unwrap_or(0.0);
+				let rotation = variant_rotation(xRotation, yRotation);
+
+				let mut run: Option<Range<usize>> = None;
+				for (index, face) in model.faces.iter().enumerate() {
+					let culled = face.cullface.is_some_and(|dir| {
+						let dir = Direction::nearest(rotation.transform_vector3(dir.normal()));
+						self.is_occluder(section, neighbour(pos, dir))
+					});
+					if culled {
+						mesh.culledFaces += 1;
+						continue;
+					}
+					mesh.emittedFaces += 1;
+
+					// faces are laid out one after another, 6 vertices each
+					let vertices = baseVertex + index * 6 .. baseVertex + (index + 1) * 6;
+					match &mut run {
+						Some(run) if run.end == vertices.start => run.end = vertices.end,
+						_ => {
+							if let Some(vertices) = run.replace(vertices) {
+								mesh.draws.push(ModelDraw {
+									pos,
+									xRotation,
+									yRotation,
+									vertices,
+									light,
+									occlusion: 0,
+								});
+							}
+						},
+					}
+				}
+				if let Some(vertices) = run {
+					mesh.draws.push(ModelDraw {
+						pos,
+						xRotation,
+						yRotation,
+						vertices,
+						light,
+						occlusion: 0,
+					});
+				}
+			}
+
+			// only worth looking up for blocks with faces left to draw
+			if mesh.draws.len() > firstDraw {
+				let occlusion = self.packed_occlusion(section, pos);
+				for draw in &mut mesh.draws[firstDraw ..] {
+					draw.occlusion = occlusion;
+				}
+			}
+		}
+		mesh
+	}
+
+	/// Adds the surface of the fluid in the block at `pos` to `mesh`.
+	fn mesh_fluid(
+		&self,
+		section: &ChunkSection,
+		pos: BlockPos,
+		fluid: FluidState,
+		mesh: &mut SectionMesh,
+	) {
+		let textures = match self.fluidTextures.get(&fluid.fluid) {
+			Some(v) => v,
+			None => return,
+		};
+		let color = match fluid.fluid {
+			// waterlogged blocks have tints of their own, so water's is kept with its faces
+			Fluid::Water => tint::water_color(section.get_biome(pos)),
+			Fluid::Lava => tint::untinted,
+		};
+		let faces = fluid_faces(fluid, color, |offset| {
+			let neighbour = BlockPos::new(pos.x + offset.x, pos.y + offset.y, pos.z + offset.z);
+			let state = block_near(section, neighbour);
+			let solid = |state| self.occluders.contains(&self.blockstates.normalize(state));
+			FluidNeighbour {
+				fluid: state.and_then(FluidState::of),
+				solid: state.is_some_and(solid),
+			}
+		});
+		if faces.is_empty() {
+			return;
+		}
+
+		let start = mesh.fluidVertices.len();
+		for face in &faces {
+			mesh.fluidVertices.extend(face.full_vertices(textures.get(face.texture), false));
+		}
+		mesh.emittedFaces += faces.len();
+		mesh.fluidDraws.push(ModelDraw {
+			pos,
+			xRotation: 0.0,
+			yRotation: 0.0,
+			vertices: start .. mesh.fluidVertices.len(),
+			light: packed_light(section, pos),
+			occlusion: 0,
+		});
+	}
+
+	/// Packs whether each block of the 3x3x3 cube around `pos` is a full, opaque cube into bit
+	/// [`occlusion_bit`] of the result.
+	pub fn packed_occlusion(&self, section: &ChunkSection, pos: BlockPos) -> u32 {
+		let mut packed = 0;
+		for dy in -1 ..= 1 {
+			for dz in -1 ..= 1 {
+				for dx in -1 ..= 1 {
+					let offset = IVec3::new(dx, dy, dz);
+					let neighbour = BlockPos::new(pos.x + dx, pos.y + dy, pos.z + dz);
+					if self.is_occluder(section, neighbour) {
+						packed |= 1 << occlusion_bit(offset);
+					}
+				}
+			}
+		}
+		packed
+	}
+
+	fn is_occluder(&self, section: &ChunkSection, pos: BlockPos) -> bool {
+		match block_near(section, pos) {
+			Some(state) => self.occluders.contains(&self.blockstates.normalize(state)),
+			None => false,
+		}
+	}
+}
+
+/// Packs the light levels of the neighbours of `pos`, in the order of [`Direction::all`], and
+/// then of the block itself into 4 bits each, starting from the lowest. Light which isn't loaded
+/// counts as full daylight.
+pub fn packed_light(section: &ChunkSection, pos: BlockPos) -> u32 {
+	let level = |pos| light_near(section, pos).unwrap_or(15) as u32;
+	let mut packed = level(pos) << 24;
+	for (index, dir) in Direction::all.into_iter().enumerate() {
+		packed |= level(neighbour(pos, dir)) << (index * 4);
+	}
+	packed
+}
+
+/// Brightness of a vertex's face drawn with `model` on a block with `light` around it, combining
+/// directional shading with the light level of the neighbour it faces. Mirrors `main.wgsl`.
+pub fn face_brightness(vertex: &FullVertex, model: Mat4, light: u32) -> f32 {
+	let dir = Direction::nearest(model.transform_vector3(vertex.normal.into()));
+	let shade = if vertex.flags & FullVertex::shadeFlag != 0 {
+		dir.shade()
+	} else {
+		1.0
+	};
+	// faces inside the block, like the top of a slab, are lit by the block itself
+	let index = if vertex.flags & FullVertex::cullfaceFlag != 0 {
+		Direction::all.iter().position(|&v| v == dir).unwrap()
+	} else {
+		6
+	};
+	shade * light_brightness(light >> (index * 4) & 0xF)
+}
+
+/// Bit of [`Mesher::packed_occlusion`] for the block at `offset` from the centre.
+pub fn occlusion_bit(offset: IVec3) -> u32 {
+	((offset.y + 1) * 9 + (offset.z + 1) * 3 + offset.x + 1) as u32
+}
+
+/// Smooth lighting of a vertex drawn with `model` on a block with `occlusion` around it. As in
+/// vanilla, this averages the brightness of the block in front of the corner the vertex lies on
+/// and of the three blocks next to it in that layer, with solid ones counting for 0.2. Mirrors
+/// `main.wgsl`.
+pub fn vertex_occlusion(vertex: &FullVertex, model: Mat4, occlusion: u32) -> f32 {
+	if vertex.flags & FullVertex::ambientOcclusionFlag == 0 {
+		return 1.0;
+	}
+	let normal = Direction::nearest(model.transform_vector3(vertex.normal.into()))
+		.normal()
+		.as_ivec3();
+	// the block's rotation keeps its centre in place
+	let blockOrigin = model.transform_point3(Vec3::splat(0.5)) - 0.5;
+	let local = model.transform_point3(vertex.pos.into()) - blockOrigin;
+
+	// faces inside the block, like the top of a slab, are surrounded by the block's own layer
+	let front = if vertex.flags & FullVertex::cullfaceFlag != 0 {
+		normal
+	} else {
+		IVec3::ZERO
+	};
+	let corner = (local - 0.5).to_array().map(|v| {
+		if v.abs() < 0.01 {
+			0
+		} else {
+			v.signum() as i32
+		}
+	});
+	let corner = IVec3::from(corner) * (IVec3::ONE - normal.abs());
+	let (tangent1, tangent2) = match normal.abs().to_array() {
+		[1, _, _] => (IVec3::Y, IVec3::Z),
+		[_, 1, _] => (IVec3::X, IVec3::Z),
+		_ => (IVec3::X, IVec3::Y),
+	};
+
+	let solid = |offset: IVec3| occlusion >> occlusion_bit(offset) & 1 != 0;
+	let side1 = solid(front + corner * tangent1);
+	let side2 = solid(front + corner * tangent2);
+	let diagonal = (side1 && side2) || solid(front + corner);
+	let brightness = |solid: bool| if solid { 0.2 } else { 1.0 };
+	(brightness(solid(front)) + brightness(side1) + brightness(side2) + brightness(diagonal)) / 4.0
+}
+
+/// Vanilla's lightmap curve without gamma, with a little ambient light so that unlit faces
+/// aren't completely black.
+pub fn light_brightness(level: u32) -> f32 {
+	let level = level as f32 / 15.0;
+	0.04 + 0.96 * level / (4.0 - 3.0 * level)
+}
+
+fn neighbour(pos: BlockPos, dir: Direction) -> BlockPos {
+	let offset = dir.normal().as_ivec3();
+	BlockPos::new(pos.x + offset.x, pos.y + offset.y, pos.z + offset.z)
+}
+
+/// Looks up a block in or around `section`, as long as whatever contains it is loaded.
+fn block_near(section: &ChunkSection, pos: BlockPos) -> Option<BlockState> {
+	let (chunkPos, sectionY) = section.pos();
+	if ChunkPos::from(pos) != chunkPos {
+		let dimension = section.dimension();
+		let block = dimension.borrow().get_block(pos);
+		return block;
+	}
+	if pos.section() == sectionY {
+		return Some(section.get_block(pos));
+	}
+	let chunk = section.chunk();
+	let block = chunk.borrow().get_block(pos);
+	block
+}
+
+/// Looks up the light level in or around `section`, like [`block_near`].
+fn light_near(section: &ChunkSection, pos: BlockPos) -> Option<u8> {
+	let (chunkPos, sectionY) = section.pos();
+	if ChunkPos::from(pos) != chunkPos {
+		let dimension = section.dimension();
+		let light = dimension.borrow().get_light(pos);
+		return light;
+	}
+	if pos.section() == sectionY {
+		return Some(section.get_light(pos));
+	}
+	let chunk = section.chunk();
+	let light = chunk.borrow().get_light(pos);
+	light
+}
+
+#[test]
+fn test_face_brightness() {
+	use super::model::Vertex;
+
+	assert_eq!(light_brightness(15), 1.0);
+	assert!((light_brightness(0) - 0.04).abs() < 1e-6);
+
+	// a shaded, culled north face, with light 15 to the north and 0 in the block itself
+	let north = Direction::all.iter().position(|&v| v == Direction::North).unwrap();
+	let light = 15 << (north * 4);
+	let mut vertex = FullVertex {
+		vert: Vertex {
+			pos: [0.0; 3],
+			uv: [0.0; 2],
+		},
+		texId: 0,
+		tintIndex: -1,
+		normal: Direction::North.normal().into(),
+		flags: FullVertex::shadeFlag | FullVertex::cullfaceFlag,
+		color: tint::untinted,
+	};
+	assert_eq!(face_brightness(&vertex, Mat4::IDENTITY, light), 0.8);
+
+	// turned to face east, where it's dark
+	let east = variant_rotation(0.0, 90.0);
+	assert!((face_brightness(&vertex, east, light) - 0.6 * 0.04).abs() < 1e-6);
+
+	// unshaded and lit by its own block
+	vertex.flags = 0;
+	assert!((face_brightness(&vertex, Mat4::IDENTITY, light) - 0.04).abs() < 1e-6);
+}
+
+#[test]
+fn test_vertex_occlusion() {
+	use super::model::Vertex;
+
+	// the north-west corner of a culled top face
+	let mut vertex = FullVertex {
+		vert: Vertex {
+			pos: [0.0, 1.0, 0.0],
+			uv: [0.0; 2],
+		},
+		texId: 0,
+		tintIndex: -1,
+		normal: Direction::Up.normal().into(),
+		flags: FullVertex::cullfaceFlag | FullVertex::ambientOcclusionFlag,
+		color: tint::untinted,
+	};
+	let bit = |x, y, z| 1 << occlusion_bit(IVec3::new(x, y, z));
+	let model = Mat4::from_translation(Vec3::new(5.0, 64.0, -3.0));
+	assert_eq!(vertex_occlusion(&vertex, model, 0), 1.0);
+
+	// a block above to the west, then one above to the north as well
+	assert!((vertex_occlusion(&vertex, model, bit(-1, 1, 0)) - 0.8).abs() < 1e-6);
+	let sides = bit(-1, 1, 0) | bit(0, 1, -1);
+	assert!((vertex_occlusion(&vertex, model, sides) - 0.4).abs() < 1e-6);
+
+	// the same corner after turning the block around is the south-east one
+	let turned = model * variant_rotation(0.0, 180.0);
+	assert_eq!(vertex_occlusion(&vertex, turned, sides), 1.0);
+	assert!((vertex_occlusion(&vertex, turned, bit(1, 1, 1)) - 0.8).abs() < 1e-6);
+
+	// blocks in the face's own layer only matter to faces inside the block
+	assert_eq!(vertex_occlusion(&vertex, model, bit(-1, 0, 0)), 1.0);
+	vertex.flags = FullVertex::ambientOcclusionFlag;
+	assert!((vertex_occlusion(&vertex, model, bit(-1, 0, 0)) - 0.8).abs() < 1e-6);
+
+	vertex.flags = FullVertex::cullfaceFlag;
+	assert_eq!(vertex_occlusion(&vertex, model, sides), 1.0);
+}
+
+#[test]
+fn test_occluders() {
+	use std::collections::BTreeMap;
+
+	use glam::UVec2;
+
+	use super::model::{bake_element, Model};
+	use super::texture::Image;
+	use crate::loader::model::Element;
+
+	let mut overlay = Image::solid_color(UVec2::splat(2), 0xFF_00FF00);
+	overlay.pixels[0] = 0;
+	let (cartographer, _) = Cartographer::from_images(
+		[
+			("test:dirt".into(), Image::solid_color(UVec2::splat(2), 0xFF_336699)),
+			("test:overlay".into(), overlay.clone()),
+			("test:glass".into(), overlay),
+		],
+		16,
+		0,
+	);
+
+	let element = |texture: &str, sides: &[&str]| {
+		let faces: Vec<_> = sides
+			.iter()
+			.map(|side| format!(r##""{side}": {{ "texture": "#{texture}" }}"##))
+			.collect();
+		let faces = faces.join(", ");
+		let json = format!(r#"{{ "from": [0, 0, 0], "to": [16, 16, 16], "faces": {{{faces}}} }}"#);
+		bake_element(&serde_json::from_str::<Element>(&json).unwrap())
+	};
+	let allSides = ["down", "up", "north", "south", "west", "east"];
+	let model = |id: &str, elements: Vec<Vec<Face>>| Model {
+		id: id.into(),
+		parent: None,
+		textureSlots: BTreeMap::from([
+			("dirt".into(), Texture::Asset("test:dirt".into())),
+			("overlay".into(), Texture::Asset("test:overlay".into())),
+			("glass".into(), Texture::Asset("test:glass".into())),
+		]),
+		faces: elements.concat(),
+		ambientOcclusion: true,
+	};
+	let mut models = ModelCache::new();
+	// like vanilla's `block/grass_block`: an opaque cube, with a translucent overlay on each side
+	let grass = model("test:grass", vec![
+		element("dirt", &allSides),
+		element("overlay", &allSides[2 ..]),
+	]);
+	models.insert(grass.id, grass);
+	models.insert("test:glass".into(), model("test:glass", vec![element("glass", &allSides)]));
+	// a cube missing its west side
+	let open = model("test:open", vec![element("dirt", &[&allSides[.. 4], &["east"]].concat())]);
+	models.insert(open.id, open);
+
+	let statemap: HashMap<_, _> = ["test:grass", "test:glass", "test:open"]
+		.into_iter()
+		.map(|name| {
+			let variant = serde_json::from_str(&format!(r#"{{"model": "{name}"}}"#)).unwrap();
+			(BlockState::stateless(name.into()), vec![vec![variant]])
+		})
+		.collect();
+	let geometry = GeometryBuffer {
+		vertices: vec![],
+		modelInfo: HashMap::new(),
+		uvlockedInfo: HashMap::new(),
+	};
+	let blockstates = BlockStateCache::new();
+	let mesher = Mesher::new(&blockstates, &statemap, &models, &geometry, &cartographer);
+	let occludes = |name: &str| mesher.occluders.contains(&BlockState::stateless(name.into()));
+	assert!(occludes("test:grass"));
+	assert!(!occludes("test:glass"));
+	assert!(!occludes("test:open"));
+}
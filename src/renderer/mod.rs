@@ -1,2 +1,440 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use glam::{uvec2, vec2, vec3, Mat4, UVec2, Vec2, Vec3};
+
+use self::gpu::GpuPipeline;
+use self::mesh::{Mesher, ModelDraw};
+use self::model::{variant_rotation, FullVertex, GeometryBuffer, ModelCache};
+use self::raster::Rasterizer;
+use self::texture::{Cartographer, Image};
+use self::tint::BlockColors;
+use crate::loader::model::BlockStateModel;
+use crate::types::blockstate::{BlockState, BlockStateCache};
+use crate::types::parallel::par_map;
+use crate::types::shared::Shared;
+use crate::types::{BlockPos, ChunkArea, ChunkPos};
+use crate::world::{Chunk, Region};
+
+pub mod entity;
+pub mod fluid;
+mod gpu;
+pub mod mesh;
 pub mod model;
+pub mod raster;
+pub mod surface;
 pub mod texture;
+pub mod tiles;
+pub mod tint;
+
+const blocksPerSection: usize = ChunkPos::diameterBlocks.pow(3) as usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+	Wgpu,
+	/// The software [`Rasterizer`]
+	Cpu,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+	/// Output resolution, in pixels
+	pub size: UVec2,
+
+	/// Colour of the background, as `0xAABBGGRR`
+	pub clearColor: u32,
+
+	/// MSAA samples per pixel on the GPU; 1 disables multisampling
+	pub sampleCount: u32,
+
+	pub backend: Backend,
+}
+
+impl RenderSettings {
+	/// Largest atlas layer the backend can take, in pixels across.
+	pub fn max_texture_diameter(&self) -> usize {
+		match self.backend {
+			// wgpu's default limit for 3D textures, which holds for every adapter
+			Backend::Wgpu => wgpu::Limits::default().max_texture_dimension_3d as usize,
+			Backend::Cpu => 8192,
+		}
+	}
+
+	pub fn aspect_ratio(&self) -> f32 {
+		self.size.x as f32 / self.size.y as f32
+	}
+}
+
+impl Default for RenderSettings {
+	fn default() -> Self {
+		Self {
+			size: uvec2(1280, 720),
+			clearColor: 0xFF_007FFF,
+			sampleCount: 4,
+			backend: Backend::Wgpu,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+	pub projection: Mat4,
+	pub view: Mat4,
+}
+
+impl Camera {
+	/// A perspective camera at `origin`, turned by `angles` (pitch then yaw, in degrees).
+	pub fn perspective(origin: Vec3, angles: Vec2, aspect: f32) -> Self {
+		let projection = Mat4::perspective_rh(110f32.to_radians(), aspect, 0.01, 1000.0);
+		let rot = Mat4::from_rotation_y(angles.y.to_radians()) *
+			Mat4::from_rotation_x(angles.x.to_radians());
+		let forward = rot.transform_vector3(Vec3::Z);
+		let view = Mat4::look_at_rh(origin, origin + forward, Vec3::Y);
+		Self { projection, view }
+	}
+
+	/// An orthographic camera looking straight down on the whole of `area`, with north at the top
+	/// of the image.
+	pub fn top_down(area: ChunkArea) -> Self {
+		let min = area.min.min_block();
+		let max = area.max.max_block();
+		let halfSize = vec2((max.x + 1 - min.x) as f32, (max.z + 1 - min.z) as f32) / 2.0;
+		let eye = vec3(
+			min.x as f32 + halfSize.x,
+			(BlockPos::maxHeight + 2) as f32,
+			min.z as f32 + halfSize.y,
+		);
+		let view = Mat4::look_at_rh(eye, eye - Vec3::Y, Vec3::NEG_Z);
+		let depth = (BlockPos::columnHeight + 2) as f32;
+		let projection = Mat4::orthographic_rh(
+			-halfSize.x,
+			halfSize.x,
+			-halfSize.y,
+			halfSize.y,
+			0.0,
+			depth,
+		);
+		Self { projection, view }
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderOutput {
+	pub image: Image,
+
+	pub emittedFaces: usize,
+
+	pub culledFaces: usize,
+}
+
+/// The faces to draw for one chunk, and the tints of its blocks.
+struct ChunkMesh {
+	pos: ChunkPos,
+
+	sections: Vec<(i8, Vec<ModelDraw>)>,
+
+	/// Drawn after `sections`, from `fluidVertices` rather than the [`GeometryBuffer`]
+	fluidSections: Vec<(i8, Vec<ModelDraw>)>,
+
+	fluidVertices: Vec<FullVertex>,
+
+	/// One per block in the chunk, `blocksPerSection` per section from the bottom of the world
+	tints: Vec<u32>,
+
+	/// Light levels around each block, laid out like `tints`; see [`mesh::packed_light`]
+	lights: Vec<u32>,
+
+	/// Solid blocks around each block, laid out like `tints`; see [`Mesher::packed_occlusion`]
+	occlusion: Vec<u32>,
+}
+
+/// Draws chunks with either the wgpu pipeline or the software rasterizer.
+pub struct Renderer<'a> {
+	blockstates: &'a BlockStateCache,
+	statemap: &'a HashMap<BlockState, Vec<Vec<BlockStateModel>>>,
+	models: &'a ModelCache,
+	cartographer: &'a Cartographer,
+	blockTextureLayers: &'a [Image],
+	blockColors: &'a BlockColors,
+	geometry: GeometryBuffer,
+	settings: RenderSettings,
+	/// Absent with the CPU backend
+	gpu: Option<GpuPipeline>,
+}
+
+impl<'a> Renderer<'a> {
+	/// `cartographer` should be built with atlas layers no larger than
+	/// [`RenderSettings::max_texture_diameter`].
+	pub fn new(
+		blockstates: &'a BlockStateCache,
+		statemap: &'a HashMap<BlockState, Vec<Vec<BlockStateModel>>>,
+		models: &'a ModelCache,
+		cartographer: &'a Cartographer,
+		blockTextureLayers: &'a [Image],
+		blockColors: &'a BlockColors,
+		settings: RenderSettings,
+	) -> anyhow::Result<Self> {
+		let geometry = models.geometry_buffer(cartographer, statemap.values().flatten().flatten());
+		let gpu = match settings.backend {
+			Backend::Wgpu => Some(GpuPipeline::new(
+				&geometry,
+				cartographer,
+				blockTextureLayers,
+				&settings,
+			)?),
+			Backend::Cpu => None,
+		};
+		Ok(Self {
+			blockstates,
+			statemap,
+			models,
+			cartographer,
+			blockTextureLayers,
+			blockColors,
+			geometry,
+			settings,
+			gpu,
+		})
+	}
+
+	pub fn settings(&self) -> &RenderSettings {
+		&self.settings
+	}
+
+	pub fn render_region(&self, region: &Region, camera: &Camera) -> RenderOutput {
+		let chunks: Vec<_> = region.chunks().collect();
+		self.render_chunks(&chunks, camera)
+	}
+
+	/// Renders `chunks` with animated textures as they are at the start of their animations.
+	pub fn render_chunks(&self, chunks: &[Shared<Chunk>], camera: &Camera) -> RenderOutput {
+		self.render_animation(chunks, camera, 0 .. 1).remove(0)
+	}
+
+	/// Renders `chunks` once for every game tick in `ticks`, with animated textures as they are at
+	/// that tick. The chunks are only meshed once, so every output counts the same faces.
+	pub fn render_animation(
+		&self,
+		chunks: &[Shared<Chunk>],
+		camera: &Camera,
+		ticks: Range<u64>,
+	) -> Vec<RenderOutput> {
+		let mesher = Mesher::new(
+			self.blockstates,
+			self.statemap,
+			self.models,
+			&self.geometry,
+			self.cartographer,
+		);
+		let meshes = par_map(chunks, |chunk| self.mesh_chunk(&mesher, &chunk.borrow()));
+		let emittedFaces = meshes.iter().map(|(_, emitted, _)| emitted).sum();
+		let culledFaces = meshes.iter().map(|(_, _, culled)| culled).sum();
+		let meshes: Vec<_> = meshes.into_iter().map(|(mesh, ..)| mesh).collect();
+
+		ticks
+			.map(|tick| {
+				let image = match &self.gpu {
+					Some(gpu) => {
+						let frames = self.cartographer.frames_at(tick);
+						gpu.render(&meshes, camera, &frames, self.settings.clearColor)
+					},
+					None => self.rasterize(&meshes, camera, tick),
+				};
+				RenderOutput {
+					image,
+					emittedFaces,
+					culledFaces,
+				}
+			})
+			.collect()
+	}
+
+	/// Returns the chunk's mesh, along with how many faces were emitted and culled.
+	fn mesh_chunk(&self, mesher: &Mesher, chunk: &Chunk) -> (ChunkMesh, usize, usize) {
+		let (mut emittedFaces, mut culledFaces) = (0, 0);
+		let mut mesh = ChunkMesh {
+			pos: chunk.pos(),
+			sections: vec![],
+			fluidSections: vec![],
+			fluidVertices: vec![],
+			tints: vec![tint::untinted; ChunkPos::sections.count() * blocksPerSection],
+			lights: vec![0; ChunkPos::sections.count() * blocksPerSection],
+			occlusion: vec![0; ChunkPos::sections.count() * blocksPerSection],
+		};
+		for sectionY in chunk.sections() {
+			let section = match chunk.get_section(sectionY) {
+				Some(v) => v,
+				None => continue,
+			};
+			let section = section.borrow();
+			let tintBase = section_index(sectionY) * blocksPerSection;
+			for pos in chunk.pos().blocks_in_section(sectionY) {
+				let state = self.blockstates.normalize(section.get_block(pos));
+				mesh.tints[tintBase + pos.index_in_section()] =
+					self.blockColors.tint_for(state, section.get_biome(pos));
+			}
+
+			let mut sectionMesh = mesher.mesh_section(&section);
+			for draw in &sectionMesh.draws {
+				let index = tintBase + draw.pos.index_in_section();
+				mesh.lights[index] = draw.light;
+				mesh.occlusion[index] = draw.occlusion;
+			}
+			let baseVertex = mesh.fluidVertices.len();
+			for draw in &mut sectionMesh.fluidDraws {
+				mesh.lights[tintBase + draw.pos.index_in_section()] = draw.light;
+				draw.vertices = baseVertex + draw.vertices.start .. baseVertex + draw.vertices.end;
+			}
+			mesh.fluidVertices.extend(sectionMesh.fluidVertices);
+			if !sectionMesh.fluidDraws.is_empty() {
+				mesh.fluidSections.push((sectionY, sectionMesh.fluidDraws));
+			}
+			emittedFaces += sectionMesh.emittedFaces;
+			culledFaces += sectionMesh.culledFaces;
+			if !sectionMesh.draws.is_empty() {
+				mesh.sections.push((sectionY, sectionMesh.draws));
+			}
+		}
+		(mesh, emittedFaces, culledFaces)
+	}
+
+	fn rasterize(&self, meshes: &[ChunkMesh], camera: &Camera, tick: u64) -> Image {
+		let mut raster = Rasterizer::new(
+			self.cartographer,
+			self.blockTextureLayers,
+			camera.projection * camera.view,
+			self.settings.size,
+			self.settings.clearColor,
+			tick,
+		);
+		draw_meshes(&mut raster, meshes, &self.geometry.vertices);
+		raster.finish()
+	}
+}
+
+/// Draws every chunk's solid geometry, and then every chunk's fluids without depth writes, so that
+/// what's under a fluid shows through it whichever chunk it's in. `geometry` holds the vertices of
+/// the solid draws.
+fn draw_meshes(raster: &mut Rasterizer, meshes: &[ChunkMesh], geometry: &[FullVertex]) {
+	for fluid in [false, true] {
+		raster.set_depth_write(!fluid);
+		for mesh in meshes {
+			let (sections, vertices) = match fluid {
+				false => (&mesh.sections, geometry),
+				true => (&mesh.fluidSections, &mesh.fluidVertices[..]),
+			};
+			for (sectionY, draws) in sections {
+				let tintBase = section_index(*sectionY) * blocksPerSection;
+				for draw in draws {
+					let translation = vec3(draw.pos.x as f32, draw.pos.y as f32, draw.pos.z as f32);
+					let model = Mat4::from_translation(translation) *
+						variant_rotation(draw.xRotation, draw.yRotation);
+					let tint = mesh.tints[tintBase + draw.pos.index_in_section()];
+					let vertices = &vertices[draw.vertices.clone()];
+					raster.draw(vertices, model, tint, draw.light, draw.occlusion);
+				}
+			}
+		}
+	}
+}
+
+/// Index of a section counting up from the bottom of the world.
+fn section_index(sectionY: i8) -> usize {
+	(sectionY - ChunkPos::sections.start()) as usize
+}
+
+#[test]
+fn test_top_down_camera() {
+	let camera = Camera::top_down(ChunkArea::new(ChunkPos::new(-1, 0), ChunkPos::new(0, 1)));
+	let project = |pos: Vec3| (camera.projection * camera.view).project_point3(pos);
+
+	// north-west corner at the top left, south-east at the bottom right
+	let nw = project(vec3(-16.0, 64.0, 0.0));
+	let se = project(vec3(16.0, 64.0, 32.0));
+	assert!(nw.truncate().abs_diff_eq(vec2(-1.0, 1.0), 1e-5));
+	assert!(se.truncate().abs_diff_eq(vec2(1.0, -1.0), 1e-5));
+
+	// the whole height of the world is in view, higher blocks in front
+	let top = project(vec3(0.0, BlockPos::maxHeight as f32 + 1.0, 16.0)).z;
+	let bottom = project(vec3(0.0, BlockPos::minHeight as f32, 16.0)).z;
+	assert!(0.0 < top && top < bottom && bottom < 1.0);
+}
+
+#[test]
+fn test_fluids_over_other_chunks() {
+	use self::model::Vertex;
+
+	let water = Image::solid_color(UVec2::splat(2), 0x80_FF0000);
+	let stone = Image::solid_color(UVec2::splat(2), 0xFF_808080);
+	let (cartographer, layers) = Cartographer::from_images(
+		[
+			("test:water".into(), water),
+			("test:stone".into(), stone),
+		],
+		16,
+		0,
+	);
+	let texId = |name: &str| cartographer.id_for_texture(name.into()).unwrap().packed();
+	// a horizontal square facing up at `y` within its block
+	let square = |y: f32, texId: u32| {
+		let corners = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+		[0, 1, 2, 1, 3, 2].map(|i: usize| {
+			let [x, z] = corners[i];
+			FullVertex {
+				vert: Vertex {
+					pos: [x, y, z],
+					uv: [x, z],
+				},
+				texId,
+				tintIndex: -1,
+				normal: [0.0, 1.0, 0.0],
+				flags: 0,
+				color: tint::untinted,
+			}
+		})
+	};
+	let chunk = |x: i32, sectionY: i8, draw: ModelDraw| {
+		let perChunk = ChunkPos::sections.count() * blocksPerSection;
+		ChunkMesh {
+			pos: ChunkPos::new(x, 0),
+			sections: vec![(sectionY, vec![draw])],
+			fluidSections: vec![],
+			fluidVertices: vec![],
+			tints: vec![tint::untinted; perChunk],
+			lights: vec![u32::MAX; perChunk],
+			occlusion: vec![0; perChunk],
+		}
+	};
+	let draw = |pos: BlockPos| ModelDraw {
+		pos,
+		xRotation: 0.0,
+		yRotation: 0.0,
+		vertices: 0 .. 6,
+		light: u32::MAX,
+		occlusion: 0,
+	};
+
+	// water at the east edge of chunk A, drawn first, in front of the top of stone one block
+	// further east and down, in chunk B
+	let mut chunkA = chunk(0, 4, draw(BlockPos::new(15, 64, 0)));
+	chunkA.fluidSections = std::mem::take(&mut chunkA.sections);
+	chunkA.fluidVertices = square(0.9, texId("test:water")).to_vec();
+	let chunkB = chunk(1, 3, draw(BlockPos::new(16, 63, 0)));
+	let geometry = square(1.0, texId("test:stone"));
+
+	// looking east and down at 45 degrees
+	let eye = vec3(10.0, 70.0, 0.5);
+	let view = Mat4::look_at_rh(eye, eye + vec3(1.0, -1.0, 0.0), Vec3::Y);
+	let viewProjection = Mat4::orthographic_rh(-4.0, 4.0, -4.0, 4.0, 0.0, 20.0) * view;
+	let size = uvec2(32, 32);
+	let mut raster = Rasterizer::new(&cartographer, &layers, viewProjection, size, 0xFF_000000, 0);
+	draw_meshes(&mut raster, &[chunkA, chunkB], &geometry);
+	let image = raster.finish();
+
+	// where both cover the same pixel, the stone shows through the water
+	let ndc = viewProjection.project_point3(vec3(16.5, 64.0, 0.5));
+	let pixel = ((vec2(ndc.x, -ndc.y) * 0.5 + 0.5) * size.as_vec2()).as_uvec2();
+	let [r, g, b, _] = image.pixels[(pixel.y * size.x + pixel.x) as usize].to_le_bytes();
+	assert_eq!((r, b), (63, 191));
+	assert_eq!(g, r);
+}
@@ -7,10 +7,11 @@ use std::sync::Arc;
 
 use anyhow::{anyhow, Context};
 use bytemuck::{Pod, Zeroable};
-use glam::{vec3, BVec3, Mat4, Vec2, Vec3};
+use glam::{vec2, vec3, BVec3, Mat4, Vec2, Vec3};
 use serde::Deserialize;
 
 use super::texture::{Cartographer, TextureId};
+use super::tint;
 use crate::jarfs::JarFS;
 use crate::loader::model::{
 	Axis,
@@ -26,7 +27,7 @@ use crate::loader::model::{
 use crate::types::blockstate::{BlockState, BlockStateBuilder, BlockStateCache};
 use crate::types::resource_location::ResourceKind;
 use crate::types::shared::Shared;
-use crate::types::{IString, ResourceLocation};
+use crate::types::{BlockPos, IString, ResourceLocation};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -46,6 +47,72 @@ pub enum Direction {
 	West,
 }
 
+impl Direction {
+	pub const all: [Self; 6] = [
+		Self::Up,
+		Self::Down,
+		Self::North,
+		Self::East,
+		Self::South,
+		Self::West,
+	];
+
+	pub fn normal(self) -> Vec3 {
+		match self {
+			Self::Up => Vec3::Y,
+			Self::Down => Vec3::NEG_Y,
+			Self::North => Vec3::NEG_Z,
+			Self::East => Vec3::X,
+			Self::South => Vec3::Z,
+			Self::West => Vec3::NEG_X,
+		}
+	}
+
+	/// Brightness vanilla gives to shaded faces pointing this way.
+	pub fn shade(self) -> f32 {
+		match self {
+			Self::Up => 1.0,
+			Self::Down => 0.5,
+			Self::North | Self::South => 0.8,
+			Self::East | Self::West => 0.6,
+		}
+	}
+
+	/// Returns the direction closest to `v`.
+	pub fn nearest(v: Vec3) -> Self {
+		Self::all
+			.into_iter()
+			.max_by(|a, b| a.normal().dot(v).total_cmp(&b.normal().dot(v)))
+			.unwrap()
+	}
+
+	/// Projects a point within the block onto the texture of a face pointing this way, as vanilla
+	/// does to find the UVs of faces which don't specify any. UVs have their origin at the top
+	/// left of the texture, as in model JSONs, but range over `0.0 ..= 1.0`.
+	fn project(self, p: Vec3) -> Vec2 {
+		match self {
+			Self::Up => vec2(p.x, p.z),
+			Self::Down => vec2(p.x, 1.0 - p.z),
+			Self::North => vec2(1.0 - p.x, 1.0 - p.y),
+			Self::East => vec2(1.0 - p.z, 1.0 - p.y),
+			Self::South => vec2(p.x, 1.0 - p.y),
+			Self::West => vec2(p.z, 1.0 - p.y),
+		}
+	}
+
+	/// Inverse of [`Self::project`], placing the point halfway through the block.
+	fn unproject(self, uv: Vec2) -> Vec3 {
+		match self {
+			Self::Up => vec3(uv.x, 0.5, uv.y),
+			Self::Down => vec3(uv.x, 0.5, 1.0 - uv.y),
+			Self::North => vec3(1.0 - uv.x, 1.0 - uv.y, 0.5),
+			Self::East => vec3(0.5, 1.0 - uv.y, 1.0 - uv.x),
+			Self::South => vec3(uv.x, 1.0 - uv.y, 0.5),
+			Self::West => vec3(0.5, 1.0 - uv.y, uv.x),
+		}
+	}
+}
+
 #[derive(Clone, Copy)]
 pub struct Cube {
 	pub mins: Vec3,
@@ -254,17 +321,36 @@ impl Cube {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Zeroable, Pod)]
+#[derive(Clone, Copy, Debug, Zeroable, Pod)]
 pub struct Vertex {
 	pub pos: [f32; 3],
 	pub uv: [f32; 2],
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Zeroable, Pod)]
+#[derive(Clone, Copy, Debug, Zeroable, Pod)]
 pub struct FullVertex {
 	pub vert: Vertex,
 	pub texId: u32,
+	/// Index of the tint applied to the face, or -1 if untinted
+	pub tintIndex: i32,
+	/// Normal of the face's direction, before the blockstate rotation
+	pub normal: [f32; 3],
+	/// Combination of `FullVertex::shadeFlag`, `cullfaceFlag` and `ambientOcclusionFlag`
+	pub flags: u32,
+	/// Colour the face is multiplied by on top of any tint, as `0xRRGGBB`
+	pub color: u32,
+}
+
+impl FullVertex {
+	/// The face is darkened depending on its direction
+	pub const shadeFlag: u32 = 1;
+
+	/// The face is lit by the neighbour it faces rather than by its own block
+	pub const cullfaceFlag: u32 = 2;
+
+	/// The face is darkened by solid blocks around each of its corners
+	pub const ambientOcclusionFlag: u32 = 4;
 }
 
 impl Deref for FullVertex {
@@ -294,7 +380,73 @@ impl From<&str> for Texture {
 #[derive(Clone, Copy)]
 pub struct Face {
 	pub verts: [Vertex; 4],
+	pub direction: Direction,
+	/// Side of the block which hides this face when covered by a neighbour
+	pub cullface: Option<Direction>,
 	pub texture: Texture,
+	pub tintIndex: Option<i32>,
+	/// Whether the face is darkened depending on its direction
+	pub shade: bool,
+	/// Colour the face is multiplied by, as `0xRRGGBB`
+	pub color: u32,
+}
+
+impl Face {
+	/// Returns the UV of a vertex with its origin at the top left of the texture, as in model
+	/// JSONs, but ranging over `0.0 ..= 1.0`.
+	pub fn texture_uv(&self, vertex: usize) -> Vec2 {
+		let [u, v] = self.verts[vertex].uv;
+		vec2(u, 1.0 - v)
+	}
+
+	pub fn set_texture_uv(&mut self, vertex: usize, uv: Vec2) {
+		self.verts[vertex].uv = [uv.x, 1.0 - uv.y];
+	}
+
+	/// Expands this face into the pair of triangles it's drawn as, textured with the atlas
+	/// texture `texId`.
+	pub fn full_vertices(&self, texId: u32, ambientOcclusion: bool) -> [FullVertex; 6] {
+		let mut flags = 0;
+		if self.shade {
+			flags |= FullVertex::shadeFlag;
+		}
+		if self.cullface.is_some() {
+			flags |= FullVertex::cullfaceFlag;
+		}
+		if ambientOcclusion {
+			flags |= FullVertex::ambientOcclusionFlag;
+		}
+		// expand triangle strip to pair of tris with slot
+		[0, 1, 2, 1, 3, 2].map(|i| FullVertex {
+			vert: self.verts[i],
+			texId,
+			tintIndex: self.tintIndex.unwrap_or(-1),
+			normal: self.direction.normal().into(),
+			flags,
+			color: self.color,
+		})
+	}
+
+	/// Whether this face exactly covers the side of the block it points towards.
+	pub fn covers_side(&self) -> bool {
+		let bounds = Cube::from_points(self.verts.iter().map(|v| Vec3::from(v.pos)));
+		let normal = self.direction.normal();
+		let axis = normal.abs();
+		let side = axis * (normal + Vec3::ONE) / 2.0;
+		bounds.mins.abs_diff_eq(side, 1e-4) &&
+			bounds.maxs.abs_diff_eq(side + Vec3::ONE - axis, 1e-4)
+	}
+
+	/// Counter-rotates the UVs of this face so that its texture stays aligned with the world once
+	/// the model is rotated by `rotation`, as with `uvlock` in blockstate JSONs.
+	pub fn uvlock(&mut self, rotation: Mat4) {
+		let rotatedDir = Direction::nearest(rotation.transform_vector3(self.direction.normal()));
+		for vertex in 0 .. self.verts.len() {
+			let pos = self.direction.unproject(self.texture_uv(vertex));
+			let uv = rotatedDir.project(rotation.transform_point3(pos));
+			self.set_texture_uv(vertex, uv);
+		}
+	}
 }
 
 #[derive(Clone)]
@@ -303,6 +455,8 @@ pub struct Model {
 	pub parent: Option<ResourceLocation>,
 	pub textureSlots: BTreeMap<IString, Texture>,
 	pub faces: Vec<Face>,
+	/// Whether faces are darkened by the blocks around each corner
+	pub ambientOcclusion: bool,
 }
 
 impl Model {
@@ -339,6 +493,35 @@ impl Model {
 		res.transform(mat);
 		res
 	}
+
+	/// Whether the faces of this model cover every side of the block.
+	pub fn is_full_cube(&self) -> bool {
+		Direction::all.into_iter().all(|dir| {
+			self.faces
+				.iter()
+				.any(|face| face.direction == dir && face.covers_side())
+		})
+	}
+
+	/// Returns a copy of this model with UVs locked for the given blockstate rotation. Only the
+	/// UVs are changed; the geometry is still rotated at render time.
+	pub fn uvlocked(&self, xRotation: f32, yRotation: f32) -> Self {
+		let rotation = variant_rotation(xRotation, yRotation);
+		let mut res = self.clone();
+		for face in &mut res.faces {
+			face.uvlock(rotation);
+		}
+		res
+	}
+}
+
+/// Builds the rotation a blockstate variant applies to its model, about the centre of the block.
+pub fn variant_rotation(xRotation: f32, yRotation: f32) -> Mat4 {
+	let centre = Vec3::splat(0.5);
+	Mat4::from_translation(centre) *
+		Mat4::from_rotation_y(-yRotation.to_radians()) *
+		Mat4::from_rotation_x(-xRotation.to_radians()) *
+		Mat4::from_translation(-centre)
 }
 
 pub struct ModelCache(BTreeMap<ResourceLocation, Model>);
@@ -422,6 +605,7 @@ impl ModelCache {
 					parent: None,
 					textureSlots: BTreeMap::new(),
 					faces: vec![],
+					ambientOcclusion: true,
 				},
 			);
 		}
@@ -466,48 +650,17 @@ impl ModelCache {
 				if let Some(elems) = &json.elements {
 					faces = Vec::with_capacity(elems.len() * 6);
 					for elem in elems {
-						let mut cube =
-							Cube::new(Vec3::from(elem.from) / 16.0, Vec3::from(elem.to) / 16.0);
-
-						let rotation = elem.rotation.map(|rot| {
-							let origin = Vec3::from(rot.origin) / 16.0;
-							let angle = rot.angle.to_radians();
-							let rot = match rot.axis {
-								Axis::X => Mat4::from_rotation_x(angle),
-								Axis::Y => Mat4::from_rotation_y(angle),
-								Axis::Z => Mat4::from_rotation_z(angle),
-							};
-							// TODO: rescale
-							Mat4::from_translation(origin) * rot * Mat4::from_translation(-origin)
-						});
-
-						for (&dir, face) in &elem.faces {
-							let mut verts = cube.vertices(dir);
-
-							if let Some(rot) = rotation {
-								for vert in &mut verts {
-									vert.pos = rot.transform_point3(Vec3::from(vert.pos)).into();
-								}
-							}
-
-							if let Some(rect) = face.uv {
-								let mins = Vec2::new(rect[0], rect[1]) / 16.0;
-								let maxs = Vec2::new(rect[2], rect[3]) / 16.0;
-								for vert in &mut verts {
-									vert.uv = (mins + (maxs - mins) * Vec2::from(vert.uv)).into();
-								}
-							}
-
-							faces.push(Face {
-								texture: face.texture.as_str().into(),
-								verts,
-							});
-						}
+						faces.extend(bake_element(elem));
 					}
 				} else {
 					faces = parent.map(|v| v.faces.clone()).unwrap_or_else(|| vec![]);
 				}
 
+				let ambientOcclusion = json
+					.ambientOcclusion
+					.or(parent.map(|v| v.ambientOcclusion))
+					.unwrap_or(true);
+
 				newModels.push((
 					loc,
 					Model {
@@ -515,6 +668,7 @@ impl ModelCache {
 						parent: json.parent,
 						textureSlots,
 						faces,
+						ambientOcclusion,
 					},
 				));
 			}
@@ -550,64 +704,122 @@ impl ModelCache {
 			.collect()
 	}
 
-	pub fn geometry_buffer(&self, cartographer: &Cartographer) -> GeometryBuffer {
-		let mut vertices = vec![];
-		let mut modelInfo = HashMap::new();
+	/// Builds the vertices of every model, plus uvlocked copies of those used by `variants` with
+	/// `uvlock` set.
+	pub fn geometry_buffer<'a>(
+		&self,
+		cartographer: &Cartographer,
+		variants: impl IntoIterator<Item = &'a BlockStateModel>,
+	) -> GeometryBuffer {
+		let mut buffer = GeometryBuffer {
+			vertices: vec![],
+			modelInfo: HashMap::new(),
+			uvlockedInfo: HashMap::new(),
+		};
 
-		let mut vertexId = 0;
 		for (&id, model) in self.0.iter() {
-			let baseVertex = vertexId;
-			let numVertices = model.faces.len() * 6;
-			vertexId += numVertices;
-			vertices.extend(model.faces.iter().flat_map(|face| {
-				let slot = match face.texture {
-					Texture::Asset(_) => panic!(),
-					Texture::Slot(name) => name.as_str(),
-				};
-				let texId = cartographer
-					.id_for_texture(model.texture(slot))
-					.unwrap_or_else(|| {
-						cartographer
-							.id_for_texture("cuview:missing_texture".into())
-							.expect("Missing texture is itself missing! D:")
-					})
-					.packed();
-				[
-					// expand triangle strip to pair of tris with slot
-					FullVertex {
-						vert: face.verts[0],
-						texId,
-					},
-					FullVertex {
-						vert: face.verts[1],
-						texId,
-					},
-					FullVertex {
-						vert: face.verts[2],
-						texId,
-					},
-					FullVertex {
-						vert: face.verts[1],
-						texId,
-					},
-					FullVertex {
-						vert: face.verts[3],
-						texId,
-					},
-					FullVertex {
-						vert: face.verts[2],
-						texId,
-					},
-				]
-			}));
-			modelInfo.insert(id, (baseVertex, numVertices));
+			let info = buffer.push_model(model, cartographer);
+			buffer.modelInfo.insert(id, info);
+		}
+
+		for variant in variants {
+			let key = match GeometryBuffer::uvlock_key(variant) {
+				Some(key) if !buffer.uvlockedInfo.contains_key(&key) => key,
+				_ => continue,
+			};
+			if let Some(model) = self.get(&variant.model) {
+				let (_, xRotation, yRotation) = key;
+				let model = model.uvlocked(xRotation as f32, yRotation as f32);
+				let info = buffer.push_model(&model, cartographer);
+				buffer.uvlockedInfo.insert(key, info);
+			}
 		}
 
-		GeometryBuffer {
-			vertices,
-			modelInfo,
+		buffer
+	}
+}
+
+/// Converts the faces of a JSON model element into [`Face`]s.
+pub fn bake_element(elem: &Element) -> Vec<Face> {
+	let cube = Cube::new(Vec3::from(elem.from) / 16.0, Vec3::from(elem.to) / 16.0);
+
+	let rotation = elem.rotation.map(|rot| {
+		let origin = Vec3::from(rot.origin) / 16.0;
+		let angle = rot.angle.to_radians();
+		let (rotation, axis) = match rot.axis {
+			Axis::X => (Mat4::from_rotation_x(angle), Vec3::X),
+			Axis::Y => (Mat4::from_rotation_y(angle), Vec3::Y),
+			Axis::Z => (Mat4::from_rotation_z(angle), Vec3::Z),
+		};
+		// stretches the element back across the block along the other two axes; vanilla treats
+		// every angle other than 22.5 degrees as 45
+		let scale = if rot.rescale {
+			let angle: f32 = if rot.angle.abs() == 22.5 { 22.5 } else { 45.0 };
+			let factor = 1.0 / angle.to_radians().cos();
+			axis + (Vec3::ONE - axis) * factor
+		} else {
+			Vec3::ONE
+		};
+		Mat4::from_translation(origin) *
+			Mat4::from_scale(scale) *
+			rotation *
+			Mat4::from_translation(-origin)
+	});
+
+	let mut faces = Vec::with_capacity(elem.faces.len());
+	for (&dir, face) in &elem.faces {
+		let mut verts = cube.vertices(dir);
+
+		if let Some(rot) = rotation {
+			for vert in &mut verts {
+				vert.pos = rot.transform_point3(Vec3::from(vert.pos)).into();
+			}
+		}
+
+		// faces without UVs take them from the element's bounds
+		let (mins, maxs) = match face.uv {
+			Some(rect) => (
+				Vec2::new(rect[0], rect[1]) / 16.0,
+				Vec2::new(rect[2], rect[3]) / 16.0,
+			),
+			None => {
+				let a = dir.project(cube.mins);
+				let b = dir.project(cube.maxs);
+				(a.min(b), a.max(b))
+			},
+		};
+		let mut baked = Face {
+			texture: face.texture.as_str().into(),
+			verts,
+			direction: dir,
+			cullface: face.cullface,
+			tintIndex: face.tintindex,
+			shade: elem.shade,
+			color: tint::untinted,
+		};
+		let rotationSteps = face.textureRotation.unwrap_or(0) / 90;
+		for (vertex, &Vertex { uv: [u, v], .. }) in verts.iter().enumerate() {
+			// `Cube::vertices` gives the corner of the texture each vertex lies on, with a bottom
+			// left origin
+			let corner = rotate_corner(vec2(u, 1.0 - v), rotationSteps);
+			baked.set_texture_uv(vertex, mins + (maxs - mins) * corner);
 		}
+		faces.push(baked);
 	}
+	faces
+}
+
+/// Turns a corner of a face's texture clockwise by `steps` quarter turns, as vanilla does for
+/// faces with a `rotation`.
+fn rotate_corner(corner: Vec2, steps: i32) -> Vec2 {
+	const corners: [Vec2; 4] = [
+		Vec2::new(0.0, 0.0),
+		Vec2::new(0.0, 1.0),
+		Vec2::new(1.0, 1.0),
+		Vec2::new(1.0, 0.0),
+	];
+	let index = corners.iter().position(|&c| c == corner).unwrap() as i32;
+	corners[(index + steps).rem_euclid(4) as usize]
 }
 
 impl Deref for ModelCache {
@@ -628,6 +840,46 @@ pub struct GeometryBuffer {
 	pub vertices: Vec<FullVertex>,
 
 	pub modelInfo: HashMap<ResourceLocation, (usize, usize)>,
+
+	/// Models with UVs locked to a blockstate rotation, keyed by that rotation in degrees
+	pub uvlockedInfo: HashMap<(ResourceLocation, i32, i32), (usize, usize)>,
+}
+
+impl GeometryBuffer {
+	/// Returns the base vertex and vertex count of the geometry to draw for a blockstate variant.
+	pub fn variant_info(&self, variant: &BlockStateModel) -> Option<(usize, usize)> {
+		match Self::uvlock_key(variant) {
+			Some(key) => self.uvlockedInfo.get(&key).copied(),
+			None => self.modelInfo.get(&variant.model).copied(),
+		}
+	}
+
+	fn uvlock_key(variant: &BlockStateModel) -> Option<(ResourceLocation, i32, i32)> {
+		let xRotation = variant.xRotation.unwrap_or(0.0) as i32;
+		let yRotation = variant.yRotation.unwrap_or(0.0) as i32;
+		let rotated = (xRotation, yRotation) != (0, 0);
+		(variant.uvlock == Some(true) && rotated).then_some((variant.model, xRotation, yRotation))
+	}
+
+	fn push_model(&mut self, model: &Model, cartographer: &Cartographer) -> (usize, usize) {
+		let baseVertex = self.vertices.len();
+		self.vertices.extend(model.faces.iter().flat_map(|face| {
+			let slot = match face.texture {
+				Texture::Asset(_) => panic!(),
+				Texture::Slot(name) => name.as_str(),
+			};
+			let texId = cartographer
+				.id_for_texture(model.texture(slot))
+				.unwrap_or_else(|| {
+					cartographer
+						.id_for_texture("cuview:missing_texture".into())
+						.expect("Missing texture is itself missing! D:")
+				})
+				.packed();
+			face.full_vertices(texId, model.ambientOcclusion)
+		}));
+		(baseVertex, self.vertices.len() - baseVertex)
+	}
 }
 
 /**
@@ -750,6 +1002,75 @@ pub fn models_for_states(
 	modelsForState
 }
 
+/// Chooses one model from each of the sets [`models_for_states`] returns for the block at `pos`,
+/// making the same choices as vanilla does.
+pub fn choose_models(
+	sets: &[Vec<BlockStateModel>],
+	pos: BlockPos,
+) -> impl Iterator<Item = &BlockStateModel> {
+	let mut random = JavaRandom::new(position_seed(pos));
+	// vanilla gives each part of a multipart model its own generator, all seeded alike; a multipart
+	// state with a single matching part looks like variants here and is treated as such
+	let partSeed = (sets.len() > 1).then(|| random.next_long());
+	sets.iter().map(move |set| match partSeed {
+		Some(seed) => choose_weighted(set, &mut JavaRandom::new(seed)),
+		None => choose_weighted(set, &mut random),
+	})
+}
+
+/// Stable per-position seed, equivalent to vanilla's `Mth.getSeed`.
+pub fn position_seed(pos: BlockPos) -> i64 {
+	let seed = pos.x.wrapping_mul(3129871) as i64 ^
+		(pos.z as i64).wrapping_mul(116129781) ^
+		pos.y as i64;
+	let seed = seed
+		.wrapping_mul(seed)
+		.wrapping_mul(42317861)
+		.wrapping_add(seed.wrapping_mul(11));
+	seed >> 16
+}
+
+fn choose_weighted<'a>(
+	models: &'a [BlockStateModel],
+	random: &mut JavaRandom,
+) -> &'a BlockStateModel {
+	let weight = |model: &BlockStateModel| model.weight.unwrap_or(1);
+	let totalWeight: i32 = models.iter().map(weight).sum();
+	if models.len() == 1 || totalWeight <= 0 {
+		return &models[0];
+	}
+
+	let mut index = (random.next_long() as i32).wrapping_abs() % totalWeight;
+	for model in models {
+		index -= weight(model);
+		if index < 0 {
+			return model;
+		}
+	}
+	models.last().unwrap()
+}
+
+/// The linear congruential generator behind `java.util.Random`.
+struct JavaRandom(i64);
+
+impl JavaRandom {
+	const multiplier: i64 = 0x5DEECE66D;
+	const mask: i64 = (1 << 48) - 1;
+
+	fn new(seed: i64) -> Self {
+		Self((seed ^ Self::multiplier) & Self::mask)
+	}
+
+	fn next(&mut self, bits: u32) -> i32 {
+		self.0 = self.0.wrapping_mul(Self::multiplier).wrapping_add(0xB) & Self::mask;
+		(self.0 >> (48 - bits)) as i32
+	}
+
+	fn next_long(&mut self) -> i64 {
+		((self.next(32) as i64) << 32).wrapping_add(self.next(32) as i64)
+	}
+}
+
 pub fn export_wavefront(models: &[(&str, &Model)], mtlFilename: &str) -> (String, String) {
 	const palette: &[u32] = &[
 		0x0000FF, 0x00FF00, 0x00FFFF, 0xFF0000, 0xFF00FF, 0xFFFF00, 0xFFFFFF, 0x7FFF00, 0xFF7F00,
@@ -840,3 +1161,168 @@ pub fn export_wavefront(models: &[(&str, &Model)], mtlFilename: &str) -> (String
 
 	(obj, mtl)
 }
+
+#[test]
+fn test_choose_models() {
+	assert_eq!(JavaRandom::new(0).next_long(), -4962768465676381896);
+	assert_eq!(position_seed(BlockPos::new(0, 0, 0)), 0);
+	assert_eq!(position_seed(BlockPos::new(1, 64, -3)), -62687207132611);
+
+	let variant = |name: &str, weight| BlockStateModel {
+		model: name.into(),
+		xRotation: None,
+		yRotation: None,
+		uvlock: None,
+		weight,
+	};
+	let set = vec![variant("heavy", Some(3)), variant("light", None)];
+	let chosen: Vec<_> = (0 .. 8)
+		.map(|x| {
+			let model = choose_models(std::slice::from_ref(&set), BlockPos::new(x, 0, 0));
+			model.map(|m| m.model.name.as_str()).collect::<String>()
+		})
+		.collect();
+	assert_eq!(
+		chosen,
+		["heavy", "heavy", "heavy", "heavy", "light", "heavy", "heavy", "light"]
+	);
+}
+
+#[test]
+fn test_uvlock() {
+	// the lower half and step of vanilla's `block/stairs`
+	let slab: Element = serde_json::from_str(
+		r##"{ "from": [0, 0, 0], "to": [16, 8, 16], "faces": {
+			"up": { "uv": [0, 0, 16, 16], "texture": "#top" },
+			"north": { "uv": [0, 8, 16, 16], "texture": "#side" }
+		} }"##,
+	)
+	.unwrap();
+	let step: Element = serde_json::from_str(
+		r##"{ "from": [8, 8, 0], "to": [16, 16, 16], "faces": {
+			"up": { "uv": [8, 0, 16, 16], "texture": "#top" },
+			"north": { "uv": [0, 0, 8, 8], "texture": "#side" },
+			"west": { "texture": "#side" }
+		} }"##,
+	)
+	.unwrap();
+	let face = |elem: &Element, dir| {
+		*bake_element(elem)
+			.iter()
+			.find(|face| face.direction == dir)
+			.unwrap()
+	};
+	// UV of the vertex which ends up at `pos` after rotation, both in 16ths
+	let uv_at = |face: &Face, rotation: Mat4, pos: Vec3| {
+		let vertex = (0 .. 4)
+			.find(|&i| {
+				let rotated = rotation.transform_point3(face.verts[i].pos.into()) * 16.0;
+				rotated.distance(pos) < 1e-3
+			})
+			.unwrap();
+		(face.texture_uv(vertex) * 16.0).round()
+	};
+
+	// faces without UVs use the element's bounds
+	let west = face(&step, Direction::West);
+	assert_eq!(uv_at(&west, Mat4::IDENTITY, vec3(8.0, 16.0, 0.0)), vec2(0.0, 0.0));
+	assert_eq!(uv_at(&west, Mat4::IDENTITY, vec3(8.0, 8.0, 16.0)), vec2(16.0, 8.0));
+
+	// facing=west: y=180; the step's top now covers the western half of the block and shows that
+	// half of the texture
+	let rotation = variant_rotation(0.0, 180.0);
+	let mut up = face(&step, Direction::Up);
+	up.uvlock(rotation);
+	assert_eq!(uv_at(&up, rotation, vec3(0.0, 16.0, 0.0)), vec2(0.0, 0.0));
+	assert_eq!(uv_at(&up, rotation, vec3(8.0, 16.0, 16.0)), vec2(8.0, 16.0));
+
+	// sides are unchanged by rotations about y
+	let mut north = face(&step, Direction::North);
+	north.uvlock(rotation);
+	assert_eq!(uv_at(&north, rotation, vec3(0.0, 16.0, 16.0)), vec2(0.0, 0.0));
+	assert_eq!(uv_at(&north, rotation, vec3(8.0, 8.0, 16.0)), vec2(8.0, 8.0));
+
+	// half=top: x=180; the slab's sides stay upright and its top becomes the block's bottom
+	let rotation = variant_rotation(180.0, 0.0);
+	let mut north = face(&slab, Direction::North);
+	north.uvlock(rotation);
+	assert_eq!(uv_at(&north, rotation, vec3(0.0, 16.0, 16.0)), vec2(0.0, 0.0));
+	assert_eq!(uv_at(&north, rotation, vec3(16.0, 8.0, 16.0)), vec2(16.0, 8.0));
+	let mut up = face(&slab, Direction::Up);
+	up.uvlock(rotation);
+	assert_eq!(uv_at(&up, rotation, vec3(0.0, 8.0, 0.0)), vec2(0.0, 16.0));
+	assert_eq!(uv_at(&up, rotation, vec3(16.0, 8.0, 16.0)), vec2(16.0, 0.0));
+}
+
+#[test]
+fn test_bake_element() {
+	// one of the two planes of vanilla's `block/cross`
+	let plane: Element = serde_json::from_str(
+		r##"{ "from": [0.8, 0, 8], "to": [15.2, 16, 8],
+			"rotation": { "origin": [8, 8, 8], "axis": "y", "angle": 45, "rescale": true },
+			"faces": { "north": { "uv": [0, 0, 16, 16], "texture": "#cross" } } }"##,
+	)
+	.unwrap();
+	let face = bake_element(&plane)[0];
+	// (0.8, y, 8) ends up at (0.8, y, 15.2) and (15.2, y, 8) at (15.2, y, 0.8)
+	let positions: Vec<Vec3> = face
+		.verts
+		.iter()
+		.map(|v| (Vec3::from(v.pos) * 16.0 * 100.0).round() / 100.0)
+		.collect();
+	assert!(positions.contains(&vec3(0.8, 16.0, 15.2)));
+	assert!(positions.contains(&vec3(0.8, 0.0, 15.2)));
+	assert!(positions.contains(&vec3(15.2, 16.0, 0.8)));
+	assert!(positions.contains(&vec3(15.2, 0.0, 0.8)));
+
+	// the texture turns clockwise, so its top left lands in the north east corner
+	let top: Element = serde_json::from_str(
+		r##"{ "from": [0, 0, 0], "to": [16, 16, 16],
+			"faces": { "up": { "uv": [0, 0, 16, 8], "rotation": 90, "texture": "#top" } } }"##,
+	)
+	.unwrap();
+	let face = bake_element(&top)[0];
+	let uv_at = |x: f32, z: f32| {
+		let vertex = face
+			.verts
+			.iter()
+			.position(|v| v.pos == [x, 1.0, z])
+			.unwrap();
+		face.texture_uv(vertex) * 16.0
+	};
+	assert_eq!(uv_at(1.0, 0.0), vec2(0.0, 0.0));
+	assert_eq!(uv_at(1.0, 1.0), vec2(16.0, 0.0));
+	assert_eq!(uv_at(0.0, 1.0), vec2(16.0, 8.0));
+	assert_eq!(uv_at(0.0, 0.0), vec2(0.0, 8.0));
+}
+
+#[test]
+fn test_full_cube() {
+	let cube: Element = serde_json::from_str(
+		r##"{ "from": [0, 0, 0], "to": [16, 16, 16], "faces": {
+			"down": { "texture": "#all" }, "up": { "texture": "#all" },
+			"north": { "texture": "#all" }, "south": { "texture": "#all" },
+			"west": { "texture": "#all" }, "east": { "texture": "#all" }
+		} }"##,
+	)
+	.unwrap();
+	let mut model = Model {
+		id: "block/cube_all".into(),
+		parent: None,
+		textureSlots: BTreeMap::new(),
+		faces: bake_element(&cube),
+		ambientOcclusion: true,
+	};
+	assert!(model.is_full_cube());
+
+	// a slab's top doesn't reach the top of the block
+	let slab = model.transformed(Mat4::from_scale(vec3(1.0, 0.5, 1.0)));
+	assert!(!slab.is_full_cube());
+	let top = slab.faces.iter().find(|f| f.direction == Direction::Up).unwrap();
+	assert!(!top.covers_side());
+	let bottom = slab.faces.iter().find(|f| f.direction == Direction::Down).unwrap();
+	assert!(bottom.covers_side());
+
+	model.faces.retain(|f| f.direction != Direction::West);
+	assert!(!model.is_full_cube());
+}
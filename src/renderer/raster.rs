@@ -0,0 +1,305 @@
+use glam::{uvec2, vec2, Mat4, UVec2, Vec2, Vec3, Vec4};
+
+use super::mesh::{face_brightness, vertex_occlusion};
+use super::model::FullVertex;
+use super::texture::{alphaCutoff, Cartographer, Image, TextureFrame, TextureRect};
+use super::tint;
+
+/// Software rasterizer which draws the same geometry as the wgpu pipeline, for machines without a
+/// GPU. Textures are sampled at full size without filtering or mipmaps, and fragments with an alpha
+/// of [`alphaCutoff`] or less are discarded, as in `main.wgsl`.
+pub struct Rasterizer<'a> {
+	layers: &'a [Image],
+	rects: Vec<TextureRect>,
+	layerBases: Vec<u32>,
+	frames: Vec<TextureFrame>,
+	viewProjection: Mat4,
+	frame: Image,
+	depth: Vec<f32>,
+	depthWrite: bool,
+}
+
+#[derive(Clone, Copy)]
+struct ClipVertex {
+	pos: Vec4,
+	uv: Vec2,
+	/// Multiplier for the texture's colour channels
+	color: Vec3,
+}
+
+/// A vertex after the perspective divide, in pixels.
+#[derive(Clone, Copy)]
+struct ScreenVertex {
+	pos: Vec3,
+	invW: f32,
+	/// UV divided by W, for perspective correct interpolation
+	uvOverW: Vec2,
+	colorOverW: Vec3,
+}
+
+impl<'a> Rasterizer<'a> {
+	/// Animated textures are drawn as they are at game tick `tick`.
+	pub fn new(
+		cartographer: &Cartographer,
+		layers: &'a [Image],
+		viewProjection: Mat4,
+		size: UVec2,
+		clearColor: u32,
+		tick: u64,
+	) -> Self {
+		Self {
+			layers,
+			rects: cartographer.texture_rects().to_vec(),
+			layerBases: cartographer.layer_bases().to_vec(),
+			frames: cartographer.frames_at(tick),
+			viewProjection,
+			frame: Image::solid_color(size, clearColor),
+			depth: vec![1.0; (size.x * size.y) as usize],
+			depthWrite: true,
+		}
+	}
+
+	/// Draws the triangles of `vertices`, positioned by `model`. `tint` is the `0xRRGGBB` colour
+	/// faces with a `tintindex` are multiplied by, while `light` and `occlusion` describe the
+	/// block's surroundings as packed by [`super::mesh`].
+	pub fn draw(
+		&mut self,
+		vertices: &[FullVertex],
+		model: Mat4,
+		tint: u32,
+		light: u32,
+		occlusion: u32,
+	) {
+		let mvp = self.viewProjection * model;
+		let [b, g, r, _] = tint.to_le_bytes();
+		let tint = Vec3::new(r as f32, g as f32, b as f32) / 255.0;
+		for tri in vertices.chunks_exact(3) {
+			let tint = if tri[0].tintIndex >= 0 { tint } else { Vec3::ONE };
+			let [b, g, r, _] = tri[0].color.to_le_bytes();
+			let faceColor = Vec3::new(r as f32, g as f32, b as f32) / 255.0;
+			let color = tint * faceColor * face_brightness(&tri[0], model, light);
+			let clip = [tri[0], tri[1], tri[2]].map(|v| ClipVertex {
+				pos: mvp * Vec3::from(v.pos).extend(1.0),
+				uv: Vec2::from(v.uv),
+				color: color * vertex_occlusion(&v, model, occlusion),
+			});
+			let polygon = clip_near(&clip);
+			for i in 1 .. polygon.len().saturating_sub(1) {
+				self.fill_triangle([polygon[0], polygon[i], polygon[i + 1]], tri[0].texId);
+			}
+		}
+	}
+
+	/// Whether drawn fragments hide those drawn after them behind, as is off for fluids.
+	pub fn set_depth_write(&mut self, enabled: bool) {
+		self.depthWrite = enabled;
+	}
+
+	pub fn finish(self) -> Image {
+		self.frame
+	}
+
+	fn fill_triangle(&mut self, tri: [ClipVertex; 3], texId: u32) {
+		let size = self.frame.size.as_vec2();
+		let [a, b, c] = tri.map(|v| {
+			let invW = 1.0 / v.pos.w;
+			let ndc = v.pos.truncate() * invW;
+			ScreenVertex {
+				pos: Vec3::new(
+					(ndc.x * 0.5 + 0.5) * size.x,
+					(0.5 - ndc.y * 0.5) * size.y,
+					ndc.z,
+				),
+				invW,
+				uvOverW: v.uv * invW,
+				colorOverW: v.color * invW,
+			}
+		});
+
+		let area = edge(a.pos, b.pos, c.pos);
+		if area == 0.0 {
+			return;
+		}
+
+		let mins = a.pos.min(b.pos).min(c.pos).truncate().max(Vec2::ZERO).floor();
+		let maxs = a.pos.max(b.pos).max(c.pos).truncate().min(size).ceil();
+		if mins.x >= maxs.x || mins.y >= maxs.y {
+			return;
+		}
+		for y in mins.y as u32 .. maxs.y as u32 {
+			for x in mins.x as u32 .. maxs.x as u32 {
+				let p = Vec3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0);
+				let weights = Vec3::new(
+					edge(b.pos, c.pos, p),
+					edge(c.pos, a.pos, p),
+					edge(a.pos, b.pos, p),
+				) / area;
+				if weights.min_element() < 0.0 {
+					continue;
+				}
+
+				let depth = weights.dot(Vec3::new(a.pos.z, b.pos.z, c.pos.z));
+				let index = (y * self.frame.size.x + x) as usize;
+				// ties go to the later face, so that layers drawn over each other show up
+				if !(0.0 ..= 1.0).contains(&depth) || depth > self.depth[index] {
+					continue;
+				}
+
+				let invW = weights.dot(Vec3::new(a.invW, b.invW, c.invW));
+				let uv = (a.uvOverW * weights.x + b.uvOverW * weights.y + c.uvOverW * weights.z) /
+					invW;
+				let color = (a.colorOverW * weights.x +
+					b.colorOverW * weights.y +
+					c.colorOverW * weights.z) /
+					invW;
+				let mut texel = self.sample_frame(texId, uv);
+				if texel[3] <= alphaCutoff {
+					continue;
+				}
+				for (channel, factor) in texel.iter_mut().zip(color.to_array()) {
+					*channel = (*channel as f32 * factor).round() as u8;
+				}
+
+				if self.depthWrite {
+					self.depth[index] = depth;
+				}
+				let dest = &mut self.frame.pixels[index];
+				*dest = u32::from_le_bytes(blend(texel, dest.to_le_bytes()));
+			}
+		}
+	}
+
+	/// Looks up the texel at `uv` in whichever frame of a texture is shown, fading between frames
+	/// of interpolated animations as `main.wgsl` does.
+	fn sample_frame(&self, texId: u32, uv: Vec2) -> [u8; 4] {
+		let layer = (texId >> 24) as usize;
+		let frame = self.frames[self.texture_index(texId)];
+		let mut texel = self.sample(frame.current, uv).to_le_bytes();
+		if frame.blend > 0.0 {
+			let next = self.sample(frame.next, uv).to_le_bytes();
+			for (channel, next) in texel[.. 3].iter_mut().zip(next) {
+				let (current, next) = (*channel as f32, next as f32);
+				*channel = (current + (next - current) * frame.blend).round() as u8;
+			}
+		}
+		texel
+	}
+
+	/// Looks up the texel at `uv` in a texture, mirroring the atlas addressing in `main.wgsl`.
+	fn sample(&self, texId: u32, uv: Vec2) -> u32 {
+		let image = &self.layers[(texId >> 24) as usize];
+		let TextureRect { origin, size } = self.rects[self.texture_index(texId)];
+		let (origin, size) = (UVec2::from(origin), UVec2::from(size));
+
+		// origin swap
+		let uv = vec2(uv.x, 1.0 - uv.y);
+		let texel = (uv * size.as_vec2())
+			.floor()
+			.clamp(Vec2::ZERO, (size - 1).as_vec2())
+			.as_uvec2();
+		let pos = origin + texel;
+		image.pixels[(pos.y * image.size.x + pos.x) as usize]
+	}
+
+	/// Index of a packed texture ID's entries in `frames` and `rects`.
+	fn texture_index(&self, texId: u32) -> usize {
+		(self.layerBases[(texId >> 24) as usize] + (texId & 0xFFFFFF)) as usize
+	}
+}
+
+/// Twice the signed area of the triangle `abp`.
+fn edge(a: Vec3, b: Vec3, p: Vec3) -> f32 {
+	(b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+/// Clips a triangle against the near plane, returning a convex polygon of up to 4 vertices.
+fn clip_near(tri: &[ClipVertex; 3]) -> Vec<ClipVertex> {
+	let mut res = Vec::with_capacity(4);
+	for i in 0 .. 3 {
+		let (a, b) = (tri[i], tri[(i + 1) % 3]);
+		if a.pos.z >= 0.0 {
+			res.push(a);
+		}
+		if (a.pos.z >= 0.0) != (b.pos.z >= 0.0) {
+			let t = a.pos.z / (a.pos.z - b.pos.z);
+			res.push(ClipVertex {
+				pos: a.pos.lerp(b.pos, t),
+				uv: a.uv.lerp(b.uv, t),
+				color: a.color.lerp(b.color, t),
+			});
+		}
+	}
+	res
+}
+
+/// Alpha blends `src` over `dest`, keeping the larger alpha as the wgpu pipeline does.
+fn blend(src: [u8; 4], dest: [u8; 4]) -> [u8; 4] {
+	let alpha = src[3] as u32;
+	let mut res = [0; 4];
+	for i in 0 .. 3 {
+		res[i] = ((src[i] as u32 * alpha + dest[i] as u32 * (255 - alpha)) / 255) as u8;
+	}
+	res[3] = src[3].max(dest[3]);
+	res
+}
+
+#[test]
+fn test_rasterizer() {
+	use super::model::Vertex;
+
+	let red = Image::solid_color(UVec2::splat(2), 0xFF_0000FF);
+	let mut holey = Image::solid_color(UVec2::splat(2), 0xFF_00FF00);
+	holey.pixels[0] = 0; // top left is transparent
+	let (cartographer, layers) = Cartographer::from_images(
+		[
+			("test:red".into(), red),
+			("test:holey".into(), holey),
+		],
+		16,
+		0,
+	);
+	let texId = |name: &str| cartographer.id_for_texture(name.into()).unwrap().packed();
+
+	// a unit quad facing the camera at `z`, as two triangles
+	let quad = |z: f32, texId: u32, tintIndex: i32| {
+		let corners = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+		[0, 1, 2, 1, 3, 2].map(|i: usize| {
+			let [x, y] = corners[i];
+			FullVertex {
+				vert: Vertex {
+					pos: [x, y, z],
+					uv: [x, y],
+				},
+				texId,
+				tintIndex,
+				normal: [0.0, 0.0, 1.0],
+				flags: 0,
+				color: tint::untinted,
+			}
+		})
+	};
+	let back = quad(-0.5, texId("test:red"), -1);
+	let vertices = [back, quad(-0.25, texId("test:holey"), 0)].concat();
+
+	let camera = Mat4::orthographic_rh(0.0, 1.0, 0.0, 1.0, 0.0, 1.0);
+	let render = |quads: [&[FullVertex]; 2]| {
+		let mut raster = Rasterizer::new(&cartographer, &layers, camera, uvec2(4, 4), 0, 0);
+		for quad in quads {
+			raster.draw(quad, Mat4::IDENTITY, 0x808080, u32::MAX, 0);
+		}
+		raster.finish()
+	};
+	let image = render([&vertices[6 .. 12], &vertices[0 .. 6]]);
+	// drawn back to front or front to back, the depth test gives the same picture
+	assert_eq!(render([&vertices[0 .. 6], &vertices[6 .. 12]]).pixels, image.pixels);
+
+	// the red quad shows through the transparent corner of the (tinted) green one
+	let grey = 0xFF_008000;
+	#[rustfmt::skip]
+	assert_eq!(image.pixels, [
+		0xFF_0000FF, 0xFF_0000FF, grey, grey,
+		0xFF_0000FF, 0xFF_0000FF, grey, grey,
+		grey, grey, grey, grey,
+		grey, grey, grey, grey,
+	]);
+}
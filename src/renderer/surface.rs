@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use glam::{uvec2, UVec2};
+
+use super::model::{variant_rotation, Direction, ModelCache, Texture};
+use super::texture::{Cartographer, Image, TextureRect};
+use super::tint::BlockColors;
+use crate::loader::model::BlockStateModel;
+use crate::types::blockstate::{BlockState, BlockStateCache};
+use crate::types::parallel::par_map;
+use crate::types::shared::Shared;
+use crate::types::{BlockPos, ChunkArea, ChunkPos};
+use crate::world::Chunk;
+
+/// Average colour of what a block looks like from above.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct TopColor {
+	/// `0xAABBGGRR`
+	rgba: u32,
+
+	/// Whether the colour is multiplied by the block's tint
+	tinted: bool,
+}
+
+/// Draws a 2D map with one pixel per block column, coloured by the topmost visible block. Unlike
+/// [`super::Renderer`] this needs no geometry and no GPU.
+pub struct SurfaceMapper<'a> {
+	blockstates: &'a BlockStateCache,
+	blockColors: &'a BlockColors,
+	colors: HashMap<BlockState, TopColor>,
+}
+
+impl<'a> SurfaceMapper<'a> {
+	pub fn new(
+		blockstates: &'a BlockStateCache,
+		statemap: &HashMap<BlockState, Vec<Vec<BlockStateModel>>>,
+		models: &ModelCache,
+		cartographer: &Cartographer,
+		blockTextureLayers: &[Image],
+		blockColors: &'a BlockColors,
+	) -> Self {
+		let mut textureColors = HashMap::new();
+		let mut average = |texture| {
+			*textureColors.entry(texture).or_insert_with(|| {
+				cartographer
+					.id_for_texture(texture)
+					.map(|id| {
+						let rect = cartographer.rect_for_id(id);
+						average_color(&blockTextureLayers[id.atlas as usize], rect)
+					})
+					.unwrap_or(0)
+			})
+		};
+
+		let mut colors = HashMap::new();
+		for (&state, sets) in statemap {
+			// the first variant of the first part is close enough for a one pixel overview
+			let variant = match sets.first().and_then(|set| set.first()) {
+				Some(v) => v,
+				None => continue,
+			};
+			let model = match models.get(&variant.model) {
+				Some(v) => v,
+				None => continue,
+			};
+			let rotation = variant_rotation(
+				variant.xRotation.unwrap_or(0.0),
+				variant.yRotation.unwrap_or(0.0),
+			);
+			let top = model.faces.iter().find(|face| {
+				Direction::nearest(rotation.transform_vector3(face.direction.normal())) ==
+					Direction::Up
+			});
+			let color = match top {
+				Some(face) => {
+					let texture = match face.texture {
+						Texture::Slot(slot) => model.texture(&slot),
+						Texture::Asset(loc) => loc,
+					};
+					TopColor {
+						rgba: average(texture),
+						tinted: face.tintIndex.is_some(),
+					}
+				},
+				// fluids have no faces of their own, but a particle texture
+				None if model.faces.is_empty() && model.textureSlots.contains_key("particle") => {
+					TopColor {
+						rgba: average(model.texture("particle")),
+						tinted: true,
+					}
+				},
+				None => continue,
+			};
+			if color.rgba >> 24 != 0 {
+				colors.insert(state, color);
+			}
+		}
+
+		Self {
+			blockstates,
+			blockColors,
+			colors,
+		}
+	}
+
+	/// Draws `chunks` into an image covering `area`, with north at the top. Columns outside of the
+	/// given chunks are left transparent. With `heightShading`, blocks higher than their northern
+	/// neighbour are drawn lighter and lower ones darker, as on vanilla maps.
+	pub fn render(&self, chunks: &[Shared<Chunk>], area: ChunkArea, heightShading: bool) -> Image {
+		let diameter = ChunkPos::diameterBlocks;
+		let size = uvec2(
+			((area.max.x - area.min.x + 1) * diameter) as u32,
+			((area.max.z - area.min.z + 1) * diameter) as u32,
+		);
+		let mut image = Image::solid_color(size, 0);
+		let mut heights = vec![None; (size.x * size.y) as usize];
+
+		let surfaces = par_map(chunks, |chunk| {
+			let chunk = chunk.borrow();
+			(chunk.pos(), self.chunk_surface(&chunk))
+		});
+		for (pos, surface) in surfaces {
+			if !area.contains(pos) {
+				continue;
+			}
+			let origin = uvec2(
+				((pos.x - area.min.x) * diameter) as u32,
+				((pos.z - area.min.z) * diameter) as u32,
+			);
+			for (column, surface) in surface.into_iter().enumerate() {
+				if let Some((y, color)) = surface {
+					let column = column as u32;
+					let pixel = origin + uvec2(column % diameter as u32, column / diameter as u32);
+					let index = (pixel.y * size.x + pixel.x) as usize;
+					image.pixels[index] = color;
+					heights[index] = Some(y);
+				}
+			}
+		}
+
+		if heightShading {
+			for index in size.x as usize .. heights.len() {
+				let (y, northY) = match (heights[index], heights[index - size.x as usize]) {
+					(Some(y), Some(northY)) => (y, northY),
+					_ => continue,
+				};
+				let shade = match y.cmp(&northY) {
+					std::cmp::Ordering::Greater => 255,
+					std::cmp::Ordering::Equal => 220,
+					std::cmp::Ordering::Less => 180,
+				};
+				image.pixels[index] = multiply(image.pixels[index], [shade; 3]);
+			}
+		}
+		image
+	}
+
+	/// Finds the height and colour of the topmost visible block of each column, in ZX order.
+	fn chunk_surface(&self, chunk: &Chunk) -> Vec<Option<(i32, u32)>> {
+		let diameter = ChunkPos::diameterBlocks;
+		let mut surface = vec![None; diameter.pow(2) as usize];
+		let mut remaining = surface.len();
+		let minBlock = chunk.pos().min_block();
+
+		for sectionY in chunk.sections().rev() {
+			let section = match chunk.get_section(sectionY) {
+				Some(v) => v,
+				None => continue,
+			};
+			let section = section.borrow();
+			for (column, surface) in surface.iter_mut().enumerate() {
+				if surface.is_some() {
+					continue;
+				}
+				let (x, z) = (column as i32 % diameter, column as i32 / diameter);
+				for dy in (0 .. diameter).rev() {
+					let pos = BlockPos::new(
+						minBlock.x + x,
+						sectionY as i32 * diameter + dy,
+						minBlock.z + z,
+					);
+					let state = self.blockstates.normalize(section.get_block(pos));
+					let color = match self.colors.get(&state) {
+						Some(v) => v,
+						None => continue,
+					};
+					let rgba = if color.tinted {
+						let tint = self.blockColors.tint_for(state, section.get_biome(pos));
+						let [b, g, r, _] = tint.to_le_bytes();
+						multiply(color.rgba, [r, g, b])
+					} else {
+						color.rgba
+					};
+					*surface = Some((pos.y, rgba | 0xFF << 24));
+					remaining -= 1;
+					break;
+				}
+			}
+			if remaining == 0 {
+				break;
+			}
+		}
+		surface
+	}
+}
+
+/// Averages the opaque texels of the texture at `rect` in an atlas layer.
+fn average_color(layer: &Image, rect: TextureRect) -> u32 {
+	let TextureRect { origin, size } = rect;
+	let (mut sum, mut count) = ([0u64; 3], 0u64);
+	for y in 0 .. size[1] {
+		for x in 0 .. size[0] {
+			let pos = UVec2::from(origin) + uvec2(x, y);
+			let [r, g, b, a] = layer.pixels[(pos.y * layer.size.x + pos.x) as usize].to_le_bytes();
+			if a <= 5 {
+				continue;
+			}
+			for (sum, channel) in sum.iter_mut().zip([r, g, b]) {
+				*sum += channel as u64;
+			}
+			count += 1;
+		}
+	}
+	if count == 0 {
+		return 0;
+	}
+	let [r, g, b] = sum.map(|v| (v / count) as u8);
+	u32::from_le_bytes([r, g, b, 0xFF])
+}
+
+/// Multiplies the colour channels of an `0xAABBGGRR` colour by `factors` out of 255.
+fn multiply(rgba: u32, factors: [u8; 3]) -> u32 {
+	let mut bytes = rgba.to_le_bytes();
+	for (channel, factor) in bytes.iter_mut().zip(factors) {
+		*channel = (*channel as u32 * factor as u32 / 255) as u8;
+	}
+	u32::from_le_bytes(bytes)
+}
+
+#[test]
+fn test_average_color() {
+	let mut layer = Image::solid_color(uvec2(4, 2), 0);
+	// right half of the layer: two red texels, one blue and one transparent
+	layer.pixels[2] = 0xFF_0000FF;
+	layer.pixels[3] = 0xFF_0000FF;
+	layer.pixels[6] = 0xFF_FF0000;
+	let rect = |x: u32, height: u32| TextureRect {
+		origin: [x, 0],
+		size: [2, height],
+	};
+	assert_eq!(average_color(&layer, rect(2, 2)), 0xFF_5500AA);
+	assert_eq!(average_color(&layer, rect(0, 2)), 0);
+	// a non-square texture covering only the top row
+	assert_eq!(average_color(&layer, rect(2, 1)), 0xFF_0000FF);
+
+	assert_eq!(multiply(0xFF_8040FF, [255, 128, 0]), 0xFF_0020FF);
+}
@@ -1,14 +1,24 @@
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::Path;
 
 use anyhow::Context;
+use bytemuck::{Pod, Zeroable};
 use glam::{ivec2, uvec2, IVec2, UVec2};
 
 use super::model::ModelCache;
 use crate::jarfs::JarFS;
+use crate::loader::texture_meta::{AnimationMeta, TextureMeta};
 use crate::types::resource_location::ResourceKind;
 use crate::types::ResourceLocation;
 
+/// Mip levels generated below full size for atlas layers loaded from resource packs. Vanilla's
+/// default, which takes 16x16 textures down to a single texel.
+pub const defaultMipLevels: u32 = 4;
+
+/// Alpha at or below which fragments are discarded, both by `main.wgsl` and the software
+/// rasterizer. Mip levels of cutout textures keep the share of texels above it the same.
+pub const alphaCutoff: u8 = 5;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct TextureId {
 	pub atlas: u8,
@@ -22,6 +32,49 @@ impl TextureId {
 	}
 }
 
+/// What to draw in place of a texture at some point in time, laid out as `TextureFrame` in
+/// `main.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Zeroable, Pod)]
+pub struct TextureFrame {
+	/// Packed [`TextureId`] of the frame being shown
+	pub current: u32,
+	/// Packed [`TextureId`] of the frame after it
+	pub next: u32,
+	/// How far to fade from `current` to `next`, for interpolated animations
+	pub blend: f32,
+}
+
+/// A texture whose frames were each registered in the atlas as a texture of their own.
+#[derive(Clone, Debug)]
+pub struct Animation {
+	/// Frames in the order they're shown, with how many ticks each is shown for
+	pub frames: Vec<(TextureId, u32)>,
+	pub interpolate: bool,
+}
+
+impl Animation {
+	/// The frame shown at game tick `tick`, starting with the first frame at tick 0.
+	pub fn frame_at(&self, tick: u64) -> TextureFrame {
+		let period: u64 = self.frames.iter().map(|&(_, time)| time as u64).sum();
+		let mut remaining = tick % period;
+		for (i, &(id, time)) in self.frames.iter().enumerate() {
+			let time = time as u64;
+			if remaining < time {
+				let next = self.frames[(i + 1) % self.frames.len()].0;
+				let blend = if self.interpolate { remaining as f32 / time as f32 } else { 0.0 };
+				return TextureFrame {
+					current: id.packed(),
+					next: next.packed(),
+					blend,
+				};
+			}
+			remaining -= time;
+		}
+		unreachable!("tick is within the animation's period")
+	}
+}
+
 #[derive(Clone, Copy, Debug)]
 struct Rect {
 	mins: IVec2,
@@ -41,55 +94,133 @@ impl Rect {
 	}
 }
 
+/// Where a texture lies in its atlas layer, in pixels, laid out as `TextureRect` in `main.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Zeroable, Pod)]
+pub struct TextureRect {
+	pub origin: [u32; 2],
+	pub size: [u32; 2],
+}
+
+/// Packs rectangles into a square atlas layer, placing each as low and then as far left as it
+/// goes on the skyline formed by the tops of those placed before it.
 #[derive(Debug)]
-struct Atlas {
-	id: u8,
-	texDiameter: usize,
-	entries: Vec<ResourceLocation>,
+struct Skyline {
+	diameter: u32,
+	/// Spans of the skyline from left to right, as `(x, height, width)`, covering the whole layer
+	segments: Vec<(u32, u32, u32)>,
 }
 
-impl Atlas {
-	fn new(id: u8, texDiameter: usize) -> Self {
+impl Skyline {
+	fn new(diameter: u32) -> Self {
 		Self {
-			id,
-			texDiameter,
-			entries: vec![],
+			diameter,
+			segments: vec![(0, 0, diameter)],
 		}
 	}
 
-	fn max_entries(&self, maxTextureDiameter: usize) -> usize {
-		let maxSize = UVec2::splat((maxTextureDiameter / self.texDiameter) as u32);
-		(maxSize.x * maxSize.y) as usize
-	}
+	/// Returns where a rectangle of `size` was placed, if there was room for it.
+	fn insert(&mut self, size: UVec2) -> Option<UVec2> {
+		let (index, origin) = (0 .. self.segments.len())
+			.filter_map(|index| Some((index, self.fit(index, size)?)))
+			.min_by_key(|&(_, origin)| (origin.y, origin.x))?;
 
-	fn full(&self, maxTextureDiameter: usize) -> bool {
-		self.entries.len() >= self.max_entries(maxTextureDiameter)
+		let right = origin.x + size.x;
+		self.segments.insert(index, (origin.x, origin.y + size.y, size.x));
+		// trim the segments that are now underneath
+		let next = index + 1;
+		while next < self.segments.len() {
+			let (x, height, width) = self.segments[next];
+			if x >= right {
+				break;
+			}
+			if x + width <= right {
+				self.segments.remove(next);
+			} else {
+				self.segments[next] = (right, height, x + width - right);
+				break;
+			}
+		}
+		// and merge neighbours at the same height
+		self.segments.dedup_by(|right, left| {
+			let merge = left.1 == right.1;
+			if merge {
+				left.2 += right.2;
+			}
+			merge
+		});
+		Some(origin)
 	}
 
-	fn merged_size(&self, maxTextureDiameter: usize) -> UVec2 {
-		let width = (maxTextureDiameter / self.texDiameter) as u32;
-		let len = self.entries.len() as u32;
-		let y = len / width;
-		let x = if y == 0 { len % width } else { width };
-		let res = uvec2(x, y) * UVec2::splat(self.texDiameter as u32);
-		// powers of two required for mipmapping
-		let res = uvec2(res.x.next_power_of_two(), res.y.next_power_of_two());
-		assert!(res.x <= maxTextureDiameter as u32);
-		assert!(res.y <= maxTextureDiameter as u32);
-		res
+	/// Where a rectangle of `size` would go with its left edge at the start of segment `index`.
+	fn fit(&self, index: usize, size: UVec2) -> Option<UVec2> {
+		let x = self.segments[index].0;
+		if x + size.x > self.diameter {
+			return None;
+		}
+		let y = self.segments[index ..]
+			.iter()
+			.take_while(|&&(segmentX, ..)| segmentX < x + size.x)
+			.map(|&(_, height, _)| height)
+			.max()?;
+		(y + size.y <= self.diameter).then_some(uvec2(x, y))
 	}
+}
 
-	fn origin(&self, maxTextureDiameter: usize, tid: u32) -> UVec2 {
-		let width = (maxTextureDiameter / self.texDiameter) as u32;
-		uvec2(tid % width, tid / width) * UVec2::splat(self.texDiameter as u32)
+/// Packs rectangles of `sizes` into as many square layers `diameter` pixels across as needed,
+/// returning each one's layer and origin. Fails if more than `maxLayers` would be needed.
+fn pack(sizes: &[UVec2], diameter: u32, maxLayers: usize) -> Option<Vec<(u8, UVec2)>> {
+	let mut layers: Vec<Skyline> = vec![];
+	let mut res = Vec::with_capacity(sizes.len());
+	for &size in sizes {
+		let placed = layers
+			.iter_mut()
+			.enumerate()
+			.find_map(|(layer, skyline)| Some((layer, skyline.insert(size)?)));
+		let (layer, origin) = match placed {
+			Some(v) => v,
+			None => {
+				if layers.len() >= maxLayers {
+					return None;
+				}
+				let mut skyline = Skyline::new(diameter);
+				let origin = skyline.insert(size)?;
+				layers.push(skyline);
+				(layers.len() - 1, origin)
+			},
+		};
+		res.push((layer as u8, origin));
 	}
+	Some(res)
+}
+
+/// Texels of clamped edges around each texture, so that filtering at every one of `mipLevels` only
+/// reaches texels of the same texture.
+fn cell_padding(mipLevels: u32) -> u32 {
+	(1 << mipLevels) / 2
+}
+
+/// Size of the space a texture of `size` takes up in an atlas layer with its padding, which is a
+/// whole number of texels at every one of `mipLevels`.
+fn cell_size(size: UVec2, mipLevels: u32) -> UVec2 {
+	let alignment = 1 << mipLevels;
+	let padded = size + UVec2::splat(cell_padding(mipLevels) * 2);
+	(padded + UVec2::splat(alignment - 1)) / alignment * alignment
 }
 
 #[derive(Debug)]
 pub struct Cartographer {
 	pub size: UVec2,
 	pub textures: HashMap<ResourceLocation, TextureId>,
-	elementDiameters: Vec<u32>,
+	/// Textures with any pixels that aren't fully opaque
+	pub translucent: HashSet<ResourceLocation>,
+	/// Animated textures by the location of their first frame, which is also registered under it
+	pub animations: HashMap<ResourceLocation, Animation>,
+	/// Every texture's place in its layer, indexed like [`Self::frames_at`]
+	rects: Vec<TextureRect>,
+	/// Number of textures in the atlas layers before each one
+	layerBases: Vec<u32>,
+	mipLevels: u32,
 }
 
 impl Cartographer {
@@ -100,39 +231,20 @@ impl Cartographer {
 	) -> anyhow::Result<(Self, Vec<Image>)> {
 		let limits = device.limits();
 		assert!(limits.max_texture_array_layers >= u8::MAX as u32);
-		let maxTextureDiameter = limits.max_texture_dimension_3d as usize;
-		let mut images = HashMap::new();
-		let mut textures = HashMap::new();
-		let mut atlases: Vec<Atlas> = Vec::with_capacity(u8::MAX as usize);
-
-		let mut add_texture = |loc: ResourceLocation, img: Image| {
-			let diameter = img.size.x as usize;
-			let atlas = if let Some(atlas) = atlases
-				.iter_mut()
-				.filter(|a| a.texDiameter == diameter && !a.full(maxTextureDiameter))
-				.next()
-			{
-				atlas
-			} else {
-				let id = atlases.len();
-				assert!(id < u8::MAX as usize);
-				atlases.push(Atlas::new(id as u8, diameter));
-				&mut atlases[id]
-			};
-			let id = atlas.entries.len();
-			atlas.entries.push(loc);
-
-			let tid = TextureId {
-				atlas: atlas.id,
-				texture: id as u32,
-			};
-			textures.insert(loc, tid);
-			images.insert(loc, img);
-		};
+		Self::load_with_max_size(fs, models, limits.max_texture_dimension_3d as usize)
+	}
 
+	/// Loads every texture used by `models` into atlas layers no larger than
+	/// `maxTextureDiameter` pixels across.
+	pub fn load_with_max_size(
+		fs: &JarFS,
+		models: &ModelCache,
+		maxTextureDiameter: usize,
+	) -> anyhow::Result<(Self, Vec<Image>)> {
 		let missingTex = "cuview:missing_texture".into();
 		let missingTexImage = missing_texture(0xFF_FF00FF);
-		add_texture(missingTex, missingTexImage.clone());
+		let mut textures = vec![(missingTex, missingTexImage.clone())];
+		let mut animations = vec![];
 
 		for loc in models
 			.all_block_textures()
@@ -140,80 +252,179 @@ impl Cartographer {
 			.collect::<BTreeSet<_>>()
 		{
 			let path = loc.into_path(ResourceKind::Texture);
-			let mut image =
+			let image =
 				Image::from_jarfs(fs, &path).unwrap_or_else(|_| missingTexImage.clone());
 
-			let UVec2 {
-				x: width,
-				y: height,
-			} = image.size;
-			if width != height {
-				let mut path = path;
-				path.set_extension(ResourceKind::TextureMeta.extension());
-				if let Ok(json) = fs.read_text(&path) {
-					path.set_extension("");
-					// TODO: also actually verify that the json specifies an animation
-					assert_eq!(
-						height % width,
-						0,
-						"malformed animated texture: {path:?} is {width}x{height}"
-					);
-
-					// crop out only first frame.
-					// TODO: in future this should instead register all frames, to be chosen from
-					// randomly per block
-					image = image.crop(UVec2::splat(width));
+			let mut metaPath = path.clone();
+			metaPath.set_extension(ResourceKind::TextureMeta.extension());
+			let animation = fs.read_text(&metaPath).ok().and_then(|json| {
+				match serde_json::from_str::<TextureMeta>(&json) {
+					Ok(meta) => meta.animation,
+					Err(err) => {
+						eprintln!("malformed texture metadata {metaPath:?}: {err}");
+						None
+					},
+				}
+			});
+			if let Some(animation) = animation {
+				let frames = animation_frames(&image, &animation);
+				if frames.is_empty() {
+					let UVec2 { x, y } = image.size;
+					eprintln!("malformed animated texture: {path:?} is {x}x{y}");
 				} else {
-					path.set_extension("");
-					let srcModels: BTreeSet<_> =
-						models.models_using_texture(loc).into_iter().collect();
-					eprintln!(
-						"texture {path:?} is not square ({width}x{height}, used by models: \
-						 {srcModels:?})"
-					);
-
-					// TODO: properly handling this will require more sophisticated texture packing
-					// and should probably just spill any models using such textures into
-					// the (future) .obj pipeline
-					image = image.crop(UVec2::splat(width.min(height)));
+					// frames other than the first are only registered if they're ever shown
+					let schedule = animation.schedule(frames.len() as u32);
+					let frameLocs: Vec<_> = (0 .. frames.len())
+						.map(|index| match index {
+							0 => loc,
+							_ => {
+								let name = format!("{}#{index}", loc.name);
+								ResourceLocation::new(&loc.modid, &name)
+							},
+						})
+						.collect();
+					let shown: BTreeSet<_> = schedule.iter().map(|&(index, _)| index).collect();
+					for (index, frame) in frames.into_iter().enumerate() {
+						if index == 0 || shown.contains(&(index as u32)) {
+							textures.push((frameLocs[index], frame));
+						}
+					}
+					if schedule.len() > 1 {
+						let schedule: Vec<_> = schedule
+							.into_iter()
+							.map(|(index, time)| (frameLocs[index as usize], time))
+							.collect();
+						animations.push((loc, schedule, animation.interpolate));
+					}
+					continue;
 				}
 			}
 
-			add_texture(loc, image);
+			textures.push((loc, image));
+		}
+
+		let (mut cartographer, layers) =
+			Self::from_images(textures, maxTextureDiameter, defaultMipLevels);
+		for (loc, schedule, interpolate) in animations {
+			let frames = schedule
+				.into_iter()
+				.map(|(frameLoc, time)| (cartographer.textures[&frameLoc], time))
+				.collect();
+			cartographer.animations.insert(loc, Animation {
+				frames,
+				interpolate,
+			});
+		}
+		Ok((cartographer, layers))
+	}
+
+	/// Packs textures of any size into atlas layers no larger than `maxTextureDiameter` pixels
+	/// across. Layers are as small as fits every texture, or that large if more than one is needed.
+	/// Textures are padded for [`Self::mip_chain`] to go down `mipLevels` levels.
+	pub fn from_images(
+		images: impl IntoIterator<Item = (ResourceLocation, Image)>,
+		maxTextureDiameter: usize,
+		mipLevels: u32,
+	) -> (Self, Vec<Image>) {
+		let maxDiameter = maxTextureDiameter as u32;
+		let images: BTreeMap<_, _> = images.into_iter().collect();
+		let mut images: Vec<_> = images.into_iter().collect();
+		for (loc, image) in &mut images {
+			let fits =
+				|image: &Image| cell_size(image.size, mipLevels).max_element() <= maxDiameter;
+			if !fits(image) {
+				eprintln!("texture {loc} is larger than an atlas layer, downscaling it");
+			}
+			while !fits(image) {
+				assert!(image.size.min_element() > 1, "layers too small for {mipLevels} mips");
+				*image = image.downscale_half();
+			}
 		}
+		// tallest first packs tightest; stable, so IDs still don't depend on hashing order
+		images.sort_by_key(|(_, image)| std::cmp::Reverse((image.size.y, image.size.x)));
 
-		let diameters: Vec<_> = atlases.iter().map(|a| a.texDiameter as u32).collect();
-		let layerSize = atlases
+		let padding = UVec2::splat(cell_padding(mipLevels));
+		let sizes: Vec<_> =
+			images.iter().map(|(_, image)| cell_size(image.size, mipLevels)).collect();
+		let area: f64 = sizes.iter().map(|size| size.x as f64 * size.y as f64).sum();
+		let largest = sizes.iter().map(|size| size.max_element()).max().unwrap_or(1);
+		// powers of two, so that every mip level halves evenly
+		let mut diameter = (area.sqrt().ceil() as u32).max(largest).next_power_of_two();
+		let placements = loop {
+			let maxLayers = if diameter < maxDiameter { 1 } else { u8::MAX as usize };
+			match pack(&sizes, diameter.min(maxDiameter), maxLayers) {
+				Some(v) => break v,
+				None => {
+					assert!(diameter < maxDiameter, "too many textures for {maxLayers} layers");
+					diameter *= 2;
+				},
+			}
+		};
+		let diameter = diameter.min(maxDiameter);
+
+		let layerCount = placements.iter().map(|&(layer, _)| layer as usize + 1).max();
+		let layerCount = layerCount.unwrap_or(0);
+		let height = placements
 			.iter()
-			.map(|a| a.merged_size(maxTextureDiameter))
-			.fold(UVec2::splat(0), |res, v| {
-				uvec2(res.x.max(v.x), res.y.max(v.y))
+			.zip(&sizes)
+			.map(|(&(_, origin), size)| origin.y + size.y)
+			.max()
+			.unwrap_or(1)
+			.next_power_of_two();
+		let layerSize = uvec2(diameter, height);
+
+		let mut textures = HashMap::new();
+		let mut translucent = HashSet::new();
+		let mut layers = vec![Image::empty(layerSize); layerCount];
+		let mut layerRects = vec![vec![]; layerCount];
+		for (((loc, image), (layer, cellOrigin)), cellSize) in
+			images.into_iter().zip(placements).zip(sizes)
+		{
+			let cell = image.clamp_extended(padding, cellSize);
+			layers[layer as usize].blit_from(&cell, cellOrigin, None);
+			let origin = cellOrigin + padding;
+			let rects = &mut layerRects[layer as usize];
+			textures.insert(loc, TextureId {
+				atlas: layer,
+				texture: rects.len() as u32,
+			});
+			rects.push(TextureRect {
+				origin: origin.to_array(),
+				size: image.size.to_array(),
 			});
-		let mut layers = Vec::with_capacity(atlases.len());
-		for (aid, atlas) in atlases.iter().enumerate() {
-			let mut layer = Image::empty(layerSize);
-			let destSize = layer.size;
-			for (tid, tex) in atlas.entries.iter().copied().enumerate() {
-				let srcImage = images.get(&tex).unwrap();
-				let srcSize = srcImage.size;
-				let origin = atlas.origin(maxTextureDiameter, tid as u32);
-				layer.blit_from(srcImage, origin, None);
+			if image.pixels.iter().any(|pixel| pixel >> 24 != 0xFF) {
+				translucent.insert(loc);
 			}
-			layers.push(layer);
 		}
 
+		let layerBases = layerRects
+			.iter()
+			.scan(0, |base, rects| {
+				let res = *base;
+				*base += rects.len() as u32;
+				Some(res)
+			})
+			.collect();
 		let new = Self {
 			size: layerSize,
 			textures,
-			elementDiameters: diameters,
+			translucent,
+			animations: HashMap::new(),
+			rects: layerRects.concat(),
+			layerBases,
+			mipLevels,
 		};
-		Ok((new, layers))
+		(new, layers)
 	}
 
 	pub fn id_for_texture(&self, tex: ResourceLocation) -> Option<TextureId> {
 		self.textures.get(&tex).copied()
 	}
 
+	pub fn is_opaque(&self, tex: ResourceLocation) -> bool {
+		!self.translucent.contains(&tex)
+	}
+
 	pub fn texture_for_id(&self, id: TextureId) -> Option<ResourceLocation> {
 		let TextureId { atlas, texture } = id;
 		self.textures
@@ -224,14 +435,117 @@ impl Cartographer {
 	}
 
 	pub fn layers(&self) -> usize {
-		self.elementDiameters.len()
+		self.layerBases.len()
+	}
+
+	/// Where every texture lies in its layer, indexed like [`Self::frames_at`].
+	pub fn texture_rects(&self) -> &[TextureRect] {
+		&self.rects
+	}
+
+	pub fn rect_for_id(&self, id: TextureId) -> TextureRect {
+		self.rects[self.frame_index(id)]
+	}
+
+	/// Number of mip levels below full size that textures are padded for.
+	pub fn mip_levels(&self) -> u32 {
+		self.mipLevels
 	}
 
-	pub fn element_diameters(&self) -> &[u32] {
-		&self.elementDiameters
+	/// Returns the mip levels below full size of atlas layer `layer`, whose full size `image` is.
+	/// Each texture is downsampled on its own, and cutout textures like leaves keep as much of
+	/// them visible as at full size rather than fading out.
+	pub fn mip_chain(&self, layer: u8, image: &Image) -> Vec<Image> {
+		let start = self.layerBases[layer as usize] as usize;
+		let end = self
+			.layerBases
+			.get(layer as usize + 1)
+			.map_or(self.rects.len(), |&base| base as usize);
+		let padding = UVec2::splat(cell_padding(self.mipLevels));
+		let cells: Vec<_> = self.rects[start .. end]
+			.iter()
+			.map(|rect| {
+				let origin = UVec2::from(rect.origin) - padding;
+				let size = cell_size(UVec2::from(rect.size), self.mipLevels);
+				let texture = image.sub_image(UVec2::from(rect.origin), UVec2::from(rect.size));
+				let coverage = texture.is_cutout().then(|| texture.alpha_coverage(alphaCutoff));
+				(origin, size, UVec2::from(rect.size), coverage)
+			})
+			.collect();
+
+		let mut levels: Vec<Image> = Vec::with_capacity(self.mipLevels as usize);
+		for level in 1 ..= self.mipLevels {
+			let above = levels.last().unwrap_or(image);
+			let mut dest = Image::solid_color(above.size / 2, 0);
+			let scale = 1 << level;
+			for &(origin, size, textureSize, coverage) in &cells {
+				let cell = above.sub_image(origin * 2 / scale, size * 2 / scale);
+				let mut cell = cell.downscale_half();
+				if let Some(coverage) = coverage {
+					// judged by the texture alone, as its padding repeats its edges
+					let textureSize = (textureSize / scale).max(UVec2::ONE);
+					let texture = cell.sub_image(padding / scale, textureSize);
+					cell.scale_alpha(texture.alpha_scale_for_coverage(coverage, alphaCutoff));
+				}
+				dest.blit_from(&cell, origin / scale, None);
+			}
+			levels.push(dest);
+		}
+		levels
+	}
+
+	/// Index of each atlas layer's first texture in [`Self::frames_at`].
+	pub fn layer_bases(&self) -> &[u32] {
+		&self.layerBases
+	}
+
+	/// What to draw in place of every texture at game tick `tick`, which is the texture itself
+	/// unless it's animated. A texture's entry is at its layer's base plus its index in the layer.
+	pub fn frames_at(&self, tick: u64) -> Vec<TextureFrame> {
+		let mut frames = vec![
+			TextureFrame {
+				current: 0,
+				next: 0,
+				blend: 0.0,
+			};
+			self.textures.len()
+		];
+		for id in self.textures.values() {
+			let packed = id.packed();
+			frames[self.frame_index(*id)] = TextureFrame {
+				current: packed,
+				next: packed,
+				blend: 0.0,
+			};
+		}
+		for (loc, animation) in &self.animations {
+			frames[self.frame_index(self.textures[loc])] = animation.frame_at(tick);
+		}
+		frames
+	}
+
+	fn frame_index(&self, id: TextureId) -> usize {
+		(self.layerBases[id.atlas as usize] + id.texture) as usize
 	}
 }
 
+/// Splits an animated texture into its frames, left to right then top to bottom. Returns nothing
+/// if the texture doesn't fit a single frame.
+fn animation_frames(image: &Image, animation: &AnimationMeta) -> Vec<Image> {
+	let size = animation.frame_size(image.size);
+	if size.x == 0 || size.y == 0 {
+		return vec![];
+	}
+	let counts = image.size / size;
+	let mut frames = Vec::with_capacity((counts.x * counts.y) as usize);
+	for y in 0 .. counts.y {
+		for x in 0 .. counts.x {
+			frames.push(image.sub_image(uvec2(x, y) * size, size));
+		}
+	}
+	frames
+}
+
 fn missing_texture(color: u32) -> Image {
 	const diameter: u32 = 16;
 	let color = Image::solid_color(UVec2::splat(diameter / 2), color);
@@ -330,6 +644,11 @@ impl Image {
 		})
 	}
 
+	pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+		let bytes = std::fs::read(path)?;
+		Self::from_png_bytes(&bytes, path)
+	}
+
 	pub fn save_to_file(&self, path: &Path) -> anyhow::Result<()> {
 		let mut file = std::fs::File::create(path)?;
 		let mut encoder = png::Encoder::new(&mut file, self.size.x, self.size.y);
@@ -341,6 +660,26 @@ impl Image {
 		Ok(())
 	}
 
+	/// Saves `frames` as an animated PNG which loops forever, showing each frame for `delay`
+	/// seconds as a fraction `(numerator, denominator)`.
+	pub fn save_animation(frames: &[Self], path: &Path, delay: (u16, u16)) -> anyhow::Result<()> {
+		let size = frames.first().context("an animation needs at least one frame")?.size;
+		let mut file = std::fs::File::create(path)?;
+		let mut encoder = png::Encoder::new(&mut file, size.x, size.y);
+		encoder.set_color(png::ColorType::Rgba);
+		encoder.set_depth(png::BitDepth::Eight);
+		encoder.set_animated(frames.len() as u32, 0)?;
+		encoder.set_frame_delay(delay.0, delay.1)?;
+
+		let mut writer = encoder.write_header()?;
+		for frame in frames {
+			anyhow::ensure!(frame.size == size, "animation frames differ in size");
+			writer.write_image_data(bytemuck::cast_slice(&frame.pixels))?;
+		}
+		writer.finish()?;
+		Ok(())
+	}
+
 	pub fn blit_from(&mut self, src: &Self, destOrigin: UVec2, srcSize: Option<UVec2>) {
 		let size = srcSize.unwrap_or(src.size);
 		assert!(size.x <= src.size.x && size.y <= src.size.y);
@@ -367,6 +706,109 @@ impl Image {
 		new
 	}
 
+	/// Copies out the `size` pixels big part of the image starting at `origin`.
+	pub fn sub_image(&self, origin: UVec2, size: UVec2) -> Self {
+		assert!(origin.x + size.x <= self.size.x && origin.y + size.y <= self.size.y);
+		let mut pixels = Vec::with_capacity((size.x * size.y) as usize);
+		for y in origin.y .. origin.y + size.y {
+			let start = (y * self.size.x + origin.x) as usize;
+			pixels.extend_from_slice(&self.pixels[start .. start + size.x as usize]);
+		}
+		Self { size, pixels }
+	}
+
+	/// Returns an image of `size` with this one at `offset`, surrounded by copies of its edges.
+	pub fn clamp_extended(&self, offset: UVec2, size: UVec2) -> Self {
+		let mut pixels = Vec::with_capacity((size.x * size.y) as usize);
+		for y in 0 .. size.y {
+			let srcY = y.saturating_sub(offset.y).min(self.size.y - 1);
+			for x in 0 .. size.x {
+				let srcX = x.saturating_sub(offset.x).min(self.size.x - 1);
+				pixels.push(self.pixels[(srcY * self.size.x + srcX) as usize]);
+			}
+		}
+		Self { size, pixels }
+	}
+
+	/// Whether every pixel is either fully transparent or fully opaque, and some of each.
+	pub fn is_cutout(&self) -> bool {
+		let alphas: HashSet<_> = self.pixels.iter().map(|pixel| pixel >> 24).collect();
+		alphas.len() == 2 && alphas.contains(&0) && alphas.contains(&0xFF)
+	}
+
+	/// Share of pixels with an alpha above `cutoff`.
+	pub fn alpha_coverage(&self, cutoff: u8) -> f32 {
+		let covered = self.pixels.iter().filter(|&&pixel| pixel >> 24 > cutoff as u32).count();
+		covered as f32 / self.pixels.len() as f32
+	}
+
+	/// A factor for alpha which puts `coverage` of the pixels above `cutoff`, or as few more as
+	/// can be. Alpha is lowered no further than that takes, so that pixels which can't be told
+	/// apart by their alpha aren't all faded away.
+	pub fn alpha_scale_for_coverage(&self, coverage: f32, cutoff: u8) -> f32 {
+		let coverageAt = |scale: f32| {
+			let covered = self.pixels.iter().filter(|&&pixel| scaled_alpha(pixel, scale) > cutoff);
+			covered.count() as f32 / self.pixels.len() as f32
+		};
+		let (mut low, mut high) = (0.0, 255.0);
+		for _ in 0 .. 24 {
+			let mid = (low + high) / 2.0;
+			if coverageAt(mid) < coverage {
+				low = mid;
+			} else {
+				high = mid;
+			}
+		}
+		let reached = coverageAt(high);
+		if high >= 1.0 || coverageAt(1.0) <= reached {
+			return high.max(1.0);
+		}
+
+		// the largest factor below 1 which keeps no more pixels than the smallest one
+		let mut low = high;
+		let mut high = 1.0;
+		for _ in 0 .. 24 {
+			let mid = (low + high) / 2.0;
+			if coverageAt(mid) <= reached {
+				low = mid;
+			} else {
+				high = mid;
+			}
+		}
+		low
+	}
+
+	pub fn scale_alpha(&mut self, scale: f32) {
+		for pixel in &mut self.pixels {
+			*pixel = (scaled_alpha(*pixel, scale) as u32) << 24 | *pixel & 0xFFFFFF;
+		}
+	}
+
+	/// Halves the image's size, averaging each 2x2 block of pixels weighted by their alpha so that
+	/// transparent pixels don't darken their neighbours.
+	pub fn downscale_half(&self) -> Self {
+		let size = self.size / 2;
+		let mut pixels = Vec::with_capacity((size.x * size.y) as usize);
+		for y in 0 .. size.y {
+			for x in 0 .. size.x {
+				let (mut rgb, mut alpha) = ([0u32; 3], 0u32);
+				for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+					let pos = uvec2(x * 2 + dx, y * 2 + dy);
+					let [r, g, b, a] = self.pixels[(pos.y * self.size.x + pos.x) as usize]
+						.to_le_bytes()
+						.map(u32::from);
+					for (sum, channel) in rgb.iter_mut().zip([r, g, b]) {
+						*sum += channel * a;
+					}
+					alpha += a;
+				}
+				let [r, g, b] = rgb.map(|sum| sum.checked_div(alpha).unwrap_or(0) as u8);
+				pixels.push(u32::from_le_bytes([r, g, b, (alpha / 4) as u8]));
+			}
+		}
+		Self { size, pixels }
+	}
+
 	pub fn flip_y(&mut self) {
 		let [width, height] = self.size.to_array().map(|v| v as usize);
 		if height < 2 {
@@ -384,6 +826,11 @@ impl Image {
 	}
 }
 
+/// Alpha of an `0xAABBGGRR` pixel multiplied by `scale`.
+fn scaled_alpha(pixel: u32, scale: f32) -> u8 {
+	((pixel >> 24) as f32 * scale).round().min(255.0) as u8
+}
+
 impl std::fmt::Debug for Image {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.debug_struct("Image").field("size", &self.size).finish()
@@ -441,4 +888,164 @@ fn test_image() {
 		(bytemuck::cast_slice_mut::<u32, [u32; 2]>(&mut pixels)).reverse();
 		assert_eq!(img.pixels, pixels);
 	}
+
+	// a transparent pixel doesn't darken the average, only lowers its alpha
+	let img = Image {
+		size: uvec2(2, 2),
+		pixels: vec![0xFF_0000FF, 0xFF_0000FF, 0xFF_00FF00, 0],
+	};
+	assert_eq!(img.downscale_half().pixels, [0xBF_0055AA]);
+}
+
+#[test]
+fn test_animation() {
+	let frames = Image {
+		size: uvec2(2, 3),
+		pixels: vec![1, 1, 2, 2, 3, 3],
+	};
+	let meta = serde_json::from_str(r#"{"width": 2, "height": 1}"#).unwrap();
+	let frames = animation_frames(&frames, &meta);
+	assert_eq!(frames.iter().map(|frame| frame.pixels[0]).collect::<Vec<_>>(), [1, 2, 3]);
+
+	let (mut cartographer, _) = Cartographer::from_images(
+		[
+			("test:still".into(), Image::solid_color(UVec2::splat(2), 0)),
+			("test:big".into(), Image::solid_color(UVec2::splat(4), 0)),
+			("test:anim".into(), frames[0].crop(UVec2::splat(1))),
+			("test:anim#1".into(), frames[1].crop(UVec2::splat(1))),
+		],
+		16,
+		0,
+	);
+	let first = cartographer.textures[&"test:anim".into()];
+	let second = cartographer.textures[&"test:anim#1".into()];
+	cartographer.animations.insert("test:anim".into(), Animation {
+		frames: vec![(first, 2), (second, 4)],
+		interpolate: true,
+	});
+
+	let frame = |tick: u64| cartographer.frames_at(tick)[cartographer.frame_index(first)];
+	let at = |current: TextureId, next: TextureId, blend: f32| TextureFrame {
+		current: current.packed(),
+		next: next.packed(),
+		blend,
+	};
+	assert_eq!(frame(0), at(first, second, 0.0));
+	assert_eq!(frame(1), at(first, second, 0.5));
+	assert_eq!(frame(3), at(second, first, 0.25));
+	assert_eq!(frame(6), frame(0));
+
+	// textures which aren't animated stand for themselves
+	for name in ["test:still", "test:big", "test:anim#1"] {
+		let still = cartographer.textures[&name.into()];
+		let frame = cartographer.frames_at(5)[cartographer.frame_index(still)];
+		assert_eq!(frame, at(still, still, 0.0));
+	}
+}
+
+#[test]
+fn test_packing() {
+	let sizes = [uvec2(16, 32), uvec2(64, 64), uvec2(8, 4), uvec2(32, 16), uvec2(5, 3)];
+	let images = || {
+		(0 .. 24u32).map(|i| {
+			let loc = ResourceLocation::new("test", &format!("{i}"));
+			(loc, Image::solid_color(sizes[i as usize % sizes.len()], i))
+		})
+	};
+
+	for maxTextureDiameter in [128, 256, 1024] {
+		let (cartographer, layers) =
+			Cartographer::from_images(images(), maxTextureDiameter, defaultMipLevels);
+		assert!(cartographer.size.x <= maxTextureDiameter as u32);
+		assert!(cartographer.size.x.is_power_of_two() && cartographer.size.y.is_power_of_two());
+		if maxTextureDiameter == 1024 {
+			// everything fits in a single layer no larger than needed
+			assert_eq!(layers.len(), 1);
+			assert!(cartographer.size.x < 1024);
+		}
+
+		let area = (cartographer.size.x * cartographer.size.y) as usize;
+		let mut covered = vec![vec![false; area]; layers.len()];
+		for (loc, image) in images() {
+			let id = cartographer.id_for_texture(loc).unwrap();
+			let TextureRect { origin, size } = cartographer.rect_for_id(id);
+			// not cropped, and not overlapping any other texture
+			assert_eq!(UVec2::from(size), image.size);
+			let layer = &layers[id.atlas as usize];
+			for y in origin[1] .. origin[1] + size[1] {
+				for x in origin[0] .. origin[0] + size[0] {
+					let index = (y * layer.size.x + x) as usize;
+					assert_eq!(layer.pixels[index], image.pixels[0]);
+					assert!(!std::mem::replace(&mut covered[id.atlas as usize][index], true));
+				}
+			}
+		}
+	}
+}
+
+#[test]
+fn test_mipmaps() {
+	// leaves: a quarter of the texels opaque, each with three transparent neighbours, which plain
+	// averaging would turn into a faint haze covering every texel
+	let mut leaves = Image::solid_color(UVec2::splat(8), 0);
+	for y in (0 .. 8).step_by(2) {
+		for x in (0 .. 8).step_by(2) {
+			leaves.pixels[y * 8 + x] = 0xFF_00FF00;
+		}
+	}
+	assert!(leaves.is_cutout());
+	assert_eq!(leaves.alpha_coverage(alphaCutoff), 0.25);
+	let red = Image::solid_color(uvec2(8, 4), 0xFF_0000FF);
+	let (cartographer, layers) = Cartographer::from_images(
+		[
+			("test:leaves".into(), leaves),
+			("test:red".into(), red),
+		],
+		64,
+		2,
+	);
+	assert!(cartographer.size.x <= 64);
+	let mips = cartographer.mip_chain(0, &layers[0]);
+	assert_eq!(mips.len(), 2);
+
+	for (level, mip) in mips.iter().enumerate() {
+		let scale = 2 << level;
+		assert_eq!(mip.size, cartographer.size / scale);
+		let texture = |name: &str| {
+			let rect = cartographer.rect_for_id(cartographer.textures[&name.into()]);
+			let size = UVec2::from(rect.size) / scale;
+			mip.sub_image(UVec2::from(rect.origin) / scale, size)
+		};
+
+		// the leaves are still at least a quarter visible
+		let leaves = texture("test:leaves");
+		let coverage = leaves.alpha_coverage(alphaCutoff);
+		assert!(coverage >= 0.25, "level {level}: {coverage}");
+		assert!(leaves.pixels.iter().all(|pixel| pixel & 0xFFFFFF == 0x00FF00));
+
+		// and nothing of the leaves bleeds into the red texture, nor the other way
+		let red = texture("test:red");
+		assert!(red.pixels.iter().all(|&pixel| pixel == 0xFF_0000FF), "level {level}");
+	}
+
+	// mipmaps blurring a texture a quarter visible over its neighbours are thinned back out, and
+	// faded no more than that takes: an alpha of 100 is cut until it rounds to 6
+	let blurred = Image {
+		size: uvec2(4, 1),
+		pixels: vec![0, 0x03_000000, 0x64_000000, 0xFF_000000],
+	};
+	let scale = blurred.alpha_scale_for_coverage(0.25, alphaCutoff);
+	assert!((0.0545 .. 0.055).contains(&scale), "{scale}");
+	let mut thinned = blurred.clone();
+	thinned.scale_alpha(scale);
+	assert_eq!(thinned.alpha_coverage(alphaCutoff), 0.25);
+	assert_eq!(blurred.alpha_scale_for_coverage(0.5, alphaCutoff), 1.0);
+	assert!(blurred.alpha_scale_for_coverage(1.0, alphaCutoff) > 1.0);
+}
+
+#[test]
+fn test_alpha_cutoff() {
+	// the shader can't share the constant, so it declares its own copy
+	let declaration = format!("let alphaCutoff: u32 = {alphaCutoff}u;");
+	assert!(include_str!("../shaders/main.wgsl").contains(&declaration));
 }
@@ -0,0 +1,252 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use glam::uvec2;
+use serde::{Deserialize, Serialize};
+
+use super::texture::Image;
+use crate::loader::common::RegionTimestamps;
+use crate::types::{ChunkPos, RegionPos};
+
+/// A `z/x/y.png` tile pyramid as read by Leaflet and other slippy maps.
+///
+/// The most detailed zoom level has one tile per region, at the region's coordinates. Each level
+/// above it covers 2x2 tiles of the one below at the same resolution, down to zoom 0. Tile
+/// coordinates can be negative, which Leaflet's `CRS.Simple` handles.
+pub struct TilePyramid {
+	root: PathBuf,
+	/// Tiles of each zoom level, coarsest first
+	levels: Vec<BTreeSet<(i32, i32)>>,
+}
+
+impl TilePyramid {
+	/// Lays out the tiles covering `regions`, adding zoomed-out levels until halving stops
+	/// reducing the number of tiles.
+	pub fn new(root: impl Into<PathBuf>, regions: impl IntoIterator<Item = RegionPos>) -> Self {
+		let base: BTreeSet<_> = regions.into_iter().map(|pos| (pos.x, pos.z)).collect();
+		let mut levels = vec![base];
+		loop {
+			let finer = levels.last().unwrap();
+			let coarser: BTreeSet<_> = finer.iter().map(|&(x, y)| (x >> 1, y >> 1)).collect();
+			if coarser.len() == finer.len() {
+				break;
+			}
+			levels.push(coarser);
+		}
+		levels.reverse();
+
+		Self {
+			root: root.into(),
+			levels,
+		}
+	}
+
+	pub fn max_zoom(&self) -> u32 {
+		self.levels.len() as u32 - 1
+	}
+
+	pub fn tiles(&self, zoom: u32) -> impl Iterator<Item = (i32, i32)> + '_ {
+		self.levels[zoom as usize].iter().copied()
+	}
+
+	pub fn tile_path(&self, zoom: u32, x: i32, y: i32) -> PathBuf {
+		self.root.join(format!("{zoom}/{x}/{y}.png"))
+	}
+
+	pub fn save_region_tile(&self, pos: RegionPos, image: &Image) -> anyhow::Result<()> {
+		self.save_tile(self.max_zoom(), pos.x, pos.z, image)
+	}
+
+	pub fn has_region_tile(&self, pos: RegionPos) -> bool {
+		self.tile_path(self.max_zoom(), pos.x, pos.z).is_file()
+	}
+
+	/// Builds every zoomed-out level from the region tiles saved so far. Missing tiles are left
+	/// transparent.
+	pub fn build_zoomed_out(&self) -> anyhow::Result<()> {
+		let base = self.levels.last().unwrap();
+		self.build_zoomed_out_of(base.iter().map(|&(x, z)| RegionPos::new(x, z)))
+	}
+
+	/// Rebuilds only the zoomed-out tiles covering `changed` regions.
+	pub fn build_zoomed_out_of(
+		&self,
+		changed: impl IntoIterator<Item = RegionPos>,
+	) -> anyhow::Result<()> {
+		let mut dirty: BTreeSet<_> = changed.into_iter().map(|pos| (pos.x, pos.z)).collect();
+		for zoom in (0 .. self.max_zoom()).rev() {
+			dirty = dirty.iter().map(|&(x, y)| (x >> 1, y >> 1)).collect();
+			for &(x, y) in &dirty {
+				let mut tile: Option<Image> = None;
+				for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+					let path = self.tile_path(zoom + 1, x * 2 + dx, y * 2 + dy);
+					if !path.is_file() {
+						continue;
+					}
+					let child = Image::from_file(&path)?.downscale_half();
+					let tile = tile.get_or_insert_with(|| Image::solid_color(child.size * 2, 0));
+					tile.blit_from(&child, uvec2(dx as u32, dy as u32) * child.size, None);
+				}
+				if let Some(tile) = tile {
+					self.save_tile(zoom, x, y, &tile)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn save_tile(&self, zoom: u32, x: i32, y: i32, image: &Image) -> anyhow::Result<()> {
+		let path = self.tile_path(zoom, x, y);
+		std::fs::create_dir_all(path.parent().unwrap())?;
+		image.save_to_file(&path)
+	}
+}
+
+/// Records which chunks went into a tile pyramid, so that later runs only re-render regions whose
+/// chunks have been saved since.
+#[derive(Debug, Default)]
+pub struct RenderManifest {
+	/// Describes the options tiles were rendered with
+	settings: String,
+
+	/// Unix time of the last run which saved the manifest
+	renderedAt: u64,
+
+	/// Anvil timestamp of each chunk when it was last rendered
+	chunks: HashMap<ChunkPos, u32>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ManifestJson {
+	settings: String,
+	renderedAt: u64,
+	/// `[x, z, timestamp]`
+	chunks: Vec<(i32, i32, u32)>,
+}
+
+impl RenderManifest {
+	/// Loads the manifest at `path`. If there is none yet, or the tiles were rendered with
+	/// different `settings`, the manifest is empty and everything counts as stale.
+	pub fn load(path: &Path, settings: &str) -> anyhow::Result<Self> {
+		let empty = Self {
+			settings: settings.to_owned(),
+			..Self::default()
+		};
+		if !path.is_file() {
+			return Ok(empty);
+		}
+		let json: ManifestJson = serde_json::from_slice(&std::fs::read(path)?)?;
+		if json.settings != settings {
+			return Ok(empty);
+		}
+		Ok(Self {
+			settings: json.settings,
+			renderedAt: json.renderedAt,
+			chunks: json
+				.chunks
+				.into_iter()
+				.map(|(x, z, timestamp)| (ChunkPos::new(x, z), timestamp))
+				.collect(),
+		})
+	}
+
+	pub fn save(&mut self, path: &Path) -> anyhow::Result<()> {
+		self.renderedAt = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+		let mut chunks: Vec<_> = self
+			.chunks
+			.iter()
+			.map(|(pos, &timestamp)| (pos.x, pos.z, timestamp))
+			.collect();
+		chunks.sort_unstable();
+		let json = ManifestJson {
+			settings: self.settings.clone(),
+			renderedAt: self.renderedAt,
+			chunks,
+		};
+		if let Some(dir) = path.parent() {
+			std::fs::create_dir_all(dir)?;
+		}
+		std::fs::write(path, serde_json::to_vec(&json)?)?;
+		Ok(())
+	}
+
+	/// Unix time of the last run, or 0 if there was none.
+	pub fn rendered_at(&self) -> u64 {
+		self.renderedAt
+	}
+
+	/// Whether any chunk of the region was saved, created or deleted since it was rendered.
+	pub fn is_stale(&self, timestamps: &RegionTimestamps) -> bool {
+		timestamps
+			.iter()
+			.any(|(pos, timestamp)| self.chunks.get(&pos).copied().unwrap_or(0) != timestamp)
+	}
+
+	/// Marks the region as rendered in the state described by `timestamps`.
+	pub fn record(&mut self, timestamps: &RegionTimestamps) {
+		for (pos, timestamp) in timestamps.iter() {
+			if timestamp == 0 {
+				self.chunks.remove(&pos);
+			} else {
+				self.chunks.insert(pos, timestamp);
+			}
+		}
+	}
+}
+
+#[test]
+fn test_tile_pyramid() {
+	let regions = [
+		RegionPos::new(0, 0),
+		RegionPos::new(1, 0),
+		RegionPos::new(-1, 0),
+		RegionPos::new(2, 3),
+	];
+	let pyramid = TilePyramid::new("tiles", regions);
+	assert_eq!(pyramid.max_zoom(), 2);
+	assert_eq!(pyramid.tiles(2).count(), 4);
+	assert_eq!(pyramid.tiles(1).collect::<Vec<_>>(), [(-1, 0), (0, 0), (1, 1)]);
+	assert_eq!(pyramid.tiles(0).collect::<Vec<_>>(), [(-1, 0), (0, 0)]);
+	assert_eq!(pyramid.tile_path(1, -1, 0), Path::new("tiles/1/-1/0.png"));
+
+	let root = std::env::temp_dir().join(format!("cuview-tiles-{}", std::process::id()));
+	let pyramid = TilePyramid::new(&root, [RegionPos::new(0, 0), RegionPos::new(1, 1)]);
+	assert_eq!(pyramid.max_zoom(), 1);
+	let red = Image::solid_color(uvec2(4, 4), 0xFF_0000FF);
+	pyramid.save_region_tile(RegionPos::new(1, 1), &red).unwrap();
+	pyramid.build_zoomed_out().unwrap();
+	let tile = Image::from_file(&pyramid.tile_path(0, 0, 0)).unwrap();
+	std::fs::remove_dir_all(&root).unwrap();
+
+	// the region is the bottom right quarter of its parent
+	assert_eq!(tile.size, uvec2(4, 4));
+	assert_eq!(tile.pixels[0], 0);
+	assert_eq!(tile.pixels[15], 0xFF_0000FF);
+	assert_eq!(tile.pixels.iter().filter(|&&v| v != 0).count(), 4);
+}
+
+#[test]
+fn test_render_manifest() {
+	let pos = RegionPos::new(0, -1);
+	let mut table = vec![0u8; 4096];
+	table[4 .. 8].copy_from_slice(&100u32.to_be_bytes()); // chunk (1, -32)
+	let before = RegionTimestamps::parse(pos, &table);
+	table[0 .. 4].copy_from_slice(&200u32.to_be_bytes()); // chunk (0, -32) created
+	let after = RegionTimestamps::parse(pos, &table);
+
+	let path = std::env::temp_dir().join(format!("cuview-manifest-{}.json", std::process::id()));
+	let mut manifest = RenderManifest::load(&path, "settings").unwrap();
+	assert!(manifest.is_stale(&before));
+	manifest.record(&before);
+	assert!(!manifest.is_stale(&before));
+	assert!(manifest.is_stale(&after));
+	manifest.save(&path).unwrap();
+
+	let reloaded = RenderManifest::load(&path, "settings").unwrap();
+	let otherSettings = RenderManifest::load(&path, "other settings").unwrap();
+	std::fs::remove_file(&path).unwrap();
+	assert!(reloaded.rendered_at() > 0);
+	assert!(!reloaded.is_stale(&before));
+	assert!(otherSettings.is_stale(&before));
+}
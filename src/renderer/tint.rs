@@ -0,0 +1,233 @@
+use glam::uvec2;
+
+use super::texture::Image;
+use crate::jarfs::JarFS;
+use crate::types::blockstate::BlockState;
+use crate::types::resource_location::ResourceKind;
+use crate::types::ResourceLocation;
+
+/// Colour of tinted faces which have nothing more specific to go by, as `0xRRGGBB`
+pub const untinted: u32 = 0xFFFFFF;
+
+const defaultWater: u32 = 0x3F76E4;
+
+/// Temperature and downfall of vanilla biomes, as of 1.20.
+#[rustfmt::skip]
+const climates: &[(&str, f32, f32)] = &[
+	("badlands", 2.0, 0.0), ("bamboo_jungle", 0.95, 0.9), ("basalt_deltas", 2.0, 0.0),
+	("beach", 0.8, 0.4), ("birch_forest", 0.6, 0.6), ("cherry_grove", 0.5, 0.8),
+	("cold_ocean", 0.5, 0.5), ("crimson_forest", 2.0, 0.0), ("dark_forest", 0.7, 0.8),
+	("deep_cold_ocean", 0.5, 0.5), ("deep_dark", 0.8, 0.4), ("deep_frozen_ocean", 0.5, 0.5),
+	("deep_lukewarm_ocean", 0.5, 0.5), ("deep_ocean", 0.5, 0.5), ("desert", 2.0, 0.0),
+	("dripstone_caves", 0.8, 0.4), ("end_barrens", 0.5, 0.5), ("end_highlands", 0.5, 0.5),
+	("end_midlands", 0.5, 0.5), ("eroded_badlands", 2.0, 0.0), ("flower_forest", 0.7, 0.8),
+	("forest", 0.7, 0.8), ("frozen_ocean", 0.0, 0.5), ("frozen_peaks", -0.7, 0.9),
+	("frozen_river", 0.0, 0.5), ("grove", -0.2, 0.8), ("ice_spikes", 0.0, 0.5),
+	("jagged_peaks", -0.7, 0.9), ("jungle", 0.95, 0.9), ("lukewarm_ocean", 0.5, 0.5),
+	("lush_caves", 0.5, 0.5), ("mangrove_swamp", 0.8, 0.9), ("meadow", 0.5, 0.8),
+	("mushroom_fields", 0.9, 1.0), ("nether_wastes", 2.0, 0.0), ("ocean", 0.5, 0.5),
+	("old_growth_birch_forest", 0.6, 0.6), ("old_growth_pine_taiga", 0.3, 0.8),
+	("old_growth_spruce_taiga", 0.25, 0.8), ("plains", 0.8, 0.4), ("river", 0.5, 0.5),
+	("savanna", 2.0, 0.0), ("savanna_plateau", 2.0, 0.0), ("small_end_islands", 0.5, 0.5),
+	("snowy_beach", 0.05, 0.3), ("snowy_plains", 0.0, 0.5), ("snowy_slopes", -0.3, 0.9),
+	("snowy_taiga", -0.5, 0.4), ("soul_sand_valley", 2.0, 0.0), ("sparse_jungle", 0.95, 0.8),
+	("stony_peaks", 1.0, 0.3), ("stony_shore", 0.2, 0.3), ("sunflower_plains", 0.8, 0.4),
+	("swamp", 0.8, 0.9), ("taiga", 0.25, 0.8), ("the_end", 0.5, 0.5), ("the_void", 0.5, 0.5),
+	("warm_ocean", 0.5, 0.5), ("warped_forest", 2.0, 0.0), ("windswept_forest", 0.2, 0.3),
+	("windswept_gravelly_hills", 0.2, 0.3), ("windswept_hills", 0.2, 0.3),
+	("windswept_savanna", 2.0, 0.0), ("wooded_badlands", 2.0, 0.0),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Colormap {
+	Grass,
+	Foliage,
+}
+
+/// Computes the colours that tinted block faces are multiplied by, i.e. the equivalent of
+/// vanilla's `BlockColors`.
+pub struct BlockColors {
+	grass: Image,
+	foliage: Image,
+}
+
+impl BlockColors {
+	pub fn load(fs: &JarFS) -> anyhow::Result<Self> {
+		let load = |name: &str| {
+			let path = ResourceLocation::new("minecraft", &format!("colormap/{name}"))
+				.into_path(ResourceKind::Texture);
+			let image = Image::from_jarfs(fs, &path)?;
+			anyhow::ensure!(
+				image.size == uvec2(256, 256),
+				"colormap {path:?} is not 256x256"
+			);
+			Ok(image)
+		};
+		Ok(Self {
+			grass: load("grass")?,
+			foliage: load("foliage")?,
+		})
+	}
+
+	/// Returns the tint of `state` in `biome` as `0xRRGGBB`, for faces with a `tintindex`.
+	pub fn tint_for(&self, state: BlockState, biome: Option<ResourceLocation>) -> u32 {
+		let block = state.block_name();
+		if block.modid.as_str() != "minecraft" {
+			return self.modded_tint(block.name.as_str(), biome);
+		}
+
+		match block.name.as_str() {
+			"grass_block" | "grass" | "short_grass" | "tall_grass" | "fern" | "large_fern" |
+			"potted_fern" | "sugar_cane" => self.biome_color(Colormap::Grass, biome),
+			"oak_leaves" | "jungle_leaves" | "acacia_leaves" | "dark_oak_leaves" |
+			"mangrove_leaves" | "vine" => self.biome_color(Colormap::Foliage, biome),
+			"birch_leaves" => 0x80A755,
+			"spruce_leaves" => 0x619961,
+			"lily_pad" => 0x208030,
+			"water" | "bubble_column" | "water_cauldron" => water_color(biome),
+			"redstone_wire" => {
+				let power = state
+					.get_property("power")
+					.and_then(|v| v.parse().ok())
+					.unwrap_or(0);
+				redstone_color(power)
+			},
+			"pumpkin_stem" | "melon_stem" => {
+				let age = state
+					.get_property("age")
+					.and_then(|v| v.parse().ok())
+					.unwrap_or(0);
+				stem_color(age)
+			},
+			"attached_pumpkin_stem" | "attached_melon_stem" => 0xE0C71C,
+			_ => untinted,
+		}
+	}
+
+	/// Modded blocks register their own colour handlers, which can't be known; blocks named like
+	/// vanilla's tinted ones are guessed to be tinted the same, and the rest are left untinted.
+	fn modded_tint(&self, name: &str, biome: Option<ResourceLocation>) -> u32 {
+		let name = name.rsplit('/').next().unwrap_or(name);
+		if name == "leaves" || name.ends_with("_leaves") || name.ends_with("_vine") {
+			self.biome_color(Colormap::Foliage, biome)
+		} else if name.contains("grass") || name.ends_with("fern") {
+			self.biome_color(Colormap::Grass, biome)
+		} else if name == "water" || name.ends_with("_water") {
+			water_color(biome)
+		} else {
+			untinted
+		}
+	}
+
+	fn biome_color(&self, map: Colormap, biome: Option<ResourceLocation>) -> u32 {
+		let name = biome
+			.filter(|b| b.modid.as_str() == "minecraft")
+			.map(|b| b.name.as_str())
+			.unwrap_or("plains");
+
+		match (map, name) {
+			(Colormap::Grass, "swamp") | (Colormap::Foliage, "swamp") => return 0x6A7039,
+			(Colormap::Grass, "badlands" | "eroded_badlands" | "wooded_badlands") => {
+				return 0x90814D;
+			},
+			(Colormap::Foliage, "badlands" | "eroded_badlands" | "wooded_badlands") => {
+				return 0x9E814D;
+			},
+			(Colormap::Foliage, "mangrove_swamp") => return 0x8DB127,
+			(_, "cherry_grove") => return 0xB6DB61,
+			_ => {},
+		}
+
+		let (temperature, downfall) = climates
+			.iter()
+			.find(|(biome, ..)| *biome == name)
+			.map(|&(_, t, d)| (t, d))
+			.unwrap_or((0.8, 0.4));
+		let image = match map {
+			Colormap::Grass => &self.grass,
+			Colormap::Foliage => &self.foliage,
+		};
+		let color = sample_colormap(image, temperature, downfall);
+
+		if map == Colormap::Grass && name == "dark_forest" {
+			((color & 0xFEFEFE) + 0x28340A) >> 1
+		} else {
+			color
+		}
+	}
+}
+
+/// Looks up the colour for a given climate in a 256x256 colormap, as `0xRRGGBB`.
+fn sample_colormap(image: &Image, temperature: f32, downfall: f32) -> u32 {
+	let temperature = temperature.clamp(0.0, 1.0);
+	let downfall = downfall.clamp(0.0, 1.0) * temperature;
+	let x = ((1.0 - temperature) * 255.0) as u32;
+	let y = ((1.0 - downfall) * 255.0) as u32;
+	abgr_to_rgb(image.pixels[(y * image.size.x + x) as usize])
+}
+
+fn abgr_to_rgb(pixel: u32) -> u32 {
+	let [r, g, b, _] = pixel.to_le_bytes();
+	u32::from_be_bytes([0, r, g, b])
+}
+
+/// Returns the colour of water in `biome`, as `0xRRGGBB`.
+pub fn water_color(biome: Option<ResourceLocation>) -> u32 {
+	let name = match biome {
+		Some(b) if b.modid.as_str() == "minecraft" => b.name.as_str(),
+		_ => return defaultWater,
+	};
+	match name {
+		"swamp" => 0x617B64,
+		"mangrove_swamp" => 0x3A7A6A,
+		"warm_ocean" => 0x43D5EE,
+		"lukewarm_ocean" | "deep_lukewarm_ocean" => 0x45ADF2,
+		"cold_ocean" | "deep_cold_ocean" | "snowy_taiga" | "snowy_beach" => 0x3D57D6,
+		"frozen_ocean" | "deep_frozen_ocean" | "frozen_river" => 0x3938C9,
+		"meadow" => 0x0E4ECF,
+		"cherry_grove" => 0x5DB7EF,
+		_ => defaultWater,
+	}
+}
+
+fn redstone_color(power: u8) -> u32 {
+	let f = power.min(15) as f32 / 15.0;
+	let r = f * 0.6 + if power > 0 { 0.4 } else { 0.3 };
+	let g = (f * f * 0.7 - 0.5).clamp(0.0, 1.0);
+	let b = (f * f * 0.6 - 0.7).clamp(0.0, 1.0);
+	let [r, g, b] = [r, g, b].map(|v| (v * 255.0).round() as u8);
+	u32::from_be_bytes([0, r, g, b])
+}
+
+fn stem_color(age: u8) -> u32 {
+	let age = age.min(7) as u32;
+	(age * 32) << 16 | (255 - age * 8) << 8 | (age * 4)
+}
+
+#[test]
+fn test_tints() {
+	let mut map = Image::empty(uvec2(256, 256));
+	map.pixels[0] = 0xFF_0000FF; // red at temperature 1.0, downfall 1.0
+	map.pixels[255 * 256 + 255] = 0xFF_00FF00; // green at temperature 0.0
+	assert_eq!(sample_colormap(&map, 1.5, 1.0), 0xFF0000);
+	assert_eq!(sample_colormap(&map, -0.5, 0.5), 0x00FF00);
+
+	assert_eq!(redstone_color(0), 0x4D0000);
+	assert_eq!(redstone_color(15), 0xFF3300);
+	assert_eq!(stem_color(0), 0x00FF00);
+	assert_eq!(stem_color(7), 0xE0C71C);
+	assert_eq!(water_color(Some("swamp".into())), 0x617B64);
+	assert_eq!(water_color(None), defaultWater);
+
+	// modded blocks are only tinted when they're named like tinted vanilla blocks
+	let colors = BlockColors {
+		grass: Image::solid_color(uvec2(256, 256), 0xFF_00FF00),
+		foliage: Image::solid_color(uvec2(256, 256), 0xFF_0000FF),
+	};
+	let tint = |name: &str| colors.tint_for(BlockState::stateless(name.into()), None);
+	assert_eq!(tint("mod:maple_leaves"), 0xFF0000);
+	assert_eq!(tint("mod:tall_prairie_grass"), 0x00FF00);
+	assert_eq!(tint("mod:salt_water"), defaultWater);
+	assert_eq!(tint("mod:redstone_pipe"), untinted);
+	assert_eq!(tint("mod:overlay"), untinted);
+}
@@ -10,6 +10,21 @@ struct VIn {
 	
 	@location(2)
 	texId: u32,
+	
+	@location(3)
+	tintIndex: i32,
+	
+	// face direction before the blockstate rotation
+	@location(4)
+	normal: vec3<f32>,
+	
+	// 1: shaded by direction, 2: lit by the neighbour it faces, 4: ambient occlusion
+	@location(5)
+	flags: u32,
+	
+	// 0xRRGGBB, multiplied in on top of any tint
+	@location(6)
+	color: u32,
 }
 
 struct VOut {
@@ -21,6 +36,19 @@ struct VOut {
 	
 	@location(1)
 	texLayer: u32,
+	
+	@location(2)
+	tint: vec3<f32>,
+	
+	// the next frame of an animated texture, faded towards by `blend`
+	@location(3)
+	nextUv: vec2<f32>,
+	
+	@location(4)
+	nextTexLayer: u32,
+	
+	@location(5)
+	blend: f32,
 }
 
 struct Camera {
@@ -32,15 +60,60 @@ struct Camera {
 @binding(0)
 var<uniform> camera: Camera;
 
+// where each texture lies in its atlas layer, in pixels, indexed like textureFrames
+struct TextureRect {
+	origin: vec2<u32>,
+	size: vec2<u32>,
+}
+
 @group(0)
 @binding(1)
-var<storage, read> atlasDiameters: array<u32>;
+var<storage, read> textureRects: array<TextureRect>;
 
 @group(0)
 @binding(2)
 var atlas: texture_2d_array<f32>;
 
-var<push_constant> section: i32;
+@group(0)
+@binding(4)
+var<storage, read> blockTints: array<u32>;
+
+// light levels of each block's neighbours (up, down, north, east, south, west) and of the block
+// itself, 4 bits each
+@group(0)
+@binding(5)
+var<storage, read> blockLights: array<u32>;
+
+// which blocks of the 3x3x3 cube around each block are solid, see occlusionBit
+@group(0)
+@binding(6)
+var<storage, read> blockOcclusion: array<u32>;
+
+// which frame to show in place of each texture, see layerBases
+struct TextureFrame {
+	current: u32,
+	next: u32,
+	blend: f32,
+}
+
+// index of each atlas layer's first texture in textureFrames
+@group(0)
+@binding(7)
+var<storage, read> layerBases: array<u32>;
+
+@group(0)
+@binding(8)
+var<storage, read> textureFrames: array<TextureFrame>;
+
+struct Section {
+	y: i32,
+	// index of this section's first block in blockTints and blockLights
+	tintBase: u32,
+	chunkX: i32,
+	chunkZ: i32,
+}
+
+var<push_constant> section: Section;
 
 fn translationMat(t: vec3<f32>) -> mat4x4<f32> {
 	return mat4x4<f32>(
@@ -83,14 +156,107 @@ fn blockTranslation(blockId: u32) -> vec3<f32> {
 	let tx = f32(blockId % chunkWidth);
 	
 	// section translation
-	let ty = f32(ty) + 16.0 * f32(section);
-	
-	// debugging
-	// let tx = tx + 16.0 * f32(section);
+	let ty = f32(ty) + 16.0 * f32(section.y);
+	let tx = tx + 16.0 * f32(section.chunkX);
+	let tz = tz + 16.0 * f32(section.chunkZ);
 	
 	return vec3<f32>(tx, ty, tz);
 }
 
+// index of the direction closest to `n`, in the order of blockLights
+fn directionIndex(n: vec3<f32>) -> u32 {
+	let a = abs(n);
+	if a.y >= a.x && a.y >= a.z {
+		return select(1u, 0u, n.y > 0.0);
+	}
+	if a.z >= a.x {
+		return select(2u, 4u, n.z > 0.0);
+	}
+	return select(5u, 3u, n.x > 0.0);
+}
+
+// vanilla's brightness for shaded faces: up 1.0, north/south 0.8, east/west 0.6, down 0.5
+fn directionShade(dir: u32) -> f32 {
+	switch dir {
+		case 0u: { return 1.0; }
+		case 1u: { return 0.5; }
+		case 2u, 4u: { return 0.8; }
+		default: { return 0.6; }
+	}
+}
+
+fn directionNormal(dir: u32) -> vec3<i32> {
+	switch dir {
+		case 0u: { return vec3<i32>(0, 1, 0); }
+		case 1u: { return vec3<i32>(0, -1, 0); }
+		case 2u: { return vec3<i32>(0, 0, -1); }
+		case 3u: { return vec3<i32>(1, 0, 0); }
+		case 4u: { return vec3<i32>(0, 0, 1); }
+		default: { return vec3<i32>(-1, 0, 0); }
+	}
+}
+
+fn occlusionBit(offset: vec3<i32>) -> u32 {
+	return u32((offset.y + 1) * 9 + (offset.z + 1) * 3 + offset.x + 1);
+}
+
+fn isSolid(occlusion: u32, offset: vec3<i32>) -> bool {
+	return ((occlusion >> occlusionBit(offset)) & 1u) != 0u;
+}
+
+fn solidBrightness(solid: bool) -> f32 {
+	return select(1.0, 0.2, solid);
+}
+
+// vanilla's smooth lighting: the average brightness of the block in front of the corner a vertex
+// lies on and of the three blocks next to it in that layer, where solid blocks count for 0.2
+fn ambientOcclusion(occlusion: u32, dir: u32, local: vec3<f32>, culled: bool) -> f32 {
+	let normal = directionNormal(dir);
+	// faces inside the block, like the top of a slab, are surrounded by the block's own layer
+	let front = select(vec3<i32>(0), normal, culled);
+	let fromCentre = local - 0.5;
+	let corner = vec3<i32>(sign(fromCentre)) *
+		vec3<i32>(abs(fromCentre) >= vec3<f32>(0.01)) *
+		(vec3<i32>(1) - abs(normal));
+	
+	var tangent1 = vec3<i32>(1, 0, 0);
+	var tangent2 = vec3<i32>(0, 1, 0);
+	if normal.x != 0 {
+		tangent1 = vec3<i32>(0, 1, 0);
+		tangent2 = vec3<i32>(0, 0, 1);
+	} else if normal.y != 0 {
+		tangent2 = vec3<i32>(0, 0, 1);
+	}
+	
+	let side1 = isSolid(occlusion, front + corner * tangent1);
+	let side2 = isSolid(occlusion, front + corner * tangent2);
+	let diagonal = (side1 && side2) || isSolid(occlusion, front + corner);
+	return (
+		solidBrightness(isSolid(occlusion, front)) +
+		solidBrightness(side1) +
+		solidBrightness(side2) +
+		solidBrightness(diagonal)
+	) / 4.0;
+}
+
+// vanilla's lightmap curve without gamma, plus a little ambient light
+fn lightBrightness(level: u32) -> f32 {
+	let level = f32(level) / 15.0;
+	return 0.04 + 0.96 * level / (4.0 - 3.0 * level);
+}
+
+fn atlasLayer(texId: u32) -> u32 {
+	return (texId & (0xFFu << 24u)) >> 24u;
+}
+
+// where `uv` within a texture lies in its atlas layer
+fn atlasUv(texId: u32, uv: vec2<f32>) -> vec2<f32> {
+	let rect = textureRects[layerBases[atlasLayer(texId)] + (texId & 0xFFFFFFu)];
+	let atlasSize = vec2<f32>(textureDimensions(atlas));
+	
+	let uv = vec2<f32>(uv.x, 1.0 - uv.y); // origin swap
+	return (vec2<f32>(rect.origin) + uv * vec2<f32>(rect.size)) / atlasSize;
+}
 
 @vertex
 fn vsMain(in: VIn) -> VOut {
@@ -109,26 +275,49 @@ fn vsMain(in: VIn) -> VOut {
 	
 	let pos = camera.projection * camera.view * model * vec4<f32>(in.pos, 1.0);
 	
-	let texId = in.texId;
-	let texLayer = (texId & (0xFFu << 24u)) >> 24u;
-	let texId = texId & 0xFFFFFFu;
-	
-	let diameter = atlasDiameters[texLayer];
-	let atlasSize = textureDimensions(atlas);
-	let scale = f32(diameter) / vec2<f32>(atlasSize);
-	let widthInElems = u32(atlasSize.x) / u32(diameter);
-	let offset = vec2<f32>(
-		f32(texId % widthInElems),
-		f32(texId / widthInElems),
-	);
+	let frame = textureFrames[layerBases[atlasLayer(in.texId)] + (in.texId & 0xFFFFFFu)];
+	
+	var tint = vec3<f32>(1.0);
+	if in.tintIndex >= 0 {
+		let rgb = blockTints[section.tintBase + instance];
+		tint = vec3<f32>(
+			f32((rgb >> 16u) & 0xFFu),
+			f32((rgb >> 8u) & 0xFFu),
+			f32(rgb & 0xFFu),
+		) / 255.0;
+	}
 	
-	let uv = vec2<f32>(in.uv.x, 1.0 - in.uv.y); // origin swap
-	let uv = scale * uv + scale * offset;
+	tint = tint * vec3<f32>(
+		f32((in.color >> 16u) & 0xFFu),
+		f32((in.color >> 8u) & 0xFFu),
+		f32(in.color & 0xFFu),
+	) / 255.0;
+	
+	let dir = directionIndex((model * vec4<f32>(in.normal, 0.0)).xyz);
+	var shade = 1.0;
+	if (in.flags & 1u) != 0u {
+		shade = directionShade(dir);
+	}
+	// faces inside the block, like the top of a slab, are lit by the block itself
+	let lightIndex = select(6u, dir, (in.flags & 2u) != 0u);
+	let light = (blockLights[section.tintBase + instance] >> (lightIndex * 4u)) & 0xFu;
+	tint = tint * shade * lightBrightness(light);
+	
+	if (in.flags & 4u) != 0u {
+		// the block's rotation keeps its centre in place, so this is the vertex within the block
+		let local = (model * vec4<f32>(in.pos, 1.0)).xyz - blockTranslation(instance);
+		let occlusion = blockOcclusion[section.tintBase + instance];
+		tint = tint * ambientOcclusion(occlusion, dir, local, (in.flags & 2u) != 0u);
+	}
 	
 	return VOut(
 		pos,
-		uv,
-		texLayer,
+		atlasUv(frame.current, in.uv),
+		atlasLayer(frame.current),
+		tint,
+		atlasUv(frame.next, in.uv),
+		atlasLayer(frame.next),
+		frame.blend,
 	);
 }
 
@@ -136,13 +325,19 @@ fn vsMain(in: VIn) -> VOut {
 @binding(3)
 var atlasSampler: sampler;
 
+// fragments with an alpha of this much out of 255 or less are discarded; `alphaCutoff` in
+// texture.rs, which keeps mipmaps covering as much as full size textures
+let alphaCutoff: u32 = 5u;
+
 @fragment
 fn fsMain(in: VOut) -> @location(0) vec4<f32> {
 	// FIXME: currently (0.9.0) Naga does not respect spec and only accepts i32s
-	let layer = i32(in.texLayer);
-	let res = textureSample(atlas, atlasSampler, in.uv, layer);
+	let current = textureSample(atlas, atlasSampler, in.uv, i32(in.texLayer));
+	let next = textureSample(atlas, atlasSampler, in.nextUv, i32(in.nextTexLayer));
+	// interpolated animations fade between the colours of frames, keeping the current one's shape
+	let res = vec4<f32>(mix(current.rgb, next.rgb, in.blend), current.a);
 	
 	// cheap hack to fix blending of overlapping transparency
-	if res.a <= 5.0 / 255.0 { discard; }
-	return res;
+	if res.a <= f32(alphaCutoff) / 255.0 { discard; }
+	return vec4<f32>(res.rgb * in.tint, res.a);
 }
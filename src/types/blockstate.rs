@@ -1,3 +1,4 @@
+use std::collections::btree_map::Entry;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::{self, Debug, Display, Write};
@@ -5,7 +6,10 @@ use std::hash::Hash;
 use std::ops::{Deref, DerefMut};
 
 use super::{IString, ResourceLocation};
+use crate::jarfs::JarFS;
 use crate::loader::blockstate::{BlockStates, State};
+use crate::loader::model::{JsonBlockState, MultipartWhen};
+use crate::types::resource_location::ResourceKind;
 use crate::world::Palette;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -40,6 +44,13 @@ impl BlockState {
 		}
 		None
 	}
+
+	pub fn properties(&self) -> impl Iterator<Item = (&'static str, &'static str)> {
+		self.props
+			.as_str()
+			.split(',')
+			.filter_map(|pair| pair.split_once('='))
+	}
 }
 
 impl Display for BlockState {
@@ -74,8 +85,11 @@ fn test_blockstate() {
 	};
 	assert!(state.get_property("abc") == Some("1"));
 	assert!(state.get_property("def") == Some("2"));
+	assert!(state.properties().collect::<Vec<_>>() == vec![("abc", "1"), ("def", "2")]);
+	assert!(BlockState::stateless(block).properties().count() == 0);
 }
 
+#[derive(Clone)]
 pub struct BlockStateBuilder {
 	block: ResourceLocation,
 	props: BTreeMap<IString, IString>,
@@ -182,6 +196,84 @@ impl BlockStateCache {
 		Self(res)
 	}
 
+	/// Builds the set of all blockstates from the blockstate JSONs of every loaded jar.
+	///
+	/// Property names and values are collected from `variants` keys and `multipart` conditions,
+	/// along with the values multipart conditions leave implied, then expanded into every possible
+	/// combination. Properties which no JSON refers to (e.g.
+	/// `waterlogged` on stairs) don't affect which models are chosen and so are omitted; use
+	/// `normalize` to strip them from states read out of a world.
+	pub fn from_jarfs(fs: &JarFS) -> Self {
+		let mut blocks = BTreeMap::new();
+		for path in fs.files(ResourceKind::BlockState) {
+			let (block, _) = ResourceLocation::from_path(&path);
+			let json = fs
+				.read_text(&path)
+				.and_then(|json| Ok(serde_json::from_str::<JsonBlockState>(&json)?));
+			match json {
+				Ok(json) => {
+					blocks.insert(block, collect_properties(&json));
+				},
+				Err(err) => eprintln!("Warning: malformed blockstate json for {block}: {err}"),
+			}
+		}
+		Self::from_properties(blocks)
+	}
+
+	/// Expands a set of block properties and their possible values into every possible state of
+	/// each block. The first state of each block (i.e. that with the lowest value of every
+	/// property) is used as its default state.
+	pub fn from_properties(
+		blocks: impl IntoIterator<Item = (ResourceLocation, BTreeMap<IString, BTreeSet<IString>>)>,
+	) -> Self {
+		let mut res = HashMap::new();
+		for (block, props) in blocks {
+			res.insert(block, expand_states(block, &props));
+		}
+		Self(res)
+	}
+
+	/// Gives `block` those of the `extra` properties its states don't have yet, in every
+	/// combination with the ones they do. This is for properties which only matter to something
+	/// other than the blockstate JSONs, and so are missing from caches built by `from_jarfs`.
+	pub fn add_properties(&mut self, block: ResourceLocation, extra: &[(&str, &[&str])]) {
+		let states = match self.0.get(&block) {
+			Some(v) => v,
+			None => return,
+		};
+		let mut props: BTreeMap<IString, BTreeSet<IString>> = BTreeMap::new();
+		for state in states {
+			for (key, value) in state.properties() {
+				props.entry(key.into()).or_default().insert(value.into());
+			}
+		}
+		let mut added = false;
+		for &(key, values) in extra {
+			if let Entry::Vacant(entry) = props.entry(key.into()) {
+				entry.insert(values.iter().copied().map(Into::into).collect());
+				added = true;
+			}
+		}
+		if added {
+			self.0.insert(block, expand_states(block, &props));
+		}
+	}
+
+	/// Strips any properties of `state` that its block's states in this cache don't have, so that
+	/// states read from a world can be looked up in a cache built by `from_jarfs`. Properties
+	/// `state` is missing (e.g. from flattened pre-1.13 blocks) are taken from the default state.
+	pub fn normalize(&self, state: BlockState) -> BlockState {
+		let default = match self.default_state_of(state.block_name()) {
+			Some(v) => v,
+			None => return state,
+		};
+		let mut builder = BlockStateBuilder::new(state.block_name());
+		for (key, defaultValue) in default.properties() {
+			builder.set_property(key, state.get_property(key).unwrap_or(defaultValue));
+		}
+		builder.build()
+	}
+
 	pub fn blocks(&self) -> impl '_ + Iterator<Item = ResourceLocation> {
 		self.0.keys().copied()
 	}
@@ -198,3 +290,143 @@ impl BlockStateCache {
 		self.0.get(&block).and_then(|xs| xs.first()).copied()
 	}
 }
+
+/// Builds every combination of `props`, with the lowest value of each first.
+fn expand_states(
+	block: ResourceLocation,
+	props: &BTreeMap<IString, BTreeSet<IString>>,
+) -> Vec<BlockState> {
+	let mut combos: Vec<BlockStateBuilder> = vec![BlockStateBuilder::new(block)];
+	for (key, values) in props {
+		combos = combos
+			.into_iter()
+			.flat_map(|builder| {
+				values.iter().map(move |value| {
+					let mut builder = builder.clone();
+					builder.set_property(key, value);
+					builder
+				})
+			})
+			.collect();
+	}
+	combos.into_iter().map(BlockStateBuilder::build).collect()
+}
+
+fn collect_properties(json: &JsonBlockState) -> BTreeMap<IString, BTreeSet<IString>> {
+	let mut props: BTreeMap<IString, BTreeSet<IString>> = BTreeMap::new();
+	let mut insert = |key: &str, value: &str| {
+		props
+			.entry(IString::lowercased(key))
+			.or_default()
+			.insert(IString::lowercased(value));
+	};
+
+	match json {
+		JsonBlockState::Variants(map) => {
+			let pairs = map
+				.keys()
+				.flat_map(|key| key.split(','))
+				.filter_map(|pair| pair.split_once('='));
+			for (k, v) in pairs {
+				insert(k, v);
+			}
+		},
+		JsonBlockState::Multipart(parts) => {
+			let cases = parts
+				.iter()
+				.filter_map(|part| part.when.as_ref())
+				.flat_map(|when| match when {
+					MultipartWhen::And(case) => std::slice::from_ref(case),
+					MultipartWhen::Or { or: cases } => cases.as_slice(),
+				});
+			for case in cases {
+				for (k, vs) in &case.0 {
+					for v in &vs.0 {
+						insert(k, v);
+					}
+				}
+			}
+
+			// conditions only test for parts being shown, e.g. `"north": "true"` on fences or
+			// `"east": "low|tall"` on walls, so the values without a part never appear
+			for values in props.values_mut() {
+				let isAny = |allowed: &[&str]| values.iter().all(|v| allowed.contains(&v.as_str()));
+				let implied: &[&str] = if isAny(&["false", "true"]) {
+					&["false", "true"]
+				} else if isAny(&["low", "tall", "side", "up"]) {
+					&["none"]
+				} else {
+					&[]
+				};
+				values.extend(implied.iter().copied().map(IString::from));
+			}
+		},
+	}
+	props
+}
+
+#[test]
+fn test_collect_multipart_properties() {
+	let json = r#"{"multipart": [
+		{"apply": {"model": "oak_fence_post"}},
+		{"when": {"north": "true"}, "apply": {"model": "oak_fence_side"}},
+		{"when": {"east": "true"}, "apply": {"model": "oak_fence_side", "y": 90}},
+		{"when": {"OR": [{"south": "low"}, {"south": "tall"}]}, "apply": {"model": "wall_side"}},
+		{"when": {"west": "side|up"}, "apply": {"model": "redstone_dust_side"}},
+		{"when": {"facing": "north"}, "apply": {"model": "lever"}}
+	]}"#;
+	let json: JsonBlockState = serde_json::from_str(json).unwrap();
+	let block = ResourceLocation::from("multipart_test");
+	let cache = BlockStateCache::from_properties([(block, collect_properties(&json))]);
+	assert!(cache.states_of(block).unwrap().len() == 2 * 2 * 3 * 3);
+
+	// a fence connected to nothing, as read from a world
+	let lone = BlockStateBuilder::from_variants_model(
+		block,
+		"east=false,facing=north,north=false,south=none,waterlogged=false,west=none",
+	)
+	.build();
+	let normalized = cache.normalize(lone);
+	let expected = "east=false,facing=north,north=false,south=none,west=none";
+	assert!(normalized.props.as_str() == expected);
+	assert!(cache.states_of(block).unwrap().contains(&normalized));
+}
+
+#[test]
+fn test_cache_from_properties() {
+	let block = ResourceLocation::from("cache_test");
+	let stateless = ResourceLocation::from("cache_test_stateless");
+	let props: BTreeMap<IString, BTreeSet<IString>> = [
+		("facing", &["north", "south"][..]),
+		("lit", &["false", "true"][..]),
+	]
+	.into_iter()
+	.map(|(k, vs)| (k.into(), vs.iter().copied().map(Into::into).collect()))
+	.collect();
+	let cache = BlockStateCache::from_properties([(block, props), (stateless, BTreeMap::new())]);
+
+	let states = cache.states_of(block).unwrap();
+	assert!(states.len() == 4);
+	assert!(cache.default_state_of(block).unwrap().props.as_str() == "facing=north,lit=false");
+	assert!(cache.states_of(stateless).unwrap() == [BlockState::stateless(stateless)]);
+
+	let full =
+		BlockStateBuilder::from_variants_model(block, "facing=south,lit=true,waterlogged=true")
+			.build();
+	let normalized = cache.normalize(full);
+	assert!(normalized.props.as_str() == "facing=south,lit=true");
+	assert!(states.contains(&normalized));
+	let partial = BlockStateBuilder::from_variants_model(block, "lit=true").build();
+	assert!(cache.normalize(partial).props.as_str() == "facing=north,lit=true");
+	assert!(cache.normalize(BlockState::stateless(stateless)) == BlockState::stateless(stateless));
+
+	// only properties the block doesn't have yet are added
+	let mut cache = cache;
+	cache.add_properties(block, &[("facing", &["east"]), ("part", &["foot", "head"])]);
+	cache.add_properties(stateless, &[("rotation", &["0", "1", "2"])]);
+	assert!(cache.states_of(block).unwrap().len() == 8);
+	assert!(
+		cache.default_state_of(block).unwrap().props.as_str() == "facing=north,lit=false,part=foot"
+	);
+	assert!(cache.states_of(stateless).unwrap().len() == 3);
+}
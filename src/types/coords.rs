@@ -29,6 +29,13 @@ impl BlockPos {
 	pub fn section(&self) -> i8 {
 		(self.y >> 4) as i8
 	}
+
+	/// Index of the block within its chunk section, in YZX order.
+	pub fn index_in_section(&self) -> usize {
+		let pos = self.chunk_relative();
+		let diameter = ChunkPos::diameterBlocks;
+		(pos.y * diameter.pow(2) + pos.z * diameter + pos.x) as usize
+	}
 }
 
 impl FromStr for BlockPos {
@@ -50,6 +57,7 @@ fn test_blockpos() {
 
 	let pos = BlockPos::new(-1, 0, 0);
 	assert!(pos.chunk_relative() == BlockPos::new(15, 0, 0));
+	assert_eq!(BlockPos::new(-1, 17, 2).index_in_section(), 256 + 2 * 16 + 15);
 
 	let pos = BlockPos::from_str("0,0,0");
 	assert!(pos == Ok(BlockPos::new(0, 0, 0)));
@@ -176,6 +184,96 @@ fn test_chunkpos() {
 	assert!(pos.is_err());
 }
 
+/// An inclusive rectangle of chunks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkArea {
+	pub min: ChunkPos,
+	pub max: ChunkPos,
+}
+
+impl ChunkArea {
+	/// The smallest area containing both corners.
+	pub fn new(a: ChunkPos, b: ChunkPos) -> Self {
+		Self {
+			min: ChunkPos::new(a.x.min(b.x), a.z.min(b.z)),
+			max: ChunkPos::new(a.x.max(b.x), a.z.max(b.z)),
+		}
+	}
+
+	/// The smallest area containing every block between two corners.
+	pub fn from_blocks(a: BlockPos, b: BlockPos) -> Self {
+		Self::new(a.into(), b.into())
+	}
+
+	pub fn contains(&self, pos: ChunkPos) -> bool {
+		(self.min.x ..= self.max.x).contains(&pos.x) && (self.min.z ..= self.max.z).contains(&pos.z)
+	}
+
+	pub fn chunks(&self) -> impl Iterator<Item = ChunkPos> {
+		let Self { min, max } = *self;
+		(min.z ..= max.z).flat_map(move |z| (min.x ..= max.x).map(move |x| ChunkPos::new(x, z)))
+	}
+
+	/// Every region which overlaps the area.
+	pub fn regions(&self) -> impl Iterator<Item = RegionPos> {
+		let min = RegionPos::from(self.min);
+		let max = RegionPos::from(self.max);
+		(min.z ..= max.z).flat_map(move |z| (min.x ..= max.x).map(move |x| RegionPos::new(x, z)))
+	}
+}
+
+impl From<ChunkPos> for ChunkArea {
+	fn from(pos: ChunkPos) -> Self {
+		Self { min: pos, max: pos }
+	}
+}
+
+impl From<RegionPos> for ChunkArea {
+	fn from(pos: RegionPos) -> Self {
+		Self {
+			min: pos.min_chunk(),
+			max: pos.max_chunk(),
+		}
+	}
+}
+
+impl FromStr for ChunkArea {
+	type Err = ParseIntError;
+
+	/// Parses two corners as `x1,z1:x2,z2`.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut split = s.splitn(2, ":");
+		let a: ChunkPos = split.next().unwrap_or("").parse()?;
+		let b: ChunkPos = split.next().unwrap_or("").parse()?;
+		Ok(Self::new(a, b))
+	}
+}
+
+#[test]
+fn test_chunkarea() {
+	let area = ChunkArea::from_str("1,-1:-1,1").unwrap();
+	assert!(area.min == ChunkPos::new(-1, -1));
+	assert!(area.max == ChunkPos::new(1, 1));
+	assert!(area.chunks().count() == 9);
+	assert!(area.contains(ChunkPos::new(0, 1)));
+	assert!(!area.contains(ChunkPos::new(2, 0)));
+	assert!(area.regions().collect::<Vec<_>>() == [
+		RegionPos::new(-1, -1),
+		RegionPos::new(0, -1),
+		RegionPos::new(-1, 0),
+		RegionPos::new(0, 0),
+	]);
+
+	let area = ChunkArea::from_blocks(BlockPos::new(0, 0, 0), BlockPos::new(31, 0, 15));
+	assert!(area.chunks().collect::<Vec<_>>() == [ChunkPos::new(0, 0), ChunkPos::new(1, 0)]);
+
+	let area = ChunkArea::from(RegionPos::new(-1, 0));
+	assert!(area.regions().collect::<Vec<_>>() == [RegionPos::new(-1, 0)]);
+	assert!(area.chunks().count() as i32 == RegionPos::diameterChunks.pow(2));
+
+	assert!(ChunkArea::from_str("0,0").is_err());
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct RegionPos {
 	pub x: i32,
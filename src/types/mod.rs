@@ -1,9 +1,10 @@
 pub mod blockstate;
 pub mod coords;
 pub mod interned_string;
+pub mod parallel;
 pub mod resource_location;
 pub mod shared;
 
-pub use coords::{BlockPos, ChunkPos, RegionPos};
+pub use coords::{BlockPos, ChunkArea, ChunkPos, RegionPos};
 pub use interned_string::IString;
 pub use resource_location::ResourceLocation;
@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Number of cores available, which [`par_map`] runs on by default.
+pub fn available_threads() -> usize {
+	thread::available_parallelism().map(|v| v.get()).unwrap_or(1)
+}
+
+/// Maps `f` over `items` on as many threads as there are cores, keeping the results in order.
+pub fn par_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+	par_map_on(available_threads(), items, f)
+}
+
+/// Maps `f` over `items` on up to `threads` threads, keeping the results in order.
+pub fn par_map_on<T: Sync, R: Send>(
+	threads: usize,
+	items: &[T],
+	f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+	let threads = threads.min(items.len());
+	if threads <= 1 {
+		return items.iter().map(f).collect();
+	}
+
+	// items are handed out one at a time, as their costs can differ wildly
+	let next = AtomicUsize::new(0);
+	let results = Mutex::new(Vec::with_capacity(items.len()));
+	thread::scope(|scope| {
+		for _ in 0 .. threads {
+			scope.spawn(|| loop {
+				let index = next.fetch_add(1, Ordering::Relaxed);
+				let item = match items.get(index) {
+					Some(v) => v,
+					None => break,
+				};
+				let result = f(item);
+				results.lock().unwrap().push((index, result));
+			});
+		}
+	});
+
+	let mut results = results.into_inner().unwrap();
+	results.sort_unstable_by_key(|&(index, _)| index);
+	results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[test]
+fn test_par_map() {
+	let items: Vec<u64> = (0 .. 1000).collect();
+	let squares = par_map(&items, |&v| v * v);
+	assert_eq!(squares, items.iter().map(|&v| v * v).collect::<Vec<_>>());
+	assert!(par_map(&[] as &[u64], |&v| v).is_empty());
+	assert_eq!(par_map_on(1, &items, |&v| v * v), squares);
+}
@@ -6,7 +6,7 @@ use std::path::{Path, PathBuf};
 use std::sync::{Arc, Weak};
 use std::{fmt, io};
 
-use crate::loader::common::AnvilRegion;
+use crate::loader::common::{AnvilRegion, LoadError};
 use crate::types::blockstate::{BlockState, BlockStateBuilder};
 use crate::types::coords::{ChunkPos, RegionPos};
 use crate::types::shared::{Shared, WeakShared};
@@ -92,24 +92,31 @@ impl Dimension {
 		self.rootDir.join("region")
 	}
 
+	/// Whether the sky lights this dimension, as it doesn't the Nether and the End.
+	pub fn has_sky_light(&self) -> bool {
+		self.id != "the_nether".into() && self.id != "the_end".into()
+	}
+
 	pub fn world(&self) -> Shared<World> {
 		self.world.clone()
 	}
 
-	pub fn new_region(&mut self, pos: RegionPos) -> Shared<Region> {
+	pub fn new_region(&mut self, pos: RegionPos) -> Result<Shared<Region>, LoadError> {
 		debug_assert!(
 			!self.regions.contains_key(&pos),
 			"Duplicate region {:?}",
 			pos
 		);
 		let this = self.this.upgrade().expect("null this");
-		let new = Region::new(this, pos, &self.region_dir());
+		let new = Region::new(this, pos, &self.region_dir())?;
 		self.regions.insert(pos, new.clone());
-		new
+		Ok(new)
 	}
 
 	pub fn unload_region(&mut self, pos: RegionPos) {
-		self.regions.remove(&pos);
+		if let Some(region) = self.regions.remove(&pos) {
+			region.borrow_mut().unload_chunks();
+		}
 	}
 
 	pub fn get_region(&self, pos: RegionPos) -> Option<Shared<Region>> {
@@ -119,6 +126,22 @@ impl Dimension {
 	pub fn is_region_loaded(&self, pos: RegionPos) -> bool {
 		self.regions.contains_key(&pos)
 	}
+
+	/// Returns the block at `pos`, if the section containing it is loaded.
+	pub fn get_block(&self, pos: BlockPos) -> Option<BlockState> {
+		let region = self.get_region(pos.into())?;
+		let chunk = region.borrow().get_chunk(pos.into())?;
+		let block = chunk.borrow().get_block(pos);
+		block
+	}
+
+	/// Returns the light level at `pos`, if the section containing it is loaded.
+	pub fn get_light(&self, pos: BlockPos) -> Option<u8> {
+		let region = self.get_region(pos.into())?;
+		let chunk = region.borrow().get_chunk(pos.into())?;
+		let light = chunk.borrow().get_light(pos);
+		light
+	}
 }
 
 impl Debug for Dimension {
@@ -141,15 +164,19 @@ pub struct Region {
 }
 
 impl Region {
-	fn new(dimension: Shared<Dimension>, pos: RegionPos, regionDir: &Path) -> Shared<Self> {
-		let anvil = AnvilRegion::new(regionDir, pos).unwrap().into();
-		Shared::new_cyclic(|this| Self {
+	fn new(
+		dimension: Shared<Dimension>,
+		pos: RegionPos,
+		regionDir: &Path,
+	) -> Result<Shared<Self>, LoadError> {
+		let anvil = AnvilRegion::new(regionDir, pos)?.into();
+		Ok(Shared::new_cyclic(|this| Self {
 			this: this.clone(),
 			dimension,
 			pos,
 			anvil,
 			chunks: HashMap::new(),
-		})
+		}))
 	}
 
 	pub fn pos(&self) -> RegionPos {
@@ -184,6 +211,26 @@ impl Region {
 	pub fn get_chunk(&self, pos: ChunkPos) -> Option<Shared<Chunk>> {
 		self.chunks.get(&pos).map(Shared::clone)
 	}
+
+	/// Drops every loaded chunk. Chunks and sections hold strong references to their parents, so
+	/// they are emptied as well to let them be freed.
+	pub fn unload_chunks(&mut self) {
+		for (_, chunk) in self.chunks.drain() {
+			chunk.borrow_mut().sections.clear();
+		}
+	}
+
+	/// Drops a single chunk, e.g. one which failed to load.
+	pub fn unload_chunk(&mut self, pos: ChunkPos) {
+		if let Some(chunk) = self.chunks.remove(&pos) {
+			chunk.borrow_mut().sections.clear();
+		}
+	}
+
+	/// Every chunk of this region which has been loaded.
+	pub fn chunks(&self) -> impl Iterator<Item = Shared<Chunk>> + '_ {
+		self.chunks.values().map(Shared::clone)
+	}
 }
 
 impl Debug for Region {
@@ -201,6 +248,7 @@ pub struct Chunk {
 	region: Shared<Region>,
 	pos: ChunkPos,
 	sections: HashMap<i8, Shared<ChunkSection>>,
+	blockEntities: HashMap<BlockPos, BlockEntity>,
 }
 
 impl Chunk {
@@ -210,6 +258,7 @@ impl Chunk {
 			region,
 			pos,
 			sections: HashMap::new(),
+			blockEntities: HashMap::new(),
 		})
 	}
 
@@ -237,7 +286,9 @@ impl Chunk {
 			y
 		);
 		let this = self.this.upgrade().expect("null this");
-		let new = ChunkSection::new(this, self.pos, y, palette);
+		let skyLit = self.region.borrow().dimension.borrow().has_sky_light();
+		let defaultSkyLight = if skyLit { 15 } else { 0 };
+		let new = ChunkSection::new(this, self.pos, y, palette, defaultSkyLight);
 		self.sections.insert(y, new.clone());
 		new
 	}
@@ -246,6 +297,35 @@ impl Chunk {
 		self.sections.get(&y).map(Shared::clone)
 	}
 
+	/// Returns the block at `pos`, if the section containing it is loaded.
+	pub fn get_block(&self, pos: BlockPos) -> Option<BlockState> {
+		debug_assert_eq!(ChunkPos::from(pos), self.pos);
+		let section = self.get_section(pos.section())?;
+		let block = section.borrow().get_block(pos);
+		Some(block)
+	}
+
+	/// Returns the light level at `pos`, if the section containing it is loaded.
+	pub fn get_light(&self, pos: BlockPos) -> Option<u8> {
+		debug_assert_eq!(ChunkPos::from(pos), self.pos);
+		let section = self.get_section(pos.section())?;
+		let light = section.borrow().get_light(pos);
+		Some(light)
+	}
+
+	pub fn set_block_entity(&mut self, pos: BlockPos, entity: BlockEntity) {
+		debug_assert_eq!(ChunkPos::from(pos), self.pos);
+		self.blockEntities.insert(pos, entity);
+	}
+
+	pub fn get_block_entity(&self, pos: BlockPos) -> Option<&BlockEntity> {
+		self.blockEntities.get(&pos)
+	}
+
+	pub fn block_entities(&self) -> impl Iterator<Item = (BlockPos, &BlockEntity)> {
+		self.blockEntities.iter().map(|(&pos, entity)| (pos, entity))
+	}
+
 	pub fn sections(&self) -> Range<i8> {
 		let min = self.sections.keys().copied().min().unwrap_or(0);
 		let max = self
@@ -265,20 +345,58 @@ impl Debug for Chunk {
 			.field("region", &self.region.borrow().pos())
 			.field("pos", &self.pos)
 			.field("sections", &self.sections)
+			.field("blockEntities", &self.blockEntities)
 			.finish()
 	}
 }
 
+/// The parts of a block entity's data which change how its block looks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockEntity {
+	pub id: ResourceLocation,
+
+	/// Patterns drawn over a banner's base colour, bottom first
+	pub bannerPatterns: Vec<BannerPattern>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BannerPattern {
+	/// Either a short code like `bs`, as used up to 1.20.4, or a name like
+	/// `minecraft:stripe_bottom`
+	pub pattern: String,
+
+	/// Index into [`flattening::colors`](crate::loader::flattening::colors)
+	pub color: u8,
+}
+
 pub struct ChunkSection {
 	chunk: Shared<Chunk>,
 	pos: ChunkPos,
 	y: i8,
 	palette: Shared<Palette>,
 	blocks: Vec<u32>,
+	biomes: Vec<ResourceLocation>,
+	/// Nibble arrays of light levels, empty if unknown
+	blockLight: Vec<u8>,
+	skyLight: Vec<u8>,
+	/// Sky light of blocks when `skyLight` is unknown, none in dimensions without a sky
+	defaultSkyLight: u8,
 }
 
 impl ChunkSection {
-	fn new(chunk: Shared<Chunk>, pos: ChunkPos, y: i8, palette: Palette) -> Shared<Self> {
+	/// Biomes are stored per 4x4x4 cell of blocks
+	pub const biomeCellsPerSection: usize = 64;
+
+	/// Light levels take 4 bits per block
+	pub const lightArrayLen: usize = 2048;
+
+	fn new(
+		chunk: Shared<Chunk>,
+		pos: ChunkPos,
+		y: i8,
+		palette: Palette,
+		defaultSkyLight: u8,
+	) -> Shared<Self> {
 		let mut blocks = Vec::new();
 		blocks.resize(16usize.pow(3), u32::MAX);
 		Self {
@@ -287,6 +405,10 @@ impl ChunkSection {
 			y,
 			palette: Shared::new(palette),
 			blocks,
+			biomes: vec![],
+			blockLight: vec![],
+			skyLight: vec![],
+			defaultSkyLight,
 		}
 		.into()
 	}
@@ -325,11 +447,11 @@ impl ChunkSection {
 	fn index_of(&self, pos: BlockPos) -> usize {
 		debug_assert_eq!(ChunkPos::from(pos), self.pos);
 		debug_assert_eq!(pos.section(), self.y);
-		let pos = pos.chunk_relative();
-		((pos.y * ChunkPos::diameterBlocks.pow(2)) + (pos.z * ChunkPos::diameterBlocks) + pos.x)
-			as usize
+		pos.index_in_section()
 	}
 
+	/// Returns the block at `pos`. Every id is checked against the palette as the section is
+	/// filled, so this can only fail if the palette is changed afterwards.
 	pub fn get_block(&self, pos: BlockPos) -> BlockState {
 		let id = self.blocks[self.index_of(pos)];
 		let palette = self.palette.borrow();
@@ -348,14 +470,64 @@ impl ChunkSection {
 		self.blocks.fill(id);
 	}
 
-	pub fn fill_from_iter(&mut self, palettedBlocks: impl Iterator<Item = u32>) {
+	/// Sets every block from palette ids in YZX order. Fails if an id isn't in the palette or
+	/// there are fewer than 4096 of them, in which case the section is left partly filled.
+	pub fn fill_from_iter(
+		&mut self,
+		palettedBlocks: impl Iterator<Item = u32>,
+	) -> Result<(), LoadError> {
+		let palette = self.palette.borrow();
 		let mut len = 0;
 		for (pos, id) in self.pos.blocks_in_section(self.y).zip(palettedBlocks) {
+			if palette.get_state(id).is_none() {
+				return Err(LoadError::PaletteIndex {
+					index: id,
+					paletteLen: palette.idToLoc.len(),
+				});
+			}
 			len += 1;
 			let index = self.index_of(pos);
 			self.blocks[index] = id;
 		}
-		debug_assert_eq!(len, 4096);
+		if len < self.blocks.len() {
+			return Err(LoadError::MissingBlocks { found: len });
+		}
+		Ok(())
+	}
+
+	pub fn get_biome(&self, pos: BlockPos) -> Option<ResourceLocation> {
+		debug_assert_eq!(ChunkPos::from(pos), self.pos);
+		debug_assert_eq!(pos.section(), self.y);
+		let pos = pos.chunk_relative();
+		let index = (pos.y >> 2) * 16 + (pos.z >> 2) * 4 + (pos.x >> 2);
+		self.biomes.get(index as usize).copied()
+	}
+
+	/// Returns the light level at `pos`, the brighter of block and sky light. Sections without
+	/// light data count as fully lit by the sky, or as dark in dimensions without one.
+	pub fn get_light(&self, pos: BlockPos) -> u8 {
+		let index = self.index_of(pos);
+		let nibble = |arr: &[u8]| arr.get(index / 2).map(|byte| byte >> (index % 2 * 4) & 0xF);
+		let blockLight = nibble(&self.blockLight).unwrap_or(0);
+		let skyLight = nibble(&self.skyLight).unwrap_or(self.defaultSkyLight);
+		blockLight.max(skyLight)
+	}
+
+	/// Sets the block and sky light nibble arrays, ordered YZX with the low nibble first. Arrays
+	/// of the wrong length are dropped, leaving that light unknown.
+	pub fn set_light(&mut self, blockLight: Option<Vec<u8>>, skyLight: Option<Vec<u8>>) {
+		let valid = |arr: Option<Vec<u8>>| {
+			arr.filter(|arr| arr.len() == Self::lightArrayLen)
+				.unwrap_or_default()
+		};
+		self.blockLight = valid(blockLight);
+		self.skyLight = valid(skyLight);
+	}
+
+	/// Sets the biomes of each 4x4x4 cell, ordered YZX.
+	pub fn set_biomes(&mut self, biomes: Vec<ResourceLocation>) {
+		assert_eq!(biomes.len(), Self::biomeCellsPerSection);
+		self.biomes = biomes;
 	}
 }
 
@@ -474,3 +646,73 @@ fn test_palette() {
 		catch_unwind(move || p.define(64, air)).unwrap_err();
 	}
 }
+
+#[test]
+fn test_fill_from_iter() {
+	let dir = std::env::temp_dir().join(format!("cuview-world-{}", std::process::id()));
+	std::fs::create_dir_all(dir.join("region")).unwrap();
+	let world = World::new(&dir);
+	let dimension = world.borrow_mut().new_dimension("overworld".into(), &dir);
+	let missing = dimension.borrow_mut().new_region(RegionPos::new(1, 0));
+	assert!(matches!(missing, Err(LoadError::Io(_))));
+	std::fs::write(dir.join("region/r.0.0.mca"), vec![0u8; 8192]).unwrap();
+	let region = dimension.borrow_mut().new_region(RegionPos::new(0, 0)).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+
+	let chunk = region.borrow_mut().new_chunk(ChunkPos::new(0, 0));
+	let air = BlockState::stateless("air".into());
+	let section = chunk.borrow_mut().new_section(0, std::iter::once(air).collect());
+	let mut section = section.borrow_mut();
+	assert!(section.fill_from_iter(std::iter::repeat_n(0, 4096)).is_ok());
+	assert!(matches!(
+		section.fill_from_iter(std::iter::repeat_n(0, 100)),
+		Err(LoadError::MissingBlocks { found: 100 })
+	));
+	assert!(matches!(
+		section.fill_from_iter([0, 0, 3].into_iter()),
+		Err(LoadError::PaletteIndex { index: 3, paletteLen: 1 })
+	));
+	drop(section);
+	dimension.borrow_mut().unload_region(RegionPos::new(0, 0));
+}
+
+#[test]
+fn test_light() {
+	let dir = std::env::temp_dir().join(format!("cuview-light-{}", std::process::id()));
+	let world = World::new(&dir);
+	let air = BlockState::stateless("air".into());
+	let mut sections = vec![];
+	for (id, root) in [("overworld", dir.clone()), ("the_nether", dir.join("DIM-1"))] {
+		std::fs::create_dir_all(root.join("region")).unwrap();
+		std::fs::write(root.join("region/r.0.0.mca"), vec![0u8; 8192]).unwrap();
+		let dimension = world.borrow_mut().new_dimension(id.into(), &root);
+		let region = dimension.borrow_mut().new_region(RegionPos::new(0, 0)).unwrap();
+		let chunk = region.borrow_mut().new_chunk(ChunkPos::new(0, 0));
+		sections.push(chunk.borrow_mut().new_section(0, std::iter::once(air).collect()));
+	}
+	std::fs::remove_dir_all(&dir).unwrap();
+	let (overworld, nether) = (&sections[0], &sections[1]);
+
+	// without light data blocks are lit by the sky, which the nether lacks
+	let pos = BlockPos::new(1, 0, 0);
+	assert_eq!(overworld.borrow().get_light(pos), 15);
+	assert_eq!(nether.borrow().get_light(pos), 0);
+
+	// block 1 has block light 12 and sky light 3
+	let mut blockLight = vec![0u8; ChunkSection::lightArrayLen];
+	let mut skyLight = blockLight.clone();
+	blockLight[0] = 0xC0;
+	skyLight[0] = 0x3F;
+	let mut section = overworld.borrow_mut();
+	section.set_light(Some(blockLight.clone()), Some(skyLight.clone()));
+	assert_eq!(section.get_light(pos), 12);
+	assert_eq!(section.get_light(BlockPos::new(0, 0, 0)), 15);
+	section.set_light(None, Some(skyLight));
+	assert_eq!(section.get_light(pos), 3);
+	drop(section);
+
+	// nether sections store only block light
+	nether.borrow_mut().set_light(Some(blockLight), None);
+	assert_eq!(nether.borrow().get_light(pos), 12);
+	assert_eq!(nether.borrow().get_light(BlockPos::new(0, 0, 0)), 0);
+}